@@ -0,0 +1,9 @@
+#![no_main]
+
+use http_server_fuzz::common::Cache;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|raw: String| {
+    let _ = Cache::parse(&raw);
+    let _ = Cache::parse_once(&raw);
+});
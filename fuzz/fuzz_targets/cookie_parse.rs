@@ -0,0 +1,9 @@
+#![no_main]
+
+use http_server_fuzz::cookie::RequestCookie;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|raw: String| {
+    let _ = RequestCookie::parse(raw.clone());
+    let _ = RequestCookie::parse_lenient(&raw);
+});
@@ -0,0 +1,36 @@
+#![no_main]
+
+use http_server_fuzz::request::handle_connection;
+use http_server_fuzz::server::{ParseMode, ServerOptions};
+use libfuzzer_sys::fuzz_target;
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::OnceLock;
+
+/// Where each input is sent from a client connection, the parser reading the
+/// server end as it would a real client.
+static LISTENER: OnceLock<TcpListener> = OnceLock::new();
+
+fuzz_target!(|data: &[u8]| {
+    // Written at once before parsing, it must fit the socket buffers
+    if data.len() > 16 * 1024 {
+        return;
+    }
+
+    let listener = LISTENER.get_or_init(|| TcpListener::bind("127.0.0.1:0").unwrap());
+
+    for parse_mode in [ParseMode::Strict, ParseMode::Lenient] {
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        client.write_all(data).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let options = ServerOptions {
+            parse_mode,
+            ..ServerOptions::default()
+        };
+
+        // Rejected or not, as long as the parser returns
+        let _ = handle_connection(listener.accept().unwrap().0, &options);
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use http_server_fuzz::mime::Mime;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|raw: String| {
+    let _ = Mime::parse(&raw);
+});
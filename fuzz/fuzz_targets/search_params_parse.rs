@@ -0,0 +1,8 @@
+#![no_main]
+
+use http_server_fuzz::search::SearchParams;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|raw: String| {
+    let _ = SearchParams::parse(raw);
+});
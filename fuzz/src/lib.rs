@@ -0,0 +1,66 @@
+//! The modules of the server, built as a library for the fuzz targets, the
+//! server itself being a binary crate.
+#![allow(dead_code)]
+
+#[path = "../../src/accept.rs"]
+pub mod accept;
+#[path = "../../src/auth.rs"]
+pub mod auth;
+#[path = "../../src/charset.rs"]
+pub mod charset;
+#[path = "../../src/common.rs"]
+pub mod common;
+#[path = "../../src/config.rs"]
+pub mod config;
+#[path = "../../src/cookie.rs"]
+pub mod cookie;
+#[path = "../../src/date.rs"]
+pub mod date;
+#[path = "../../src/digest.rs"]
+pub mod digest;
+#[path = "../../src/download.rs"]
+pub mod download;
+#[path = "../../src/drain.rs"]
+pub mod drain;
+#[path = "../../src/encoding.rs"]
+pub mod encoding;
+#[path = "../../src/forwarded.rs"]
+pub mod forwarded;
+#[path = "../../src/host.rs"]
+pub mod host;
+#[path = "../../src/json.rs"]
+pub mod json;
+#[path = "../../src/limit.rs"]
+pub mod limit;
+#[path = "../../src/log.rs"]
+pub mod log;
+#[path = "../../src/maintenance.rs"]
+pub mod maintenance;
+#[path = "../../src/mime.rs"]
+pub mod mime;
+#[path = "../../src/origin.rs"]
+pub mod origin;
+#[path = "../../src/pagination.rs"]
+pub mod pagination;
+#[path = "../../src/path.rs"]
+pub mod path;
+#[path = "../../src/pool.rs"]
+pub mod pool;
+#[path = "../../src/problem.rs"]
+pub mod problem;
+#[path = "../../src/quota.rs"]
+pub mod quota;
+#[path = "../../src/range.rs"]
+pub mod range;
+#[path = "../../src/record.rs"]
+pub mod record;
+#[path = "../../src/redirect.rs"]
+pub mod redirect;
+#[path = "../../src/request.rs"]
+pub mod request;
+#[path = "../../src/response.rs"]
+pub mod response;
+#[path = "../../src/search.rs"]
+pub mod search;
+#[path = "../../src/server.rs"]
+pub mod server;
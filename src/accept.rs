@@ -1,4 +1,7 @@
+use crate::charset::Charset;
+use crate::mime::Mime;
 use crate::response::BodyEncoding;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::str::FromStr;
 
 #[derive(Debug, Clone)]
@@ -9,13 +12,24 @@ impl AcceptEncodings {
         AcceptEncodings(encodings)
     }
 
+    /// Whether the client accepts `encoding`, i.e. with a quality above 0.
     pub fn accept(&self, encoding: &BodyEncoding) -> bool {
-        for accept in &self.0 {
-            if accept.accept(encoding) {
-                return true;
-            }
-        }
-        false
+        self.quality(encoding) > 0.0
+    }
+
+    /// The quality the client gives to `encoding`: the one of its own entry,
+    /// else the one of `*`, else 0.
+    pub fn quality(&self, encoding: &BodyEncoding) -> f32 {
+        let quality = |wanted: Encoding| {
+            self.0
+                .iter()
+                .find(|accept| accept.encoding == wanted)
+                .map(|accept| accept.q.unwrap_or(1.0))
+        };
+
+        quality(Encoding::from(*encoding))
+            .or_else(|| quality(Encoding::All))
+            .unwrap_or(0.0)
     }
 }
 
@@ -30,8 +44,10 @@ impl FromStr for AcceptEncodings {
             if encoding.is_empty() {
                 continue;
             }
-            let encoding: AcceptEncoding = encoding.parse()?;
-            encodings.push(encoding);
+            // Codings the server cannot produce (`identity`, `zstd`, ...) don't matter
+            if let Ok(encoding) = encoding.parse() {
+                encodings.push(encoding);
+            }
         }
         Ok(AcceptEncodings::new(encodings))
     }
@@ -70,7 +86,7 @@ impl FromStr for Encoding {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        match s.to_lowercase().as_str() {
             "gzip" => Ok(Encoding::Gzip),
             "deflate" => Ok(Encoding::Deflate),
             "br" => Ok(Encoding::Br),
@@ -105,11 +121,11 @@ impl ToString for Encoding {
 #[derive(Debug, Copy, Clone)]
 pub struct AcceptEncoding {
     encoding: Encoding,
-    q: Option<i8>,
+    q: Option<f32>,
 }
 
 impl AcceptEncoding {
-    pub fn new(encoding: Encoding, q: Option<i8>) -> Self {
+    pub fn new(encoding: Encoding, q: Option<f32>) -> Self {
         AcceptEncoding { encoding, q }
     }
 
@@ -117,7 +133,7 @@ impl AcceptEncoding {
         self.encoding
     }
 
-    pub fn quality(&self) -> Option<i8> {
+    pub fn quality(&self) -> Option<f32> {
         self.q
     }
 
@@ -139,9 +155,15 @@ impl FromStr for AcceptEncoding {
         let mut split = s.split(";");
         let encoding = split.next().ok_or(())?.trim();
         let encoding: Encoding = encoding.parse()?;
-        let q: Option<i8> = split
-            .next()
-            .and_then(|s| s.split("=").nth(1).and_then(|s| s.parse().ok()));
+        let q: Option<f32> = split.find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+
+            if name.trim().eq_ignore_ascii_case("q") {
+                value.trim().parse().ok()
+            } else {
+                None
+            }
+        });
         Ok(AcceptEncoding::new(encoding, q))
     }
 }
@@ -157,3 +179,382 @@ impl ToString for AcceptEncoding {
         result
     }
 }
+
+/// The media ranges of an `Accept` header, e.g. `text/html, application/*;q=0.8`.
+///
+/// An empty list (no `Accept` header) accepts any type.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptMimes(Vec<AcceptMime>);
+
+impl AcceptMimes {
+    pub fn new(ranges: Vec<AcceptMime>) -> Self {
+        AcceptMimes(ranges)
+    }
+
+    pub fn ranges(&self) -> &[AcceptMime] {
+        &self.0
+    }
+
+    /// The quality the client gives to `mime`, from its most specific matching
+    /// range; `0.0` when no range matches.
+    pub fn quality(&self, mime: &Mime) -> f32 {
+        self.rank(mime, false).0
+    }
+
+    /// The quality of the most specific range matching `mime`, and whether it
+    /// matched without going through a structured syntax suffix.
+    fn rank(&self, mime: &Mime, match_suffix: bool) -> (f32, bool) {
+        if self.0.is_empty() {
+            return (1.0, true);
+        }
+
+        self.0
+            .iter()
+            .filter_map(|range| Some((range, range.specificity(mime, match_suffix)?)))
+            .max_by_key(|(_, specificity)| *specificity)
+            .map(|(range, specificity)| (range.q, specificity != SUFFIX_MATCH))
+            .unwrap_or((0.0, true))
+    }
+
+    /// Pick the available type the client prefers. Ties, including `*/*`,
+    /// go to the first one in `available`.
+    pub fn negotiate(&self, available: &[Mime]) -> Option<Mime> {
+        self.negotiate_with(available, false)
+    }
+
+    /// Like `negotiate`, `match_suffix` also letting `application/json` and
+    /// `application/*+json` match suffixed types such as `application/problem+json`
+    /// (RFC 6839). At equal quality, exact matches win over suffix ones.
+    pub fn negotiate_with(&self, available: &[Mime], match_suffix: bool) -> Option<Mime> {
+        let mut best: Option<(&Mime, f32, bool)> = None;
+
+        for mime in available {
+            let (q, exact) = self.rank(mime, match_suffix);
+
+            if q > 0.0
+                && best.is_none_or(|(_, best_q, best_exact)| {
+                    q > best_q || (q == best_q && exact && !best_exact)
+                })
+            {
+                best = Some((mime, q, exact));
+            }
+        }
+
+        best.map(|(mime, _, _)| mime.clone())
+    }
+}
+
+impl FromStr for AcceptMimes {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = Vec::new();
+
+        for range in s.split(',') {
+            let range = range.trim();
+            if range.is_empty() {
+                continue;
+            }
+            ranges.push(range.parse()?);
+        }
+
+        Ok(AcceptMimes::new(ranges))
+    }
+}
+
+impl Display for AcceptMimes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let ranges: Vec<String> = self.0.iter().map(|range| range.to_string()).collect();
+
+        write!(f, "{}", ranges.join(", "))
+    }
+}
+
+/// Specificity of a range matching through a structured syntax suffix.
+const SUFFIX_MATCH: u8 = 2;
+
+/// A single media range, `*` standing for any type or subtype.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptMime {
+    type_: String,
+    subtype: String,
+    q: f32,
+}
+
+impl AcceptMime {
+    pub fn new(type_: &str, subtype: &str, q: f32) -> Self {
+        AcceptMime {
+            type_: type_.to_lowercase(),
+            subtype: subtype.to_lowercase(),
+            q,
+        }
+    }
+
+    pub fn quality(&self) -> f32 {
+        self.q
+    }
+
+    pub fn matches(&self, mime: &Mime) -> bool {
+        self.specificity(mime, false).is_some()
+    }
+
+    /// How specifically the range matches `mime`, `None` if it does not:
+    /// `*/*` < `type/*` < `type/*+suffix` or `type/suffix` < `type/subtype`
+    fn specificity(&self, mime: &Mime, match_suffix: bool) -> Option<u8> {
+        if self.type_ == "*" {
+            return (self.subtype == "*").then_some(0);
+        }
+
+        if !self.type_.eq_ignore_ascii_case(mime.type_()) {
+            return None;
+        }
+
+        if self.subtype == "*" {
+            return Some(1);
+        }
+
+        if self.subtype.eq_ignore_ascii_case(mime.subtype()) {
+            return Some(3);
+        }
+
+        let (_, suffix) = mime.subtype().rsplit_once('+')?;
+        let range_suffix = self.subtype.strip_prefix("*+").unwrap_or(&self.subtype);
+
+        (match_suffix && range_suffix.eq_ignore_ascii_case(suffix)).then_some(SUFFIX_MATCH)
+    }
+}
+
+impl FromStr for AcceptMime {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split(';');
+        let (type_, subtype) = split.next().ok_or(())?.trim().split_once('/').ok_or(())?;
+
+        if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+            return Err(());
+        }
+
+        let q = split
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+            .map(|(_, q)| q.trim().parse::<f32>().map_err(|_| ()))
+            .transpose()?
+            .unwrap_or(1.0);
+
+        Ok(AcceptMime::new(
+            type_.trim(),
+            subtype.trim(),
+            q.clamp(0.0, 1.0),
+        ))
+    }
+}
+
+impl Display for AcceptMime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+
+        if self.q < 1.0 {
+            write!(f, ";q={}", self.q)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The charsets of an `Accept-Charset` header, e.g. `iso-8859-1, utf-8;q=0.7`.
+///
+/// An empty list (no `Accept-Charset` header) accepts any charset.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptCharsets(Vec<AcceptCharset>);
+
+impl AcceptCharsets {
+    pub fn new(charsets: Vec<AcceptCharset>) -> Self {
+        AcceptCharsets(charsets)
+    }
+
+    pub fn charsets(&self) -> &[AcceptCharset] {
+        &self.0
+    }
+
+    /// The quality the client gives to the charset `label`: the one of its own
+    /// entry (aliases such as `latin1` included), else the one of `*`, else 0.
+    pub fn quality(&self, label: &str) -> f32 {
+        if self.0.is_empty() {
+            return 1.0;
+        }
+
+        self.0
+            .iter()
+            .find(|accept| accept.matches(label))
+            .or_else(|| self.0.iter().find(|accept| accept.charset == "*"))
+            .map_or(0.0, |accept| accept.q)
+    }
+
+    /// Pick the available charset the client prefers. Ties, including `*`, go
+    /// to the first one in `available`.
+    pub fn negotiate(&self, available: &[&str]) -> Option<String> {
+        let mut best: Option<(&str, f32)> = None;
+
+        for label in available {
+            let q = self.quality(label);
+
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((label, q));
+            }
+        }
+
+        best.map(|(label, _)| label.to_string())
+    }
+}
+
+impl FromStr for AcceptCharsets {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut charsets = Vec::new();
+
+        for charset in s.split(',') {
+            let charset = charset.trim();
+            if charset.is_empty() {
+                continue;
+            }
+            charsets.push(charset.parse()?);
+        }
+
+        Ok(AcceptCharsets::new(charsets))
+    }
+}
+
+impl Display for AcceptCharsets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let charsets: Vec<String> = self.0.iter().map(|charset| charset.to_string()).collect();
+
+        write!(f, "{}", charsets.join(", "))
+    }
+}
+
+/// A single charset, `*` standing for any other one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptCharset {
+    charset: String,
+    q: f32,
+}
+
+impl AcceptCharset {
+    pub fn new(charset: &str, q: f32) -> Self {
+        AcceptCharset {
+            charset: charset.to_lowercase(),
+            q,
+        }
+    }
+
+    pub fn charset(&self) -> &str {
+        &self.charset
+    }
+
+    pub fn quality(&self) -> f32 {
+        self.q
+    }
+
+    /// Whether the entry names `label`, directly or through another label of
+    /// the same charset.
+    pub fn matches(&self, label: &str) -> bool {
+        if self.charset.eq_ignore_ascii_case(label.trim()) {
+            return true;
+        }
+
+        match (
+            Charset::from_label(&self.charset),
+            Charset::from_label(label),
+        ) {
+            (Some(accepted), Some(charset)) => accepted == charset,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for AcceptCharset {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split(';');
+        let charset = split.next().ok_or(())?.trim();
+
+        if charset.is_empty() {
+            return Err(());
+        }
+
+        let q = split
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+            .map(|(_, q)| q.trim().parse::<f32>().map_err(|_| ()))
+            .transpose()?
+            .unwrap_or(1.0);
+
+        Ok(AcceptCharset::new(charset, q.clamp(0.0, 1.0)))
+    }
+}
+
+impl Display for AcceptCharset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.charset)?;
+
+        if self.q < 1.0 {
+            write!(f, ";q={}", self.q)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The type negotiated for a client sending `accept` among `available`.
+    fn negotiated(accept: &str, available: &[Mime], match_suffix: bool) -> Option<String> {
+        let accept: AcceptMimes = accept.parse().unwrap();
+
+        accept
+            .negotiate_with(available, match_suffix)
+            .map(|mime| mime.to_string())
+    }
+
+    #[test]
+    fn structured_syntax_suffixes() {
+        let problem = Mime::application("problem+json");
+        let json = Mime::application("json");
+        let available = [problem.clone()];
+
+        // Off by default
+        assert!("application/json"
+            .parse::<AcceptMimes>()
+            .unwrap()
+            .negotiate(&available)
+            .is_none());
+        assert_eq!(negotiated("application/json", &available, false), None);
+        assert_eq!(
+            negotiated("application/json", &available, true).as_deref(),
+            Some("application/problem+json")
+        );
+        assert_eq!(
+            negotiated(
+                "application/*+json",
+                &[Mime::text("html"), problem.clone()],
+                true
+            )
+            .as_deref(),
+            Some("application/problem+json")
+        );
+
+        // An exact match wins over a suffix one, wherever it is listed
+        assert_eq!(
+            negotiated("application/json", &[problem, json], true).as_deref(),
+            Some("application/json")
+        );
+
+        // A suffix only stands for its own syntax
+        assert_eq!(negotiated("application/xml", &available, true), None);
+    }
+}
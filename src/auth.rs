@@ -0,0 +1,266 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// One challenge of a `WWW-Authenticate` header: `Bearer realm="api", error="invalid_token"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub scheme: String,
+    /// Parameters in the order they are sent, values unquoted.
+    pub params: Vec<(String, String)>,
+    /// A single opaque value instead of parameters (`Negotiate YII=`).
+    pub token68: Option<String>,
+}
+
+impl Challenge {
+    pub fn new(scheme: &str) -> Challenge {
+        Challenge {
+            scheme: scheme.to_string(),
+            params: vec![],
+            token68: None,
+        }
+    }
+
+    /// `Basic realm="<realm>"`
+    pub fn basic(realm: &str) -> Challenge {
+        Challenge::new("Basic").param("realm", realm)
+    }
+
+    /// `Bearer realm="<realm>"`, plus the `error` of RFC 6750 §3.1 if any (`invalid_token`, ...).
+    pub fn bearer(realm: &str, error: Option<&str>) -> Challenge {
+        let challenge = Challenge::new("Bearer").param("realm", realm);
+
+        match error {
+            Some(error) => challenge.param("error", error),
+            None => challenge,
+        }
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> Challenge {
+        self.params.push((name.to_string(), value.to_string()));
+
+        self
+    }
+
+    /// Case-insensitive on the name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parse the value of a `WWW-Authenticate` (or `Proxy-Authenticate`) header,
+    /// which may hold several challenges.
+    pub fn parse_list(raw: &str) -> Result<Vec<Challenge>, String> {
+        let mut parser = Parser {
+            chars: raw.chars().collect(),
+            index: 0,
+        };
+        let mut challenges: Vec<Challenge> = vec![];
+
+        loop {
+            parser.skip(|c| c == ',' || c == ' ' || c == '\t');
+
+            if parser.done() {
+                break;
+            }
+
+            let token = parser.token();
+
+            if token.is_empty() {
+                return Err(format!("Unexpected {:?} in {:?}", parser.peek(), raw));
+            }
+
+            parser.skip(|c| c == ' ' || c == '\t');
+
+            // `name=value`, a parameter of the current challenge
+            if parser.peek() == Some('=') && !parser.is_token68_end() {
+                parser.index += 1;
+                parser.skip(|c| c == ' ' || c == '\t');
+
+                let value = match parser.peek() {
+                    Some('"') => parser.quoted()?,
+                    _ => parser.token(),
+                };
+
+                match challenges.last_mut() {
+                    Some(challenge) => challenge.params.push((token, value)),
+                    None => return Err(format!("Parameter {} before any scheme", token)),
+                }
+
+                continue;
+            }
+
+            let mut challenge = Challenge::new(&token);
+
+            // A token68 is the only thing following its scheme
+            let start = parser.index;
+            let token68 = parser.token68();
+
+            if !token68.is_empty() && parser.at_item_end() {
+                challenge.token68 = Some(token68);
+            } else {
+                parser.index = start;
+            }
+
+            challenges.push(challenge);
+        }
+
+        Ok(challenges)
+    }
+
+    /// Format challenges as the value of one header line.
+    pub fn format_list(challenges: &[Challenge]) -> String {
+        challenges
+            .iter()
+            .map(|challenge| challenge.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Parameter values are always sent quoted, which is valid for any of them and
+/// what clients expect for `realm`.
+impl Display for Challenge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.scheme)?;
+
+        if let Some(ref token68) = self.token68 {
+            return write!(f, " {}", token68);
+        }
+
+        for (index, (name, value)) in self.params.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+
+            write!(f, "{}{}=\"{}\"", separator, name, escaped)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+}
+
+impl Parser {
+    fn done(&self) -> bool {
+        self.index >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn skip(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&predicate) {
+            self.index += 1;
+        }
+    }
+
+    fn take(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.index;
+
+        self.skip(predicate);
+
+        self.chars[start..self.index].iter().collect()
+    }
+
+    fn token(&mut self) -> String {
+        self.take(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+    }
+
+    fn token68(&mut self) -> String {
+        let mut token68 = self.take(|c| c.is_ascii_alphanumeric() || "-._~+/".contains(c));
+
+        token68.push_str(&self.take(|c| c == '='));
+
+        token68
+    }
+
+    /// Whether only `=` padding (then the end of the item) follows, as in `abc==`.
+    fn is_token68_end(&self) -> bool {
+        let padding = self.chars[self.index..]
+            .iter()
+            .take_while(|c| **c == '=')
+            .count();
+
+        self.is_item_end(self.index + padding)
+    }
+
+    fn at_item_end(&self) -> bool {
+        self.is_item_end(self.index)
+    }
+
+    /// Whether only whitespace is left before the next `,` or the end.
+    fn is_item_end(&self, index: usize) -> bool {
+        self.chars[index..]
+            .iter()
+            .find(|c| **c != ' ' && **c != '\t')
+            .is_none_or(|c| *c == ',')
+    }
+
+    fn quoted(&mut self) -> Result<String, String> {
+        let mut value = String::new();
+
+        self.index += 1;
+
+        while let Some(c) = self.peek() {
+            self.index += 1;
+
+            match c {
+                '"' => return Ok(value),
+                '\\' => match self.peek() {
+                    Some(escaped) => {
+                        value.push(escaped);
+                        self.index += 1;
+                    }
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+
+        Err("Unclosed quoted string".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_several_challenges() {
+        let challenges = vec![
+            Challenge::bearer("api", Some("invalid_token")),
+            Challenge::basic(r#"Admin, "staff" only"#),
+            Challenge::new("Negotiate"),
+        ];
+        let formatted = Challenge::format_list(&challenges);
+
+        assert_eq!(
+            formatted,
+            r#"Bearer realm="api", error="invalid_token", Basic realm="Admin, \"staff\" only", Negotiate"#
+        );
+        assert_eq!(Challenge::parse_list(&formatted).unwrap(), challenges);
+    }
+
+    #[test]
+    fn parse_token68_and_unquoted_params() {
+        let challenges =
+            Challenge::parse_list("Negotiate YII=, Digest realm=files, qop=\"auth\"").unwrap();
+
+        assert_eq!(challenges.len(), 2);
+        assert_eq!(challenges[0].token68.as_deref(), Some("YII="));
+        assert_eq!(challenges[1].get("REALM"), Some("files"));
+        assert_eq!(challenges[1].get("qop"), Some("auth"));
+        assert_eq!(challenges[0].to_string(), "Negotiate YII=");
+    }
+
+    #[test]
+    fn reject_malformed_lists() {
+        assert!(Challenge::parse_list("realm=\"x\"").is_err());
+        assert!(Challenge::parse_list("Basic realm=\"unclosed").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    Utf8,
+    Utf16,
+    Utf16Le,
+    Utf16Be,
+    Iso88591,
+    UsAscii,
+}
+
+impl Charset {
+    /// Look up a charset by one of its (case-insensitive) IANA labels.
+    pub fn from_label(label: &str) -> Option<Charset> {
+        match label.trim().trim_matches('"').to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Charset::Utf8),
+            "utf-16" | "utf16" => Some(Charset::Utf16),
+            "utf-16le" => Some(Charset::Utf16Le),
+            "utf-16be" => Some(Charset::Utf16Be),
+            "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1" => Some(Charset::Iso88591),
+            "us-ascii" | "ascii" => Some(Charset::UsAscii),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "utf-8",
+            Charset::Utf16 => "utf-16",
+            Charset::Utf16Le => "utf-16le",
+            Charset::Utf16Be => "utf-16be",
+            Charset::Iso88591 => "iso-8859-1",
+            Charset::UsAscii => "us-ascii",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CharsetError {
+    /// The charset label is not one of the supported ones.
+    Unsupported(String),
+    /// The bytes are not valid in the charset; `position` is the offset of the first bad byte.
+    InvalidData { charset: Charset, position: usize },
+}
+
+impl Display for CharsetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            CharsetError::Unsupported(label) => write!(f, "Unsupported charset: {}", label),
+            CharsetError::InvalidData { charset, position } => {
+                write!(f, "Invalid {} data at byte {}", charset.label(), position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CharsetError {}
+
+/// Decode `bytes` using `charset`, never replacing undecodable bytes.
+///
+/// A leading byte order mark is honored for UTF-16 (big endian without one)
+/// and stripped for UTF-8.
+pub fn decode(bytes: &[u8], charset: Charset) -> Result<String, CharsetError> {
+    match charset {
+        Charset::Utf8 => {
+            let bytes = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]).unwrap_or(bytes);
+
+            match std::str::from_utf8(bytes) {
+                Ok(text) => Ok(text.to_string()),
+                Err(err) => Err(CharsetError::InvalidData {
+                    charset,
+                    position: err.valid_up_to(),
+                }),
+            }
+        }
+        Charset::Iso88591 => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+        Charset::UsAscii => match bytes.iter().position(|byte| !byte.is_ascii()) {
+            Some(position) => Err(CharsetError::InvalidData { charset, position }),
+            None => Ok(bytes.iter().map(|byte| *byte as char).collect()),
+        },
+        Charset::Utf16 => match bytes {
+            [0xff, 0xfe, rest @ ..] => decode_utf16(rest, charset, true),
+            [0xfe, 0xff, rest @ ..] => decode_utf16(rest, charset, false),
+            _ => decode_utf16(bytes, charset, false),
+        },
+        Charset::Utf16Le => decode_utf16(
+            bytes.strip_prefix(&[0xff, 0xfe]).unwrap_or(bytes),
+            charset,
+            true,
+        ),
+        Charset::Utf16Be => decode_utf16(
+            bytes.strip_prefix(&[0xfe, 0xff]).unwrap_or(bytes),
+            charset,
+            false,
+        ),
+    }
+}
+
+/// Encode `text` in `charset`, the reverse of `decode`, without a byte order
+/// mark (UTF-16 being big endian). Characters the charset cannot hold are an
+/// error, `position` being their byte offset in `text`.
+pub fn encode(text: &str, charset: Charset) -> Result<Vec<u8>, CharsetError> {
+    let single_byte = |max: char| {
+        text.char_indices()
+            .map(|(position, c)| {
+                if c <= max {
+                    Ok(c as u8)
+                } else {
+                    Err(CharsetError::InvalidData { charset, position })
+                }
+            })
+            .collect()
+    };
+
+    match charset {
+        Charset::Utf8 => Ok(text.as_bytes().to_vec()),
+        Charset::Iso88591 => single_byte('\u{ff}'),
+        Charset::UsAscii => single_byte('\u{7f}'),
+        Charset::Utf16 | Charset::Utf16Be => {
+            Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect())
+        }
+        Charset::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+    }
+}
+
+/// Re-encode `bytes` from one charset to another, e.g. legacy ISO-8859-1
+/// content to UTF-8 or the other way around.
+pub fn transcode(bytes: &[u8], from: Charset, to: Charset) -> Result<Vec<u8>, CharsetError> {
+    encode(&decode(bytes, from)?, to)
+}
+
+fn decode_utf16(
+    bytes: &[u8],
+    charset: Charset,
+    little_endian: bool,
+) -> Result<String, CharsetError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(CharsetError::InvalidData {
+            charset,
+            position: bytes.len() - 1,
+        });
+    }
+
+    let units = bytes.chunks(2).map(|pair| {
+        if little_endian {
+            u16::from_le_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_be_bytes([pair[0], pair[1]])
+        }
+    });
+
+    let mut out = String::new();
+    let mut position = 0;
+
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) => {
+                out.push(c);
+                position += c.len_utf16() * 2;
+            }
+            Err(_) => return Err(CharsetError::InvalidData { charset, position }),
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin1_transcoding() {
+        let latin1 = b"caf\xe9 \xa9";
+
+        assert_eq!(
+            transcode(latin1, Charset::Iso88591, Charset::Utf8).unwrap(),
+            "café ©".as_bytes()
+        );
+        assert_eq!(
+            transcode("café ©".as_bytes(), Charset::Utf8, Charset::Iso88591).unwrap(),
+            latin1
+        );
+
+        // Past U+00FF, at the byte offset of the character
+        assert_eq!(
+            encode("café €", Charset::Iso88591),
+            Err(CharsetError::InvalidData {
+                charset: Charset::Iso88591,
+                position: 6
+            })
+        );
+        assert_eq!(
+            transcode(b"caf\xc3", Charset::Utf8, Charset::Iso88591),
+            Err(CharsetError::InvalidData {
+                charset: Charset::Utf8,
+                position: 3
+            })
+        );
+    }
+}
@@ -1,12 +1,19 @@
 use crate::accept::AcceptEncodings;
+use crate::auth::Challenge;
 use crate::cookie::{RequestCookie, ResponseCookie};
+use crate::date::{HttpDate, RetryAfter};
+use crate::digest::{self, Digest};
 use crate::mime::Mime;
-use crate::response::BodyEncoding;
+use crate::origin;
+use crate::pagination::Link;
+use crate::range::{ContentRange, RangeUnit};
+use crate::response::{BodyEncoding, SendfileStyle};
 use crate::search::SearchParams;
-use std::ops::Add;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::time::Duration;
 use urlencoding::decode;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Method {
     Get,
     Post,
@@ -20,6 +27,24 @@ pub enum Method {
     Unknown(String),
 }
 
+impl Method {
+    /// Case-insensitive, anything else is kept as `Unknown`.
+    pub fn parse(raw: &str) -> Method {
+        match raw.to_uppercase().as_str() {
+            "GET" => Method::Get,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "HEAD" => Method::Head,
+            "PATCH" => Method::Patch,
+            "OPTIONS" => Method::Options,
+            "CONNECT" => Method::Connect,
+            "TRACE" => Method::Trace,
+            _ => Method::Unknown(raw.to_string()),
+        }
+    }
+}
+
 impl ToString for Method {
     fn to_string(&self) -> String {
         match self {
@@ -37,17 +62,33 @@ impl ToString for Method {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Version {
+    /// A request line without a version, `GET /path`: no headers either way.
+    Http09,
     Http10,
     Http11,
     Http20,
     Unknown(String),
 }
 
+impl Version {
+    /// Anything but HTTP/0.9, 1.0, 1.1 and 2.0 is kept as `Unknown`.
+    pub fn parse(raw: &str) -> Version {
+        match raw {
+            "HTTP/0.9" => Version::Http09,
+            "HTTP/1.0" => Version::Http10,
+            "HTTP/1.1" => Version::Http11,
+            "HTTP/2.0" => Version::Http20,
+            _ => Version::Unknown(raw.to_string()),
+        }
+    }
+}
+
 impl ToString for Version {
     fn to_string(&self) -> String {
         match self {
+            Version::Http09 => "HTTP/0.9".to_string(),
             Version::Http10 => "HTTP/1.0".to_string(),
             Version::Http11 => "HTTP/1.1".to_string(),
             Version::Http20 => "HTTP/2.0".to_string(),
@@ -75,6 +116,50 @@ impl ToString for Connection {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpgradeProtocol {
+    WebSocket,
+    H2c,
+    Tls,
+    Other(String),
+}
+
+impl Display for UpgradeProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            UpgradeProtocol::WebSocket => write!(f, "websocket"),
+            UpgradeProtocol::H2c => write!(f, "h2c"),
+            UpgradeProtocol::Tls => write!(f, "TLS/1.0"),
+            UpgradeProtocol::Other(raw_protocol) => write!(f, "{}", raw_protocol),
+        }
+    }
+}
+
+impl UpgradeProtocol {
+    /// Parse a single protocol token of an `Upgrade` header,
+    /// ignoring the optional `/version` suffix except for TLS.
+    pub fn parse_once(raw_protocol: &str) -> UpgradeProtocol {
+        let raw_protocol = raw_protocol.trim();
+        let name = raw_protocol.split('/').next().unwrap_or("");
+
+        match name.to_lowercase().as_str() {
+            "websocket" => UpgradeProtocol::WebSocket,
+            "h2c" => UpgradeProtocol::H2c,
+            "tls" => UpgradeProtocol::Tls,
+            _ => UpgradeProtocol::Other(raw_protocol.to_string()),
+        }
+    }
+
+    /// Parse the comma separated protocol list of an `Upgrade` header.
+    pub fn parse(raw_protocols: &str) -> Vec<UpgradeProtocol> {
+        raw_protocols
+            .split(',')
+            .filter(|raw_protocol| !raw_protocol.trim().is_empty())
+            .map(UpgradeProtocol::parse_once)
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Dnt {
     PrefersAllowTrack,
@@ -92,7 +177,7 @@ impl ToString for Dnt {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Cache {
     MaxAge(u32),
     NoCache,
@@ -109,6 +194,8 @@ pub enum Cache {
     MaxStale(u32),
     MinFresh(u32),
     OnlyIfCached,
+    /// A directive this server doesn't know, kept verbatim so it survives re-serialization.
+    Extension(String, Option<String>),
 }
 
 impl ToString for Cache {
@@ -129,70 +216,70 @@ impl ToString for Cache {
             Cache::MaxStale(age) => format!("max-stale={}", age),
             Cache::MinFresh(age) => format!("min-fresh={}", age),
             Cache::OnlyIfCached => "only-if-cached".to_string(),
+            Cache::Extension(name, Some(value)) => format!("{}={}", name, value),
+            Cache::Extension(name, None) => name.clone(),
         }
     }
 }
 
 impl Cache {
     pub fn parse_once(raw_cache: &String) -> Option<Cache> {
-        if raw_cache.contains("=") {
-            let (key, value) = raw_cache.split_at(raw_cache.find('=')?);
-
-            return match key {
-                "max-age" => Some(Cache::MaxAge(value.parse().ok()?)),
-                "stale-while-revalidate" => Some(Cache::StaleWhileRevalidate(value.parse().ok()?)),
-                "stale-if-error" => Some(Cache::StaleIfError(value.parse().ok()?)),
-                "max-stale" => Some(Cache::MaxStale(value.parse().ok()?)),
-                "min-fresh" => Some(Cache::MinFresh(value.parse().ok()?)),
-                _ => None,
-            };
+        let raw_cache = raw_cache.trim();
+
+        if raw_cache.is_empty() {
+            return None;
         }
 
-        match raw_cache.as_str() {
-            "no-cache" => Some(Cache::NoCache),
-            "must-revalidate" => Some(Cache::MustRevalidate),
-            "proxy-revalidate" => Some(Cache::ProxyRevalidate),
-            "no-store" => Some(Cache::NoStore),
-            "private" => Some(Cache::Private),
-            "public" => Some(Cache::Public),
-            "must-understand" => Some(Cache::MustUnderstand),
-            "no-transform" => Some(Cache::NoTransform),
-            "immutable" => Some(Cache::Immutable),
-            "only-if-cached" => Some(Cache::OnlyIfCached),
-            _ => None,
+        if let Some((key, value)) = raw_cache.split_once('=') {
+            let (key, value) = (key.trim(), value.trim());
+            let age = value.parse().ok();
+
+            return Some(match (key.to_ascii_lowercase().as_str(), age) {
+                ("max-age", Some(age)) => Cache::MaxAge(age),
+                ("stale-while-revalidate", Some(age)) => Cache::StaleWhileRevalidate(age),
+                ("stale-if-error", Some(age)) => Cache::StaleIfError(age),
+                ("max-stale", Some(age)) => Cache::MaxStale(age),
+                ("min-fresh", Some(age)) => Cache::MinFresh(age),
+                _ => Cache::Extension(key.to_string(), Some(value.to_string())),
+            });
         }
+
+        Some(match raw_cache.to_ascii_lowercase().as_str() {
+            "no-cache" => Cache::NoCache,
+            "must-revalidate" => Cache::MustRevalidate,
+            "proxy-revalidate" => Cache::ProxyRevalidate,
+            "no-store" => Cache::NoStore,
+            "private" => Cache::Private,
+            "public" => Cache::Public,
+            "must-understand" => Cache::MustUnderstand,
+            "no-transform" => Cache::NoTransform,
+            "immutable" => Cache::Immutable,
+            "only-if-cached" => Cache::OnlyIfCached,
+            _ => Cache::Extension(raw_cache.to_string(), None),
+        })
     }
 
+    /// Parse a `Cache-Control` value; commas inside quoted values don't split directives.
     pub fn parse(raw_cache: &String) -> Vec<Cache> {
-        raw_cache
-            .split(',')
-            .map(|raw_cache| {
-                let mut d = String::from(raw_cache);
+        let mut in_quotes = false;
 
-                while d.starts_with(" ") {
-                    d.remove(0);
-                }
-
-                while d.ends_with(" ") {
-                    d.remove(d.len() - 1);
+        raw_cache
+            .split(|c| {
+                if c == '"' {
+                    in_quotes = !in_quotes;
                 }
 
-                Cache::parse_once(&d)
+                c == ',' && !in_quotes
             })
-            .filter_map(|cache| cache)
+            .filter_map(|raw_cache| Cache::parse_once(&raw_cache.to_string()))
             .collect()
     }
 
     pub fn format(vec: &Vec<Cache>) -> String {
-        let mut val = vec.iter().fold(String::new(), |acc, current| {
-            acc.add(&current.to_string()).add(", ")
-        });
-
-        while val.ends_with(" ") || val.ends_with(",") {
-            val.remove(val.len() - 1);
-        }
-
-        val
+        vec.iter()
+            .map(|cache| cache.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
     }
 }
 
@@ -207,8 +294,8 @@ pub enum Header {
     AcceptEncoding(AcceptEncodings),
     AcceptLanguage(String),
     AcceptCharset(String),
-    AcceptDatetime(String),
-    AcceptRanges(String),
+    AcceptDatetime(HttpDate),
+    AcceptRanges(Vec<RangeUnit>),
     CacheControl(Vec<Cache>),
     Cookie(Vec<RequestCookie>),
     Date(String),
@@ -223,9 +310,38 @@ pub enum Header {
     SetCookie(ResponseCookie),
     Location(String),
     ContentEncoding(Vec<BodyEncoding>),
+    IfMatch(Vec<String>),
+    IfUnmodifiedSince(HttpDate),
+    Vary(Vec<String>),
+    Allow(Vec<Method>),
+    ContentMd5(Vec<u8>),
+    Digest(Vec<Digest>),
+    ReprDigest(Vec<Digest>),
+    Referer(String),
+    /// `Keep-Alive: timeout=15, max=99`, the idle timeout and the requests left on the connection.
+    KeepAlive(Duration, Option<usize>),
+    /// `Upgrade-Insecure-Requests: 1`, sent by browsers that prefer HTTPS.
+    UpgradeInsecureRequests,
+    MementoDatetime(HttpDate),
+    Range(String),
+    ContentRange(ContentRange),
+    /// Transfer codings the client accepts in the response, `trailers` included.
+    Te(Vec<String>),
+    /// The file a fronting proxy sends in place of the body, see `Response::internal_redirect`.
+    Sendfile(SendfileStyle, String),
+    WwwAuthenticate(Vec<Challenge>),
+    RetryAfter(RetryAfter),
+    /// `Link: <https://example.com/items?page=2>; rel="next"`, see `Pagination::link_header`.
+    Link(Vec<Link>),
     Unknown(String, String),
 }
 
+/// Headers defined as comma separated lists: several lines of one of these
+/// can be merged into a single line without changing their meaning.
+///
+/// Anything else (notably `Set-Cookie`) must be kept as separate lines.
+pub const LIST_HEADERS: [&str; 4] = ["Allow", "Cache-Control", "Content-Encoding", "Vary"];
+
 impl ToString for Header {
     fn to_string(&self) -> String {
         match self {
@@ -251,21 +367,25 @@ impl ToString for Header {
             Header::AcceptDatetime(accept_datetime) => {
                 format!("Accept-Datetime: {}\r\n", accept_datetime)
             }
-            Header::AcceptRanges(accept_ranges) => format!("Accept-Ranges: {}\r\n", accept_ranges),
+            Header::AcceptRanges(units) => {
+                let units = units
+                    .iter()
+                    .map(|unit| unit.to_string())
+                    .collect::<Vec<_>>();
+
+                format!("Accept-Ranges: {}\r\n", units.join(", "))
+            }
             Header::CacheControl(cache_control) => {
                 format!("Cache-Control: {}\r\n", Cache::format(cache_control))
             }
-            Header::Cookie(cookie) => {
-                let mut out = "Cookie: ".to_string();
-
-                for cookie in cookie {
-                    out += &format!("{}={}; ", cookie.name(), cookie.value());
-                }
-
-                out += "\r\n";
-
-                out
-            }
+            Header::Cookie(cookie) => format!(
+                "Cookie: {}\r\n",
+                cookie
+                    .iter()
+                    .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
             Header::Date(date) => format!("Date: {}\r\n", date),
             Header::Pragma(pragma) => format!("Pragma: {}\r\n", pragma.to_string()),
             Header::Trailer(trailer) => format!("Trailer: {}\r\n", trailer),
@@ -279,7 +399,7 @@ impl ToString for Header {
             Header::Server(server) => format!("Server: {}\r\n", server),
             Header::Origin(origin) => format!("Origin: {}\r\n", origin),
             Header::Dnt(dnt) => format!(
-                "DNT: {}",
+                "DNT: {}\r\n",
                 match dnt {
                     Dnt::PrefersAllowTrack => "0",
                     Dnt::PrefersNoTrack => "1",
@@ -302,12 +422,170 @@ impl ToString for Header {
 
                 out
             }
+            Header::IfMatch(if_match) => format!("If-Match: {}\r\n", if_match.join(", ")),
+            Header::IfUnmodifiedSince(date) => format!("If-Unmodified-Since: {}\r\n", date),
+            Header::Vary(vary) => format!("Vary: {}\r\n", vary.join(", ")),
+            Header::Allow(allow) => format!(
+                "Allow: {}\r\n",
+                allow
+                    .iter()
+                    .map(|method| method.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Header::ContentMd5(md5) => {
+                format!("Content-MD5: {}\r\n", digest::encode_base64(md5))
+            }
+            Header::Digest(digests) => format!("Digest: {}\r\n", Digest::format_legacy(digests)),
+            Header::ReprDigest(digests) => {
+                format!("Repr-Digest: {}\r\n", Digest::format_repr(digests))
+            }
+            Header::Referer(referer) => format!("Referer: {}\r\n", referer),
+            Header::KeepAlive(timeout, max) => match max {
+                Some(max) => format!("Keep-Alive: timeout={}, max={}\r\n", timeout.as_secs(), max),
+                None => format!("Keep-Alive: timeout={}\r\n", timeout.as_secs()),
+            },
+            Header::UpgradeInsecureRequests => "Upgrade-Insecure-Requests: 1\r\n".to_string(),
+            Header::MementoDatetime(date) => format!("Memento-Datetime: {}\r\n", date),
+            Header::Range(range) => format!("Range: {}\r\n", range),
+            Header::ContentRange(range) => format!("Content-Range: {}\r\n", range),
+            Header::Te(codings) => format!("TE: {}\r\n", codings.join(", ")),
+            Header::Sendfile(style, path) => format!("{}: {}\r\n", style.header_name(), path),
+            Header::RetryAfter(retry_after) => format!("Retry-After: {}\r\n", retry_after),
+            Header::Link(links) => format!(
+                "Link: {}\r\n",
+                links
+                    .iter()
+                    .map(Link::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Header::WwwAuthenticate(challenges) => {
+                format!(
+                    "WWW-Authenticate: {}\r\n",
+                    Challenge::format_list(challenges)
+                )
+            }
             Header::Unknown(name, value) => format!("{}: {}\r\n", name, value),
         }
     }
 }
 
 impl Header {
+    /// Split an entity-tag list (`"a", W/"b"`) as found in `If-Match` and
+    /// `If-None-Match`, keeping each tag verbatim. `*` is kept as a single element.
+    pub fn parse_etags(raw: &str) -> Vec<String> {
+        raw.split(',')
+            .map(|etag| etag.trim())
+            .filter(|etag| !etag.is_empty())
+            .map(|etag| etag.to_string())
+            .collect()
+    }
+
+    /// Strong comparison of two entity-tags: both must be strong and have the same opaque value.
+    pub fn etag_strong_eq(a: &str, b: &str) -> bool {
+        let strong = |etag: &str| -> Option<String> {
+            let etag = etag.trim();
+
+            if etag.starts_with("W/") {
+                return None;
+            }
+
+            Some(etag.trim_matches('"').to_string())
+        };
+
+        match (strong(a), strong(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Whether `name` is a valid field name: a non-empty RFC 7230 token,
+    /// so no spaces, colons, control nor non-ASCII characters.
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+    }
+
+    /// The serialized value of the header, without its name.
+    pub fn value(&self) -> String {
+        let line = self.to_string();
+
+        line[(self.name().len() + 1)..]
+            .trim_end_matches("\r\n")
+            .trim()
+            .to_string()
+    }
+
+    /// Merge two lines of the same list header into one.
+    ///
+    /// Typed lists are concatenated (dropping duplicates for `Vary` and `Allow`),
+    /// anything else is comma-joined as an `Unknown` header.
+    pub fn merge(self, other: &Header) -> Header {
+        match (self, other) {
+            (Header::Vary(mut vary), Header::Vary(other)) => {
+                for name in other {
+                    if !vary.iter().any(|v| v.eq_ignore_ascii_case(name)) {
+                        vary.push(name.clone());
+                    }
+                }
+
+                Header::Vary(vary)
+            }
+            (Header::Allow(mut allow), Header::Allow(other)) => {
+                for method in other {
+                    if !allow.contains(method) {
+                        allow.push(method.clone());
+                    }
+                }
+
+                Header::Allow(allow)
+            }
+            (Header::CacheControl(mut cache), Header::CacheControl(other)) => {
+                cache.extend(other.iter().cloned());
+
+                Header::CacheControl(cache)
+            }
+            (Header::ContentEncoding(mut encodings), Header::ContentEncoding(other)) => {
+                encodings.extend(other.iter().cloned());
+
+                Header::ContentEncoding(encodings)
+            }
+            (header, other) => Header::Unknown(
+                header.name(),
+                format!("{}, {}", header.value(), other.value()),
+            ),
+        }
+    }
+
+    /// Merge every header called `name` into the first of them, in place.
+    pub fn merge_all(headers: &mut Vec<Header>, name: &str) {
+        let positions = headers
+            .iter()
+            .enumerate()
+            .filter(|(_, header)| header.name().eq_ignore_ascii_case(name))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if positions.len() < 2 {
+            return;
+        }
+
+        let mut merged = headers[positions[0]].clone();
+
+        for index in positions[1..].iter() {
+            merged = merged.merge(&headers[*index]);
+        }
+
+        headers[positions[0]] = merged;
+
+        for index in positions[1..].iter().rev() {
+            headers.remove(*index);
+        }
+    }
+
     pub fn name(&self) -> String {
         match self {
             Header::Connection(_) => "Connection",
@@ -335,35 +613,68 @@ impl Header {
             Header::SetCookie(_) => "Set-Cookie",
             Header::Location(_) => "Location",
             Header::ContentEncoding(_) => "Content-Encoding",
+            Header::IfMatch(_) => "If-Match",
+            Header::IfUnmodifiedSince(_) => "If-Unmodified-Since",
+            Header::Vary(_) => "Vary",
+            Header::Allow(_) => "Allow",
+            Header::ContentMd5(_) => "Content-MD5",
+            Header::Digest(_) => "Digest",
+            Header::ReprDigest(_) => "Repr-Digest",
+            Header::Referer(_) => "Referer",
+            Header::KeepAlive(..) => "Keep-Alive",
+            Header::UpgradeInsecureRequests => "Upgrade-Insecure-Requests",
+            Header::MementoDatetime(_) => "Memento-Datetime",
+            Header::Range(_) => "Range",
+            Header::ContentRange(_) => "Content-Range",
+            Header::Te(_) => "TE",
+            Header::Sendfile(style, _) => style.header_name(),
+            Header::WwwAuthenticate(_) => "WWW-Authenticate",
+            Header::RetryAfter(_) => "Retry-After",
+            Header::Link(_) => "Link",
             Header::Unknown(ref a, _) => a.as_str(),
         }
         .to_string()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Uri {
     pub scheme: String,
     pub host: String,
     pub path: String,
     pub search: SearchParams,
+    pub raw_query: Option<String>,
 }
 
 impl Uri {
+    /// Whether the request-target is the asterisk form (`OPTIONS * HTTP/1.1`),
+    /// which targets the server as a whole rather than a resource.
+    pub fn is_asterisk(&self) -> bool {
+        self.path == "*"
+    }
+
+    /// Whether the host is an IPv4 or IPv6 address rather than a name.
+    pub fn host_is_ip(&self) -> bool {
+        origin::split_host_port(&self.host).is_some_and(|(host, _)| origin::is_ip(&host))
+    }
+
     pub fn absolute(host: String, mut path: String) -> Uri {
-        let search = if path.contains('?') {
+        let (search, raw_query) = if path.contains('?') {
             let index = path.match_indices('?').next().unwrap().0;
 
             let search_raw = String::from(&path[index..]);
 
-            path = decode(&path[..index]).unwrap().into_owned();
+            path = decode(&path[..index])
+                .map_or_else(|_| path[..index].to_string(), |p| p.into_owned());
 
-            match SearchParams::parse(search_raw) {
+            let search = match SearchParams::parse(search_raw.clone()) {
                 Ok(v) => v,
                 Err(_) => SearchParams::empty(),
-            }
+            };
+
+            (search, Some(search_raw[1..].to_string()))
         } else {
-            SearchParams::empty()
+            (SearchParams::empty(), None)
         };
 
         Uri {
@@ -371,8 +682,58 @@ impl Uri {
             host,
             path,
             search,
+            raw_query,
         }
     }
+
+    /// The query string exactly as received, without the leading `?`.
+    ///
+    /// Use it when the exact bytes matter (e.g. signature verification);
+    /// `search` holds the decoded parameters.
+    pub fn raw_query(&self) -> Option<&str> {
+        self.raw_query.as_deref()
+    }
+
+    /// The query parameters to change, reflected by `to_string` but not by `raw_query`.
+    pub fn query_mut(&mut self) -> &mut SearchParams {
+        &mut self.search
+    }
+
+    /// A normalized form for cache keys and logs: lowercase scheme and host, no
+    /// default port and the query parameters sorted.
+    pub fn canonical(&self) -> String {
+        let scheme = self.scheme.to_lowercase();
+        let host = match origin::split_host_port(&self.host) {
+            Some((host, Some(port))) if origin::default_port(&scheme) != Some(port) => {
+                format!("{}:{}", host, port)
+            }
+            Some((host, _)) => host,
+            None => self.host.to_lowercase(),
+        };
+
+        format!(
+            "{}://{}{}{}",
+            scheme,
+            host,
+            self.path,
+            self.search.to_string_sorted()
+        )
+    }
+
+    /// Like `to_string`, but with the query string as received instead of
+    /// one regenerated from `search`.
+    pub fn to_string_raw(&self) -> String {
+        format!(
+            "{}://{}{}{}",
+            self.scheme,
+            self.host,
+            self.path,
+            match self.raw_query {
+                Some(ref raw_query) => format!("?{}", raw_query),
+                None => String::new(),
+            }
+        )
+    }
 }
 
 impl ToString for Uri {
@@ -400,6 +761,7 @@ pub enum Status {
     MovedPermanently,
     MovedTemporarily,
     NotModified,
+    TemporaryRedirect,
     BadRequest,
     Unauthorized,
     Forbidden,
@@ -417,6 +779,9 @@ pub enum Status {
     UnsupportedMediaType,
     RequestedRangeNotSatisfiable,
     ExpectationFailed,
+    ImATeapot,
+    PreconditionRequired,
+    TooManyRequests,
     InternalServerError,
     NotImplemented,
     BadGateway,
@@ -430,7 +795,7 @@ pub enum Status {
 impl ToString for Status {
     fn to_string(&self) -> String {
         match self {
-            Status::SwitchingProtocols => "HTTP/1.1 101 Switching Protocols\r\n".to_string(),
+            Status::SwitchingProtocols => "101 Switching Protocols".to_string(),
             Status::Ok => "200 OK".to_string(),
             Status::Created => "201 Created".to_string(),
             Status::Accepted => "202 Accepted".to_string(),
@@ -441,6 +806,7 @@ impl ToString for Status {
             Status::MovedPermanently => "301 Moved Permanently".to_string(),
             Status::MovedTemporarily => "302 Moved Temporarily".to_string(),
             Status::NotModified => "304 Not Modified".to_string(),
+            Status::TemporaryRedirect => "307 Temporary Redirect".to_string(),
             Status::BadRequest => "400 Bad Request".to_string(),
             Status::Unauthorized => "401 Unauthorized".to_string(),
             Status::Forbidden => "403 Forbidden".to_string(),
@@ -460,6 +826,9 @@ impl ToString for Status {
                 "416 Requested Range Not Satisfiable".to_string()
             }
             Status::ExpectationFailed => "417 Expectation Failed".to_string(),
+            Status::ImATeapot => "418 I'm a teapot".to_string(),
+            Status::PreconditionRequired => "428 Precondition Required".to_string(),
+            Status::TooManyRequests => "429 Too Many Requests".to_string(),
             Status::InternalServerError => "500 Internal Server Error".to_string(),
             Status::NotImplemented => "501 Not Implemented".to_string(),
             Status::BadGateway => "502 Bad Gateway".to_string(),
@@ -473,6 +842,51 @@ impl ToString for Status {
 }
 
 impl Status {
+    pub fn code(&self) -> u16 {
+        match self {
+            Status::SwitchingProtocols => 101,
+            Status::Ok => 200,
+            Status::Created => 201,
+            Status::Accepted => 202,
+            Status::NoContent => 204,
+            Status::ResetContent => 205,
+            Status::PartialContent => 206,
+            Status::MultipleChoices => 300,
+            Status::MovedPermanently => 301,
+            Status::MovedTemporarily => 302,
+            Status::NotModified => 304,
+            Status::TemporaryRedirect => 307,
+            Status::BadRequest => 400,
+            Status::Unauthorized => 401,
+            Status::Forbidden => 403,
+            Status::NotFound => 404,
+            Status::MethodNotAllowed => 405,
+            Status::NotAcceptable => 406,
+            Status::ProxyAuthenticationRequired => 407,
+            Status::RequestTimeout => 408,
+            Status::Conflict => 409,
+            Status::Gone => 410,
+            Status::LengthRequired => 411,
+            Status::PreconditionFailed => 412,
+            Status::RequestEntityTooLarge => 413,
+            Status::RequestUriTooLong => 414,
+            Status::UnsupportedMediaType => 415,
+            Status::RequestedRangeNotSatisfiable => 416,
+            Status::ExpectationFailed => 417,
+            Status::ImATeapot => 418,
+            Status::PreconditionRequired => 428,
+            Status::TooManyRequests => 429,
+            Status::InternalServerError => 500,
+            Status::NotImplemented => 501,
+            Status::BadGateway => 502,
+            Status::ServiceUnavailable => 503,
+            Status::GatewayTimeout => 504,
+            Status::HttpVersionNotSupported => 505,
+            Status::Unknown(code) => *code,
+            Status::Custom(code, _) => *code,
+        }
+    }
+
     pub fn from_code(code: u16) -> Status {
         match code {
             101 => Status::SwitchingProtocols,
@@ -486,6 +900,7 @@ impl Status {
             301 => Status::MovedPermanently,
             302 => Status::MovedTemporarily,
             304 => Status::NotModified,
+            307 => Status::TemporaryRedirect,
             400 => Status::BadRequest,
             401 => Status::Unauthorized,
             403 => Status::Forbidden,
@@ -502,6 +917,10 @@ impl Status {
             414 => Status::RequestUriTooLong,
             415 => Status::UnsupportedMediaType,
             416 => Status::RequestedRangeNotSatisfiable,
+            417 => Status::ExpectationFailed,
+            418 => Status::ImATeapot,
+            428 => Status::PreconditionRequired,
+            429 => Status::TooManyRequests,
             500 => Status::InternalServerError,
             501 => Status::NotImplemented,
             502 => Status::BadGateway,
@@ -511,4 +930,112 @@ impl Status {
             _ => Status::Unknown(code),
         }
     }
+
+    /// The status of a `<code> <reason>` status line, `Custom` unless `reason` is
+    /// exactly the standard phrase of `code`, so that forwarding it keeps the phrase.
+    pub fn from_parts(code: u16, reason: &str) -> Status {
+        match Status::from_code(code) {
+            Status::Unknown(code) if !reason.is_empty() => Status::Custom(code, reason.to_string()),
+            Status::Unknown(code) => Status::Unknown(code),
+            status if status.reason() == reason => status,
+            _ => Status::Custom(code, reason.to_string()),
+        }
+    }
+
+    /// The reason phrase, `Not Found`.
+    pub fn reason(&self) -> String {
+        match self {
+            Status::Custom(_, reason) => reason.clone(),
+            status => status
+                .to_string()
+                .split_once(' ')
+                .map(|(_, reason)| reason.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_reason_phrases_round_trip() {
+        let custom = Status::from_parts(599, "Totally Custom");
+        assert!(matches!(&custom, Status::Custom(599, reason) if reason == "Totally Custom"));
+        assert_eq!(custom.to_string(), "599 Totally Custom");
+        assert_eq!(custom.reason(), "Totally Custom");
+
+        let renamed = Status::from_parts(404, "Nothing Here");
+        assert_eq!(renamed.code(), 404);
+        assert_eq!(renamed.to_string(), "404 Nothing Here");
+    }
+
+    #[test]
+    fn standard_reason_phrases_give_the_variant() {
+        assert!(matches!(
+            Status::from_parts(404, "Not Found"),
+            Status::NotFound
+        ));
+        assert_eq!(Status::NotFound.to_string(), "404 Not Found");
+
+        assert!(matches!(
+            Status::from_parts(418, "I'm a teapot"),
+            Status::ImATeapot
+        ));
+        assert_eq!(Status::from_code(418).to_string(), "418 I'm a teapot");
+        assert_eq!(Status::ImATeapot.reason(), "I'm a teapot");
+
+        assert!(matches!(Status::from_parts(299, ""), Status::Unknown(299)));
+    }
+
+    #[test]
+    fn cache_control_round_trips() {
+        let values = [
+            "no-cache",
+            "no-store, max-age=0",
+            "public, max-age=31536000, immutable",
+            "private, max-age=0, must-revalidate",
+            "max-age=604800, stale-while-revalidate=86400, stale-if-error=259200",
+            "no-transform,immutable,custom-ext=1",
+            "public , s-maxage=600 ,proxy-revalidate",
+            "private=\"Set-Cookie, X-Token\", no-cache=\"Authorization\"",
+            "max-stale=60, min-fresh=10, only-if-cached, must-understand",
+            "MAX-AGE=60, Vendor-Ext",
+            "max-age=abc",
+            ", no-store,,",
+        ];
+
+        for value in values {
+            let parsed = Cache::parse(&value.to_string());
+            let formatted = Cache::format(&parsed);
+
+            assert_eq!(Cache::parse(&formatted), parsed, "{}", value);
+            assert_eq!(Cache::format(&Cache::parse(&formatted)), formatted);
+        }
+
+        assert_eq!(
+            Cache::parse(&"no-transform,immutable,custom-ext=1".to_string()),
+            [
+                Cache::NoTransform,
+                Cache::Immutable,
+                Cache::Extension("custom-ext".to_string(), Some("1".to_string())),
+            ]
+        );
+        // Quoted commas stay in their directive
+        assert_eq!(
+            Cache::parse(&"private=\"Set-Cookie, X-Token\", max-age=5".to_string()),
+            [
+                Cache::Extension(
+                    "private".to_string(),
+                    Some("\"Set-Cookie, X-Token\"".to_string())
+                ),
+                Cache::MaxAge(5),
+            ]
+        );
+        assert_eq!(
+            Cache::format(&Cache::parse(&" public ,max-age = 60 ".to_string())),
+            "public, max-age=60"
+        );
+    }
 }
@@ -0,0 +1,549 @@
+use crate::common::Method;
+use crate::log::{LogFormat, Logger};
+use crate::response::ServerHeaderPolicy;
+use crate::server::{InvalidHeaderPolicy, ParseMode, ServerOptions};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Settings of the server binary, read from a TOML file:
+///
+/// ```toml
+/// [server]
+/// profile = "hardened"             # or "development", first as it resets the others
+/// port = 8080
+/// log = true
+/// log_format = "combined"          # "common", "combined" or "json"
+/// log_file = "access.log"          # appended to, stdout when not set
+/// log_canonical = false            # sorted query, no default port
+/// workers = 8                      # one per CPU when not set
+/// worker_queue = 128               # requests waiting for a worker, more get 503
+/// spawn_per_connection = false     # a thread per connection instead of workers
+/// max_connection_threads = 256     # live connection threads when spawning
+/// keep_alive_timeout = 15          # idle seconds, 0 closes after each response
+/// keep_alive_max_requests = 100    # per connection, 0 for no limit
+/// tcp_nodelay = true
+/// tcp_keepalive = 60               # idle seconds before probing, 0 for none
+/// reuse_address = true
+/// read_timeout = 10                # seconds, 0 waits forever
+/// slow_request_threshold = 1000    # milliseconds to receive a request, 0 for none
+///
+/// [http]
+/// answer_server_options = true
+/// allowed_methods = ["GET", "HEAD", "OPTIONS"]
+/// server_header = "off"            # "default", "off" or a custom value
+/// invalid_header_names = "reject"  # or "drop"
+/// parse_mode = "strict"            # or "lenient"
+/// http09 = false                   # answer HTTP/0.9 requests instead of 505
+/// security_headers = true          # nosniff, SAMEORIGIN frames
+/// strict_slashes = false
+/// trust_proxy = false              # client address from Forwarded headers
+/// debug_headers = false            # X-Compression stats on responses
+///
+/// [limits]
+/// max_headers = 100
+/// max_head_size = 16384
+/// max_body_size = 1048576
+/// max_ranges = 16
+/// max_connections = 1024           # open at once, 0 for no limit
+/// max_connections_per_ip = 32      # open at once, 0 for no limit
+/// count_ipv6_by_subnet = true      # IPv6 clients counted by /64
+///
+/// [compression]
+/// threshold = 256
+/// brotli_buffer_size = 32768
+/// ```
+///
+/// Only this subset of TOML is understood: tables, strings, integers, booleans
+/// and arrays of strings on a single line.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub port: u16,
+    pub options: ServerOptions,
+    /// The access log format, when set `options.logger` writes it.
+    pub log_format: Option<LogFormat>,
+    /// Where the access log goes, stdout when `None`.
+    pub log_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 8080,
+            options: ServerOptions::default(),
+            log_format: None,
+            log_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    /// 1-based line of the error, 0 when the file could not be read.
+    pub line: usize,
+    /// The key as `table.key`, if the error is about one.
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match (self.line, &self.key) {
+            (0, _) => write!(f, "{}", self.message),
+            (line, Some(key)) => write!(f, "line {}: `{}`: {}", line, key, self.message),
+            (line, None) => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+impl Config {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let raw = fs::read_to_string(&path).map_err(|err| ConfigError {
+            line: 0,
+            key: None,
+            message: format!("Cannot read {}: {}", path.as_ref().display(), err),
+        })?;
+
+        Config::parse(&raw)
+    }
+
+    pub fn parse(raw: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut table = String::new();
+
+        for (index, line) in raw.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |key: Option<&str>, message: String| ConfigError {
+                line: line_number,
+                key: key.map(|key| key.to_string()),
+                message,
+            };
+
+            let line = strip_comment(line).trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let Some(name) = name.strip_suffix(']') else {
+                    return Err(error(None, "Unclosed table header".to_string()));
+                };
+                let name = name.trim();
+
+                if !["server", "http", "limits", "compression"].contains(&name) {
+                    return Err(error(None, format!("Unknown table [{}]", name)));
+                }
+
+                table = name.to_string();
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(None, "Expected `key = value`".to_string()));
+            };
+
+            let key = match table.as_str() {
+                "" => key.trim().to_string(),
+                table => format!("{}.{}", table, key.trim()),
+            };
+            let value = parse_value(value.trim()).map_err(|message| error(Some(&key), message))?;
+
+            config
+                .set(&key, value)
+                .map_err(|message| error(Some(&key), message))?;
+        }
+
+        // Known once every key is read, as the format and file may come in any order
+        if config.log_format.is_some() || config.log_file.is_some() {
+            let format = config.log_format.unwrap_or_default();
+
+            let logger = match config.log_file {
+                Some(ref path) => Logger::file(path, format).map_err(|err| ConfigError {
+                    line: 0,
+                    key: Some("server.log_file".to_string()),
+                    message: format!("Cannot open {}: {}", path.display(), err),
+                })?,
+                None => Logger::formatted(format, io::stdout()),
+            };
+
+            // Kept out of the access log, whose lines tools expect in `format`
+            config.options.logger = Some(logger.with_slow_requests(|slow| eprintln!("{}", slow)));
+        }
+
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        let options = &mut self.options;
+
+        match key {
+            "server.port" => self.port = integer(value, 1, u16::MAX as i64)? as u16,
+            "server.profile" => {
+                *options = match string(value)?.as_str() {
+                    "hardened" => ServerOptions::hardened(),
+                    "development" => ServerOptions::development(),
+                    other => {
+                        return Err(format!(
+                            "expected \"hardened\" or \"development\", found \"{}\"",
+                            other
+                        ))
+                    }
+                }
+            }
+            "server.log" => {
+                options.logger = boolean(value)?.then(Logger::stdout);
+            }
+            "server.log_format" => {
+                self.log_format = Some(match string(value)?.as_str() {
+                    "common" => LogFormat::Common,
+                    "combined" => LogFormat::Combined,
+                    "json" => LogFormat::Json,
+                    other => {
+                        return Err(format!(
+                            "expected \"common\", \"combined\" or \"json\", found \"{}\"",
+                            other
+                        ))
+                    }
+                })
+            }
+            "server.log_file" => self.log_file = Some(PathBuf::from(string(value)?)),
+            "server.log_canonical" => options.log_canonical = boolean(value)?,
+            "server.keep_alive_timeout" => {
+                options.keep_alive_timeout = match integer(value, 0, 3600)? {
+                    0 => None,
+                    seconds => Some(Duration::from_secs(seconds as u64)),
+                }
+            }
+            "server.keep_alive_max_requests" => {
+                options.keep_alive_max_requests = match integer(value, 0, 1_000_000)? {
+                    0 => None,
+                    max => Some(max as usize),
+                }
+            }
+            "server.tcp_nodelay" => options.tcp_nodelay = boolean(value)?,
+            "server.tcp_keepalive" => {
+                options.tcp_keepalive = match integer(value, 0, 86400)? {
+                    0 => None,
+                    seconds => Some(Duration::from_secs(seconds as u64)),
+                }
+            }
+            "server.reuse_address" => options.reuse_address = boolean(value)?,
+            "server.read_timeout" => {
+                options.read_timeout = match integer(value, 0, 3600)? {
+                    0 => None,
+                    seconds => Some(Duration::from_secs(seconds as u64)),
+                }
+            }
+            "server.slow_request_threshold" => {
+                options.slow_request_threshold = match integer(value, 0, 3_600_000)? {
+                    0 => None,
+                    millis => Some(Duration::from_millis(millis as u64)),
+                }
+            }
+            "server.workers" => options.workers = Some(integer(value, 1, 1024)? as usize),
+            "server.worker_queue" => options.worker_queue = integer(value, 0, 65536)? as usize,
+            "server.spawn_per_connection" => options.spawn_per_connection = boolean(value)?,
+            "server.max_connection_threads" => {
+                options.max_connection_threads = integer(value, 1, 65536)? as usize
+            }
+            "http.answer_server_options" => options.answer_server_options = boolean(value)?,
+            "http.allowed_methods" => match value {
+                Value::Array(methods) => {
+                    options.allowed_methods = methods.iter().map(|m| Method::parse(m)).collect()
+                }
+                value => return Err(format!("expected an array, found {}", value.kind())),
+            },
+            "http.server_header" => {
+                options.server_header = match string(value)?.as_str() {
+                    "default" => ServerHeaderPolicy::Default,
+                    "off" => ServerHeaderPolicy::Off,
+                    custom => ServerHeaderPolicy::Custom(custom.to_string()),
+                }
+            }
+            "http.invalid_header_names" => {
+                options.invalid_header_names = match string(value)?.as_str() {
+                    "reject" => InvalidHeaderPolicy::Reject,
+                    "drop" => InvalidHeaderPolicy::Drop,
+                    other => {
+                        return Err(format!(
+                            "expected \"reject\" or \"drop\", found \"{}\"",
+                            other
+                        ))
+                    }
+                }
+            }
+            "http.parse_mode" => {
+                options.parse_mode = match string(value)?.as_str() {
+                    "strict" => ParseMode::Strict,
+                    "lenient" => ParseMode::Lenient,
+                    other => {
+                        return Err(format!(
+                            "expected \"strict\" or \"lenient\", found \"{}\"",
+                            other
+                        ))
+                    }
+                }
+            }
+            "http.http09" => options.http09 = boolean(value)?,
+            "http.security_headers" => options.security_headers = boolean(value)?,
+            "http.strict_slashes" => options.strict_slashes = boolean(value)?,
+            "http.trust_proxy" => options.trust_proxy = boolean(value)?,
+            "http.debug_headers" => options.debug_headers = boolean(value)?,
+            "limits.max_headers" => options.max_headers = integer(value, 1, i64::MAX)? as usize,
+            "limits.max_head_size" => options.max_head_size = integer(value, 1, i64::MAX)? as usize,
+            "limits.max_body_size" => {
+                options.max_body_size = Some(integer(value, 0, i64::MAX)? as usize)
+            }
+            "limits.max_ranges" => options.max_ranges = integer(value, 1, i64::MAX)? as usize,
+            "limits.max_connections" => {
+                options.max_connections = match integer(value, 0, i64::MAX)? {
+                    0 => None,
+                    max => Some(max as usize),
+                }
+            }
+            "limits.max_connections_per_ip" => {
+                options.max_connections_per_ip = match integer(value, 0, i64::MAX)? {
+                    0 => None,
+                    max => Some(max as usize),
+                }
+            }
+            "limits.count_ipv6_by_subnet" => options.count_ipv6_by_subnet = boolean(value)?,
+            "compression.threshold" => {
+                options.compression_threshold = integer(value, 0, i64::MAX)? as usize
+            }
+            "compression.brotli_buffer_size" => {
+                options.brotli_buffer_size = integer(value, 1, i64::MAX)? as usize
+            }
+            _ => return Err("unknown key".to_string()),
+        }
+
+        Ok(())
+    }
+}
+
+/// Remove a `#` comment, unless it is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn parse_value(raw: &str) -> Result<Value, String> {
+    match raw {
+        "true" => return Ok(Value::Boolean(true)),
+        "false" => return Ok(Value::Boolean(false)),
+        "" => return Err("missing value".to_string()),
+        _ => {}
+    }
+
+    if raw.starts_with('"') {
+        return parse_string(raw).map(Value::String);
+    }
+
+    if let Some(items) = raw.strip_prefix('[') {
+        let Some(items) = items.strip_suffix(']') else {
+            return Err("unclosed array".to_string());
+        };
+
+        return items
+            .split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(parse_string)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array);
+    }
+
+    raw.replace('_', "")
+        .parse()
+        .map(Value::Integer)
+        .map_err(|_| format!("invalid value {}", raw))
+}
+
+fn parse_string(raw: &str) -> Result<String, String> {
+    let Some(inner) = raw.strip_prefix('"').and_then(|raw| raw.strip_suffix('"')) else {
+        return Err(format!("invalid string {}", raw));
+    };
+
+    let mut out = String::new();
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            other => {
+                return Err(format!(
+                    "unsupported escape \\{}",
+                    other.map(String::from).unwrap_or_default()
+                ))
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn integer(value: Value, min: i64, max: i64) -> Result<i64, String> {
+    match value {
+        Value::Integer(n) if n >= min && n <= max => Ok(n),
+        Value::Integer(n) => Err(format!("{} is out of range ({} to {})", n, min, max)),
+        value => Err(format!("expected an integer, found {}", value.kind())),
+    }
+}
+
+fn boolean(value: Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean(b) => Ok(b),
+        value => Err(format!("expected a boolean, found {}", value.kind())),
+    }
+}
+
+fn string(value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        value => Err(format!("expected a string, found {}", value.kind())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"# A service behind a proxy
+[server]
+port = 9000
+workers = 4
+keep_alive_timeout = 0          # close after each response
+read_timeout = 30
+
+[http]
+allowed_methods = ["GET", "HEAD"]
+server_header = "edge #1"
+parse_mode = "lenient"
+trust_proxy = true
+
+[limits]
+max_body_size = 1_048_576
+max_connections_per_ip = 16
+
+[compression]
+threshold = 1024
+"#;
+
+    #[test]
+    fn valid_file() {
+        let path = std::env::temp_dir().join(format!("config-{}.toml", std::process::id()));
+
+        fs::write(&path, FIXTURE).unwrap();
+
+        let config = Config::from_file(&path);
+
+        fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        let options = &config.options;
+
+        assert_eq!(config.port, 9000);
+        assert_eq!(options.workers, Some(4));
+        assert_eq!(options.keep_alive_timeout, None);
+        assert_eq!(options.read_timeout, Some(Duration::from_secs(30)));
+        assert!(matches!(
+            options.allowed_methods[..],
+            [Method::Get, Method::Head]
+        ));
+        assert_eq!(
+            options.server_header,
+            ServerHeaderPolicy::Custom("edge #1".to_string())
+        );
+        assert_eq!(options.parse_mode, ParseMode::Lenient);
+        assert!(options.trust_proxy);
+        assert_eq!(options.max_body_size, Some(1024 * 1024));
+        assert_eq!(options.max_connections_per_ip, Some(16));
+        assert_eq!(options.compression_threshold, 1024);
+        assert!(config.log_format.is_none() && options.logger.is_none());
+    }
+
+    #[test]
+    fn invalid_values_name_the_key_and_line() {
+        let err = Config::parse("[server]\nworkers = 4\nport = 0\n").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "line 3: `server.port`: 0 is out of range (1 to 65535)"
+        );
+
+        let err = Config::parse("\n[http]\ntrust_proxy = \"yes\"\n").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "line 3: `http.trust_proxy`: expected a boolean, found a string"
+        );
+
+        let err = Config::parse("[limits]\nmax_bodysize = 10\n").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "line 2: `limits.max_bodysize`: unknown key"
+        );
+
+        let err = Config::parse("[server]\nport 8080\n").unwrap_err();
+
+        assert_eq!(err.to_string(), "line 2: Expected `key = value`");
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = Config::from_file("/nonexistent/server.toml").unwrap_err();
+
+        assert_eq!(err.line, 0);
+        assert!(
+            err.to_string()
+                .starts_with("Cannot read /nonexistent/server.toml: "),
+            "{}",
+            err
+        );
+    }
+}
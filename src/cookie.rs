@@ -1,6 +1,8 @@
+use crate::request::Request;
 use urlencoding::decode;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RequestCookie(pub String, pub String);
 
 #[derive(Debug, Clone)]
@@ -40,6 +42,24 @@ impl RequestCookie {
         return Ok(out);
     }
 
+    /// Like `parse`, but pairs that cannot be read are skipped and `;` needs no space after it.
+    pub fn parse_lenient(raw: &str) -> Vec<RequestCookie> {
+        raw.split(';')
+            .filter_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+
+                if name.is_empty() {
+                    return None;
+                }
+
+                Some(RequestCookie(
+                    name.to_string(),
+                    decode(value).ok()?.to_string(),
+                ))
+            })
+            .collect()
+    }
+
     pub fn name(&self) -> &String {
         &self.0
     }
@@ -50,6 +70,7 @@ impl RequestCookie {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResponseCookie {
     pub name: String,
     pub value: String,
@@ -61,6 +82,23 @@ pub struct ResponseCookie {
     pub http_only: bool,
 }
 
+impl ResponseCookie {
+    /// A cookie for the whole site that scripts can't read, `Secure` when the
+    /// request came over HTTPS (see `Request::is_secure`), until the browser closes.
+    pub fn session(name: &str, value: &str, request: &Request) -> ResponseCookie {
+        ResponseCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            max_age: None,
+            expires: None,
+            path: Some("/".to_string()),
+            domain: None,
+            secure: request.is_secure(),
+            http_only: true,
+        }
+    }
+}
+
 impl ToString for ResponseCookie {
     fn to_string(&self) -> String {
         let mut out = String::new();
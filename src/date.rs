@@ -0,0 +1,185 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+const IMF_FIXDATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+const RFC_850: &str = "%A, %d-%b-%y %H:%M:%S GMT";
+const ASCTIME: &str = "%a %b %e %H:%M:%S %Y";
+
+/// A date as used by HTTP headers (`Date`, `Last-Modified`, `If-Unmodified-Since`, ...).
+///
+/// HTTP dates have a one second precision, so sub-second parts are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HttpDate(DateTime<Utc>);
+
+impl HttpDate {
+    pub fn now() -> HttpDate {
+        HttpDate::from(Utc::now())
+    }
+
+    pub fn from_timestamp(secs: i64) -> Option<HttpDate> {
+        Utc.timestamp_opt(secs, 0).single().map(HttpDate)
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.0.timestamp()
+    }
+
+    pub fn datetime(&self) -> DateTime<Utc> {
+        self.0
+    }
+
+    /// Parse any of the three formats a recipient must accept:
+    /// IMF-fixdate, the obsolete RFC 850 format and asctime.
+    pub fn parse(raw: &str) -> Option<HttpDate> {
+        let raw = raw.trim();
+
+        [IMF_FIXDATE, RFC_850, ASCTIME]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(raw, format).ok())
+            .map(|naive| HttpDate(Utc.from_utc_datetime(&naive)))
+    }
+}
+
+impl From<DateTime<Utc>> for HttpDate {
+    fn from(datetime: DateTime<Utc>) -> Self {
+        HttpDate::from_timestamp(datetime.timestamp()).unwrap_or(HttpDate(datetime))
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HttpDate::parse(s).ok_or(())
+    }
+}
+
+impl Display for HttpDate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.0.format(IMF_FIXDATE))
+    }
+}
+
+/// The current date formatted for a `Date` header, formatted again at most
+/// once per second however many responses ask for it.
+#[derive(Debug)]
+pub struct DateCache {
+    /// The second formatted and its formatting.
+    cached: RwLock<(i64, String)>,
+}
+
+/// The cache of the `Date` header the server adds to every response.
+pub static DATE_CACHE: DateCache = DateCache::new();
+
+impl DateCache {
+    pub const fn new() -> DateCache {
+        DateCache {
+            cached: RwLock::new((i64::MIN, String::new())),
+        }
+    }
+
+    /// The current date, `Sun, 06 Nov 1994 08:49:37 GMT`.
+    pub fn now(&self) -> String {
+        self.at(Utc::now().timestamp())
+    }
+
+    /// The date of the Unix `timestamp`, from the cache when it is the second
+    /// formatted last.
+    pub fn at(&self, timestamp: i64) -> String {
+        {
+            let cached = self.cached.read().unwrap_or_else(|err| err.into_inner());
+
+            if cached.0 == timestamp {
+                return cached.1.clone();
+            }
+        }
+
+        let formatted = match HttpDate::from_timestamp(timestamp) {
+            Some(date) => date.to_string(),
+            None => return String::new(),
+        };
+        let mut cached = self.cached.write().unwrap_or_else(|err| err.into_inner());
+
+        // Another thread may have cached a later second meanwhile
+        if timestamp > cached.0 {
+            *cached = (timestamp, formatted.clone());
+        }
+
+        formatted
+    }
+}
+
+impl Default for DateCache {
+    fn default() -> Self {
+        DateCache::new()
+    }
+}
+
+/// The value of a `Retry-After` header: a delay or a date.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryAfter {
+    Seconds(u64),
+    Date(HttpDate),
+}
+
+impl RetryAfter {
+    pub fn parse(raw: &str) -> Option<RetryAfter> {
+        let raw = raw.trim();
+
+        match raw.parse() {
+            Ok(seconds) => Some(RetryAfter::Seconds(seconds)),
+            Err(_) => HttpDate::parse(raw).map(RetryAfter::Date),
+        }
+    }
+}
+
+impl Display for RetryAfter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            RetryAfter::Seconds(seconds) => write!(f, "{}", seconds),
+            RetryAfter::Date(date) => write!(f, "{}", date),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_cache_across_a_second_boundary() {
+        let cache = DateCache::new();
+
+        assert_eq!(cache.at(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(cache.at(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(cache.at(784111778), "Sun, 06 Nov 1994 08:49:38 GMT");
+        assert_eq!(cache.cached.read().unwrap().0, 784111778);
+
+        // A slower thread formatting an older second keeps the later one cached
+        assert_eq!(cache.at(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(cache.cached.read().unwrap().0, 784111778);
+    }
+
+    #[test]
+    fn http_dates_round_trip() {
+        let date = HttpDate::from_timestamp(784111777).unwrap();
+
+        for raw in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(HttpDate::parse(raw), Some(date), "{}", raw);
+        }
+
+        // Always written as IMF-fixdate
+        assert_eq!(date.to_string(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(date.to_string().parse(), Ok(date));
+
+        for raw in ["", "yesterday", "Sun, 06 Nov 1994 25:49:37 GMT"] {
+            assert_eq!(HttpDate::parse(raw), None, "{}", raw);
+        }
+    }
+}
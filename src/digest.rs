@@ -0,0 +1,160 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use md5::Md5;
+use sha2::{Digest as _, Sha256, Sha512};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha256,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    pub fn parse(raw: &str) -> Option<DigestAlgorithm> {
+        match raw.trim().to_lowercase().as_str() {
+            "md5" => Some(DigestAlgorithm::Md5),
+            "sha-256" => Some(DigestAlgorithm::Sha256),
+            "sha-512" => Some(DigestAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "md5",
+            DigestAlgorithm::Sha256 => "sha-256",
+            DigestAlgorithm::Sha512 => "sha-512",
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgorithm::Md5 => Md5::digest(data).to_vec(),
+            DigestAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            DigestAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+/// A single `algorithm=value` member of a `Digest` or `Repr-Digest` header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Digest {
+    pub algorithm: DigestAlgorithm,
+    pub value: Vec<u8>,
+}
+
+impl Digest {
+    pub fn new(algorithm: DigestAlgorithm, data: &[u8]) -> Digest {
+        Digest {
+            algorithm,
+            value: algorithm.compute(data),
+        }
+    }
+
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.algorithm.compute(data) == self.value
+    }
+
+    /// Parse a legacy `Digest` header (RFC 3230): `sha-256=BASE64, md5=BASE64`.
+    ///
+    /// Members using an unsupported algorithm are skipped.
+    pub fn parse_legacy(raw: &str) -> Option<Vec<Digest>> {
+        Digest::parse_list(raw, |value| Some(value))
+    }
+
+    /// Parse a `Repr-Digest` header (RFC 9530): `sha-256=:BASE64:`.
+    ///
+    /// Members using an unsupported algorithm are skipped.
+    pub fn parse_repr(raw: &str) -> Option<Vec<Digest>> {
+        Digest::parse_list(raw, |value| value.strip_prefix(':')?.strip_suffix(':'))
+    }
+
+    pub fn format_legacy(digests: &[Digest]) -> String {
+        digests
+            .iter()
+            .map(|digest| {
+                format!(
+                    "{}={}",
+                    digest.algorithm.name(),
+                    BASE64.encode(&digest.value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn format_repr(digests: &[Digest]) -> String {
+        digests
+            .iter()
+            .map(|digest| {
+                format!(
+                    "{}=:{}:",
+                    digest.algorithm.name(),
+                    BASE64.encode(&digest.value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn parse_list(raw: &str, unwrap: fn(&str) -> Option<&str>) -> Option<Vec<Digest>> {
+        let mut digests = vec![];
+
+        for member in raw.split(',') {
+            let (algorithm, value) = member.trim().split_once('=')?;
+            let value = BASE64.decode(unwrap(value.trim())?).ok()?;
+
+            if let Some(algorithm) = DigestAlgorithm::parse(algorithm) {
+                digests.push(Digest { algorithm, value });
+            }
+        }
+
+        Some(digests)
+    }
+}
+
+pub fn decode_base64(raw: &str) -> Option<Vec<u8>> {
+    BASE64.decode(raw.trim()).ok()
+}
+
+pub fn encode_base64(data: &[u8]) -> String {
+    BASE64.encode(data)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DigestError {
+    /// A digest header could not be parsed.
+    Malformed(String),
+    /// The body does not match the digest sent in `header`.
+    Mismatch {
+        header: String,
+        algorithm: DigestAlgorithm,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for DigestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            DigestError::Malformed(header) => write!(f, "Malformed {} header", header),
+            DigestError::Mismatch {
+                header,
+                algorithm,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} mismatch ({}): expected {}, body has {}",
+                header,
+                algorithm.name(),
+                expected,
+                actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DigestError {}
@@ -0,0 +1,199 @@
+use crate::mime::Mime;
+use urlencoding::encode;
+
+/// Which types `Response::safe_download` keeps for a file, by extension. Any
+/// other file is sent as `application/octet-stream`.
+#[derive(Debug, Clone)]
+pub struct DownloadPolicy {
+    safe_types: Vec<(String, Mime)>,
+}
+
+impl Default for DownloadPolicy {
+    /// Plain text, PDF, common images, audio, video and archives.
+    fn default() -> Self {
+        let mut policy = DownloadPolicy { safe_types: vec![] };
+
+        policy
+            .allow("txt", Mime::text("plain"))
+            .allow("pdf", Mime::application("pdf"))
+            .allow("png", Mime::image("png"))
+            .allow("jpg", Mime::image("jpeg"))
+            .allow("jpeg", Mime::image("jpeg"))
+            .allow("gif", Mime::image("gif"))
+            .allow("webp", Mime::image("webp"))
+            .allow("mp3", Mime::audio("mpeg"))
+            .allow("mp4", Mime::video("mp4"))
+            .allow("zip", Mime::application("zip"));
+
+        policy
+    }
+}
+
+impl DownloadPolicy {
+    /// No type kept, every file is `application/octet-stream`.
+    pub fn new() -> DownloadPolicy {
+        DownloadPolicy { safe_types: vec![] }
+    }
+
+    /// Send files ending in `.extension` as `mime`. Types a browser may run
+    /// scripts from (HTML, SVG, XML) are never kept.
+    pub fn allow(&mut self, extension: &str, mime: Mime) -> &mut Self {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+
+        self.safe_types.retain(|(known, _)| *known != extension);
+        self.safe_types.push((extension, mime));
+
+        self
+    }
+
+    /// The type to send `filename` as.
+    pub fn content_type(&self, filename: &str) -> Mime {
+        let extension = match filename.rsplit_once('.') {
+            Some((_, extension)) => extension.to_ascii_lowercase(),
+            None => return Mime::application("octet-stream"),
+        };
+
+        self.safe_types
+            .iter()
+            .find(|(known, _)| *known == extension)
+            .map(|(_, mime)| mime.clone())
+            .filter(|mime| !is_active(mime))
+            .unwrap_or_else(|| Mime::application("octet-stream"))
+    }
+}
+
+/// Whether a browser may run scripts from a document of type `mime`.
+fn is_active(mime: &Mime) -> bool {
+    let subtype = mime.subtype().to_ascii_lowercase();
+
+    matches!(
+        (mime.type_().to_ascii_lowercase().as_str(), subtype.as_str()),
+        ("text", "html" | "xml" | "javascript")
+            | ("application", "xml" | "xhtml+xml" | "javascript")
+    ) || subtype.ends_with("+xml")
+}
+
+/// The last component of an uploaded file's name, without control characters
+/// or quotes: `..\..\x.html` is `x.html`. `download` when nothing is left.
+pub fn sanitize_filename(filename: &str) -> String {
+    let name = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .collect::<String>();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    if name.is_empty() {
+        "download".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `attachment; filename="report.pdf"`, along a UTF-8 `filename*` (RFC 6266)
+/// when `filename` isn't ASCII.
+pub fn attachment_disposition(filename: &str) -> String {
+    if filename.is_ascii() {
+        return format!("attachment; filename=\"{}\"", filename);
+    }
+
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback,
+        encode(filename)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Header;
+    use crate::response::Response;
+
+    #[test]
+    fn uploaded_files_downloaded_safely() {
+        let header = |response: &Response, name| response.get_header(name).map(Header::value);
+
+        let svg = Response::safe_download(b"<svg onload=alert(1)>".to_vec(), "evil.svg");
+
+        assert_eq!(
+            header(&svg, "Content-Type").as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(
+            header(&svg, "Content-Disposition").as_deref(),
+            Some("attachment; filename=\"evil.svg\"")
+        );
+        assert_eq!(
+            header(&svg, "X-Content-Type-Options").as_deref(),
+            Some("nosniff")
+        );
+
+        let html = Response::safe_download(vec![], "..\\..\\x.html");
+
+        assert_eq!(
+            header(&html, "Content-Disposition").as_deref(),
+            Some("attachment; filename=\"x.html\"")
+        );
+        assert_eq!(
+            header(&html, "Content-Type").as_deref(),
+            Some("application/octet-stream")
+        );
+
+        let pdf = Response::safe_download(vec![], "Report.PDF");
+
+        assert_eq!(
+            header(&pdf, "Content-Type").as_deref(),
+            Some("application/pdf")
+        );
+    }
+
+    #[test]
+    fn filenames_sanitized() {
+        let cases = [
+            ("../../etc/passwd", "passwd"),
+            ("..\\..\\x.html", "x.html"),
+            ("a\r\nb\"c.txt", "abc.txt"),
+            ("  .hidden. ", "hidden"),
+            ("dir/", "download"),
+            ("..", "download"),
+        ];
+
+        for (filename, sanitized) in cases {
+            assert_eq!(sanitize_filename(filename), sanitized, "{:?}", filename);
+        }
+
+        assert_eq!(
+            attachment_disposition("résumé.pdf"),
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        );
+    }
+
+    #[test]
+    fn active_types_never_allowed() {
+        let mut policy = DownloadPolicy::new();
+
+        policy
+            .allow(".svg", Mime::image("svg+xml"))
+            .allow("html", Mime::text("html"))
+            .allow("csv", Mime::text("csv"));
+
+        for filename in ["a.svg", "a.html", "a.png", "noextension"] {
+            assert_eq!(
+                policy.content_type(filename).to_string(),
+                "application/octet-stream",
+                "{}",
+                filename
+            );
+        }
+
+        assert_eq!(policy.content_type("a.CSV").to_string(), "text/csv");
+    }
+}
@@ -0,0 +1,175 @@
+use crate::limit::ConnectionCounts;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The shutdown switch of a server and its requests in flight. Clones share
+/// them, so one can be kept to stop a server moved into `Server::serve`.
+#[derive(Debug, Clone)]
+pub struct Drain {
+    state: Arc<DrainState>,
+    connections: Arc<ConnectionCounts>,
+    /// The addresses of the listeners, connected to once to wake them up.
+    listeners: Vec<SocketAddr>,
+}
+
+#[derive(Debug, Default)]
+struct DrainState {
+    shutting_down: AtomicBool,
+    progress: Mutex<Progress>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    /// Requests handed to the application and not dropped yet.
+    in_flight: usize,
+    /// Whether the shutdown is over, every connection closed.
+    closed: bool,
+}
+
+impl Drain {
+    pub fn new(connections: Arc<ConnectionCounts>, listeners: Vec<SocketAddr>) -> Drain {
+        Drain {
+            state: Arc::default(),
+            connections,
+            listeners,
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.state.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Requests handed to the application that weren't dropped yet.
+    pub fn in_flight(&self) -> usize {
+        self.progress().in_flight
+    }
+
+    /// Count a request in flight until the returned guard is dropped.
+    pub fn start(&self) -> InFlight {
+        self.progress().in_flight += 1;
+
+        InFlight {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Stop accepting connections and wait up to `timeout` for the requests in
+    /// flight to be dropped, their responses sent with `Connection: close`.
+    /// Every connection still open is then closed, requests included.
+    ///
+    /// Returns whether all requests finished in time.
+    pub fn shutdown_graceful(&self, timeout: Duration) -> bool {
+        self.state.shutting_down.store(true, Ordering::SeqCst);
+        self.wake_listeners();
+
+        let deadline = Instant::now() + timeout;
+        let mut progress = self.progress();
+
+        while progress.in_flight > 0 {
+            let now = Instant::now();
+
+            if now >= deadline {
+                break;
+            }
+
+            progress = self
+                .state
+                .changed
+                .wait_timeout(progress, deadline - now)
+                .unwrap_or_else(|err| err.into_inner())
+                .0;
+        }
+
+        let drained = progress.in_flight == 0;
+
+        self.connections.close_all();
+        progress.closed = true;
+        self.state.changed.notify_all();
+
+        drained
+    }
+
+    /// Stop accepting connections and close every open one at once.
+    pub fn shutdown(&self) {
+        self.shutdown_graceful(Duration::ZERO);
+    }
+
+    /// Block until a shutdown closed the connections.
+    pub fn wait(&self) {
+        let mut progress = self.progress();
+
+        while !progress.closed {
+            progress = self
+                .state
+                .changed
+                .wait(progress)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// Connect to each listener, so that a thread blocked accepting sees the shutdown.
+    fn wake_listeners(&self) {
+        for address in self.listeners.iter() {
+            let ip = match address.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                ip => ip,
+            };
+
+            let _ = TcpStream::connect_timeout(
+                &SocketAddr::new(ip, address.port()),
+                Duration::from_secs(1),
+            );
+        }
+    }
+
+    fn progress(&self) -> MutexGuard<'_, Progress> {
+        self.state
+            .progress
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// A request counted in `Drain::in_flight`, until dropped along with it.
+#[derive(Debug)]
+pub struct InFlight {
+    state: Arc<DrainState>,
+}
+
+impl InFlight {
+    /// Whether the server is shutting down, the connection then closing after
+    /// the response.
+    pub fn is_draining(&self) -> bool {
+        self.state.shutting_down.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut progress = self
+            .state
+            .progress
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        progress.in_flight -= 1;
+        self.state.changed.notify_all();
+    }
+}
+
+/// Why `Server::next` gave no request: the server was shut down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShutDown;
+
+impl Display for ShutDown {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "The server was shut down")
+    }
+}
+
+impl std::error::Error for ShutDown {}
@@ -0,0 +1,261 @@
+use crate::response::{BodyEncoding, CompressionLevel};
+use brotli::{CompressorWriter, Decompressor};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, Read, Result as IoResult, Write};
+
+/// Size of the internal buffer of the brotli encoder in `compress`.
+const BROTLI_BUFFER_SIZE: usize = 32 * 1024;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The decoded data would be larger than the limit, which is given.
+    TooLarge(usize),
+    /// A content coding other than gzip, deflate, br or identity.
+    Unsupported(String),
+    /// The data is not valid for the encoding.
+    Invalid(BodyEncoding, IoError),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            DecodeError::TooLarge(limit) => write!(f, "Decoded data larger than {} bytes", limit),
+            DecodeError::Unsupported(coding) => write!(f, "Unsupported content coding: {}", coding),
+            DecodeError::Invalid(encoding, err) => {
+                write!(f, "Invalid {} data: {}", encoding.to_string(), err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[cfg(test)]
+thread_local! {
+    /// Makes `compress_buffered` fail on this thread, for tests of its callers.
+    pub static FAIL_COMPRESSION: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Compress `data` with `encoding` at `level`.
+pub fn compress(data: &[u8], encoding: BodyEncoding, level: CompressionLevel) -> IoResult<Vec<u8>> {
+    compress_buffered(data, encoding, level, BROTLI_BUFFER_SIZE)
+}
+
+/// Like `compress`, with `brotli_buffer_size` the size of the brotli encoder buffer.
+pub fn compress_buffered(
+    data: &[u8],
+    encoding: BodyEncoding,
+    level: CompressionLevel,
+    brotli_buffer_size: usize,
+) -> IoResult<Vec<u8>> {
+    #[cfg(test)]
+    if FAIL_COMPRESSION.get() {
+        return Err(IoError::other("Compression failure injected by a test"));
+    }
+
+    let level = level.level();
+
+    match encoding {
+        BodyEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        BodyEncoding::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(level));
+
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        BodyEncoding::Brotli => {
+            // Writing into the output keeps the encoder buffer fixed, whatever the body size
+            let mut encoder = CompressorWriter::new(Vec::new(), brotli_buffer_size, level, 20);
+
+            encoder.write_all(data)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/// Decompress `data` encoded with `encoding`, failing with `TooLarge` as soon
+/// as the output goes past `max_output` bytes: a small bomb never gets to
+/// allocate more than that.
+///
+/// `deflate` is read with or without the zlib wrapper, as clients send both.
+pub fn decompress(
+    data: &[u8],
+    encoding: BodyEncoding,
+    max_output: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    let decoder: Box<dyn Read + '_> = match encoding {
+        BodyEncoding::Gzip => Box::new(GzDecoder::new(data)),
+        BodyEncoding::Deflate if is_zlib(data) => Box::new(ZlibDecoder::new(data)),
+        BodyEncoding::Deflate => Box::new(DeflateDecoder::new(data)),
+        BodyEncoding::Brotli => Box::new(Decompressor::new(data, 4096)),
+    };
+
+    // One byte past the limit tells a body of exactly `max_output` from a larger one
+    let mut output = Vec::new();
+
+    decoder
+        .take(max_output as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|err| DecodeError::Invalid(encoding, err))?;
+
+    if output.len() > max_output {
+        return Err(DecodeError::TooLarge(max_output));
+    }
+
+    Ok(output)
+}
+
+/// Undo the content codings of a `Content-Encoding` header, listed in the order
+/// they were applied, each step bounded by `max_output`.
+pub fn decode_content(
+    data: &[u8],
+    codings: &[String],
+    max_output: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    let mut data = data.to_vec();
+
+    for coding in codings.iter().rev() {
+        let encoding = match coding.trim().to_ascii_lowercase().as_str() {
+            "identity" | "" => continue,
+            "gzip" | "x-gzip" => BodyEncoding::Gzip,
+            "deflate" => BodyEncoding::Deflate,
+            "br" => BodyEncoding::Brotli,
+            _ => return Err(DecodeError::Unsupported(coding.trim().to_string())),
+        };
+
+        data = decompress(&data, encoding, max_output)?;
+    }
+
+    Ok(data)
+}
+
+/// Whether `data` starts with a zlib header (RFC 1950): deflate method and a
+/// valid header checksum.
+fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+
+    const ENCODINGS: [BodyEncoding; 3] = [
+        BodyEncoding::Gzip,
+        BodyEncoding::Deflate,
+        BodyEncoding::Brotli,
+    ];
+
+    #[test]
+    fn round_trips() {
+        let data = "The quick brown fox jumps over the lazy dog. ".repeat(200);
+
+        for encoding in ENCODINGS {
+            for level in [CompressionLevel::fast(), CompressionLevel::best()] {
+                let compressed = compress(data.as_bytes(), encoding, level).unwrap();
+
+                assert!(compressed.len() < data.len());
+                assert_eq!(
+                    decompress(&compressed, encoding, data.len()).unwrap(),
+                    data.as_bytes()
+                );
+            }
+        }
+
+        // Deflate with the zlib wrapper, as most clients send it
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+
+        zlib.write_all(data.as_bytes()).unwrap();
+
+        let zlib = zlib.finish().unwrap();
+
+        assert_eq!(
+            decompress(&zlib, BodyEncoding::Deflate, data.len()).unwrap(),
+            data.as_bytes()
+        );
+    }
+
+    #[test]
+    fn brotli_bodies_of_any_size() {
+        let sentence = "The quick brown fox jumps over the lazy dog. ";
+
+        for size in [10, 64 * 1024, 4 << 20] {
+            let data = sentence.repeat(size / sentence.len() + 1);
+            let data = &data.as_bytes()[..size];
+
+            // The encoder buffer stays the same however large the body
+            for buffer in [32 * 1024, 64 * 1024] {
+                let compressed =
+                    compress_buffered(data, BodyEncoding::Brotli, CompressionLevel::fast(), buffer)
+                        .unwrap();
+
+                assert_eq!(
+                    decompress(&compressed, BodyEncoding::Brotli, size).unwrap(),
+                    data,
+                    "{} bytes, {} bytes buffer",
+                    size,
+                    buffer
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gzip_bomb_stops_at_the_limit() {
+        let bomb = compress(
+            &vec![0; 1 << 20],
+            BodyEncoding::Gzip,
+            CompressionLevel::best(),
+        )
+        .unwrap();
+
+        assert!(bomb.len() <= 1200, "{} bytes", bomb.len());
+        assert!(matches!(
+            decompress(&bomb, BodyEncoding::Gzip, 64 * 1024),
+            Err(DecodeError::TooLarge(65536))
+        ));
+        // Exactly at the limit is fine
+        assert_eq!(
+            decompress(&bomb, BodyEncoding::Gzip, 1 << 20)
+                .unwrap()
+                .len(),
+            1 << 20
+        );
+    }
+
+    #[test]
+    fn invalid_and_layered_codings() {
+        let data = b"hello, hello, hello";
+
+        for encoding in ENCODINGS {
+            let compressed = compress(data, encoding, CompressionLevel::best()).unwrap();
+
+            assert!(matches!(
+                decompress(&compressed[..compressed.len() / 2], encoding, 1024),
+                Err(DecodeError::Invalid(..))
+            ));
+        }
+
+        // Applied gzip then br, undone in reverse
+        let gzip = compress(data, BodyEncoding::Gzip, CompressionLevel::fast()).unwrap();
+        let both = compress(&gzip, BodyEncoding::Brotli, CompressionLevel::fast()).unwrap();
+        let codings = ["gzip".to_string(), " br".to_string()];
+
+        assert_eq!(decode_content(&both, &codings, 1024).unwrap(), data);
+        assert!(matches!(
+            decode_content(data, &["zstd".to_string()], 1024),
+            Err(DecodeError::Unsupported(coding)) if coding == "zstd"
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::origin;
+use std::net::IpAddr;
+
+/// The addresses of an `X-Forwarded-For` header, client first:
+/// `203.0.113.7, 10.0.0.2`. Entries that aren't an IP address are `None`.
+pub fn parse_x_forwarded_for(value: &str) -> Vec<Option<IpAddr>> {
+    value
+        .split(',')
+        .map(|node| parse_node(node.trim()))
+        .collect()
+}
+
+/// The `for` addresses of a `Forwarded` header (RFC 7239), client first:
+/// `for=203.0.113.7;proto=https, for="[2001:db8::1]:4711"`. Hidden (`_abc`)
+/// or `unknown` nodes are `None`.
+pub fn parse_forwarded(value: &str) -> Vec<Option<IpAddr>> {
+    forwarded_parameter(value, "for")
+        .into_iter()
+        .map(|node| node.and_then(parse_node))
+        .collect()
+}
+
+/// The `proto` schemes of a `Forwarded` header, client side first, lowercase.
+pub fn parse_forwarded_proto(value: &str) -> Vec<Option<String>> {
+    forwarded_parameter(value, "proto")
+        .into_iter()
+        .map(|proto| proto.map(str::to_ascii_lowercase))
+        .collect()
+}
+
+/// The schemes of an `X-Forwarded-Proto` header, `https` or `https, http`
+/// through several proxies, lowercase.
+pub fn parse_x_forwarded_proto(value: &str) -> Vec<Option<String>> {
+    value
+        .split(',')
+        .map(|proto| Some(proto.trim().to_ascii_lowercase()).filter(|proto| !proto.is_empty()))
+        .collect()
+}
+
+/// The value of the `name` parameter in each element of a `Forwarded` header.
+fn forwarded_parameter<'a>(value: &'a str, name: &str) -> Vec<Option<&'a str>> {
+    value
+        .split(',')
+        .map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+
+                key.trim()
+                    .eq_ignore_ascii_case(name)
+                    .then(|| value.trim().trim_matches('"'))
+            })
+        })
+        .collect()
+}
+
+/// `192.0.2.1`, `192.0.2.1:8080`, `[2001:db8::1]:8080` or `2001:db8::1`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+
+    let (host, _) = origin::split_host_port(node)?;
+
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn multi_hop_x_forwarded_for() {
+        let client = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let proxy = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let ipv6 = IpAddr::V6("2001:db8::1".parse::<Ipv6Addr>().unwrap());
+
+        assert_eq!(
+            parse_x_forwarded_for("203.0.113.7, 10.0.0.2"),
+            [Some(client), Some(proxy)]
+        );
+        assert_eq!(
+            parse_x_forwarded_for("203.0.113.7:51234,unknown , [2001:db8::1]:8080, 2001:db8::1"),
+            [Some(client), None, Some(ipv6), Some(ipv6)]
+        );
+        assert_eq!(parse_x_forwarded_for(""), [None]);
+    }
+
+    #[test]
+    fn forwarded_nodes() {
+        let value =
+            r#"for=203.0.113.7;proto=https, for="[2001:db8::1]:4711", For=_hidden;proto=HTTP"#;
+
+        assert_eq!(
+            parse_forwarded(value),
+            [
+                Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))),
+                Some("2001:db8::1".parse().unwrap()),
+                None
+            ]
+        );
+        assert_eq!(
+            parse_forwarded_proto(value),
+            [Some("https".to_string()), None, Some("http".to_string())]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::common::Header;
+use crate::origin;
+use crate::request::Request;
+use crate::response::Response;
+
+/// How the server rewrites the host a request was sent to before handing it
+/// over, see `ServerOptions::host_canonicalization`: `www.example.com` to
+/// `example.com`, `example.com.` to `example.com`.
+#[derive(Debug, Clone, Default)]
+pub struct HostCanonicalization {
+    /// Hosts and the canonical host each stands for, compared without port and
+    /// case-insensitively.
+    pub aliases: Vec<(String, String)>,
+    /// Drop the trailing dot of a fully qualified `example.com.`.
+    pub strip_trailing_dot: bool,
+    /// Answer `301 Moved Permanently` to the canonical host, with the same path
+    /// and query. Otherwise the request goes on with its `Host` header and
+    /// `uri.host` rewritten.
+    pub redirect: bool,
+}
+
+impl HostCanonicalization {
+    pub fn new() -> HostCanonicalization {
+        HostCanonicalization::default()
+    }
+
+    /// Send requests for `alias` to `canonical`.
+    pub fn alias(mut self, alias: &str, canonical: &str) -> HostCanonicalization {
+        self.aliases
+            .push((alias.to_ascii_lowercase(), canonical.to_ascii_lowercase()));
+
+        self
+    }
+
+    pub fn strip_trailing_dot(mut self, strip: bool) -> HostCanonicalization {
+        self.strip_trailing_dot = strip;
+
+        self
+    }
+
+    pub fn redirect(mut self, redirect: bool) -> HostCanonicalization {
+        self.redirect = redirect;
+
+        self
+    }
+
+    /// The canonical form of `authority` (`host[:port]`), its port kept, `None`
+    /// when it already is.
+    pub fn canonical(&self, authority: &str) -> Option<String> {
+        let (host, port) = origin::split_host_port(authority)?;
+        let stripped = match host.strip_suffix('.') {
+            Some(stripped) if self.strip_trailing_dot && !stripped.is_empty() => stripped,
+            _ => host.as_str(),
+        };
+        let canonical = self
+            .aliases
+            .iter()
+            .find(|(alias, _)| alias.trim_end_matches('.') == stripped)
+            .map_or(stripped, |(_, canonical)| canonical.as_str());
+
+        if canonical == host {
+            return None;
+        }
+
+        Some(match port {
+            Some(port) => format!("{}:{}", canonical, port),
+            None => canonical.to_string(),
+        })
+    }
+
+    /// Rewrite the host of `request`, or the redirect to answer it with when
+    /// `redirect` is set and its host isn't canonical.
+    pub fn apply(&self, request: &mut Request) -> Option<Response> {
+        let host = match request.get_header("Host") {
+            Some(Header::Host(host)) => host.clone(),
+            _ => request.uri.host.clone(),
+        };
+        let canonical = self.canonical(&host)?;
+
+        if self.redirect {
+            return Some(Response::host_redirect(request, &canonical));
+        }
+
+        for header in request.headers.iter_mut() {
+            if let Header::Host(host) = header {
+                *host = canonical.clone();
+            }
+        }
+
+        if let Some(host) = self.canonical(&request.uri.host) {
+            request.uri.host = host;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_hosts() {
+        let canonicalization = HostCanonicalization::new()
+            .alias("www.example.com", "example.com")
+            .strip_trailing_dot(true);
+
+        assert_eq!(
+            canonicalization.canonical("www.example.com").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            canonicalization
+                .canonical("www.example.com.:8080")
+                .as_deref(),
+            Some("example.com:8080")
+        );
+        assert_eq!(
+            canonicalization.canonical("example.com.").as_deref(),
+            Some("example.com")
+        );
+
+        // Already canonical, nothing to redirect to
+        for authority in ["example.com", "example.com:8080", "other.example"] {
+            assert_eq!(canonicalization.canonical(authority), None, "{}", authority);
+        }
+
+        let keep_dots = HostCanonicalization::new().strip_trailing_dot(false);
+
+        assert_eq!(keep_dots.canonical("example.com."), None);
+    }
+}
@@ -0,0 +1,675 @@
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+
+/// Deepest nesting of arrays and objects `Value::parse` accepts.
+pub const MAX_DEPTH: usize = 128;
+
+/// A JSON document, for the little JSON a server reads and writes without serde.
+///
+/// Objects keep their members in order, duplicates included.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// Integers are kept exact, other numbers are `f64`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    /// Integers above `i64::MAX`.
+    UInt(u64),
+    Float(f64),
+}
+
+impl Value {
+    /// An empty object, to fill with `insert`.
+    pub fn object() -> Value {
+        Value::Object(vec![])
+    }
+
+    /// An empty array, to fill with `push`.
+    pub fn array() -> Value {
+        Value::Array(vec![])
+    }
+
+    /// Add the member `key`, replacing any member with that name. Does nothing
+    /// unless this is an object.
+    pub fn insert(mut self, key: &str, value: impl Into<Value>) -> Value {
+        if let Value::Object(ref mut members) = self {
+            let value = value.into();
+
+            match members.iter_mut().find(|(name, _)| name == key) {
+                Some((_, member)) => *member = value,
+                None => members.push((key.to_string(), value)),
+            }
+        }
+
+        self
+    }
+
+    /// Add an element at the end. Does nothing unless this is an array.
+    pub fn push(mut self, value: impl Into<Value>) -> Value {
+        if let Value::Array(ref mut elements) = self {
+            elements.push(value.into());
+        }
+
+        self
+    }
+
+    /// The first member named `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The element at `index` of an array.
+    pub fn at(&self, index: usize) -> Option<&Value> {
+        match self {
+            Value::Array(elements) => elements.get(index),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The number if it is an integer that fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Number(Number::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Any number, integers above 2^53 losing precision.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(Number::Int(value)) => Some(*value as f64),
+            Value::Number(Number::UInt(value)) => Some(*value as f64),
+            Value::Number(Number::Float(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    /// Parse a JSON document, nested at most `MAX_DEPTH` deep.
+    pub fn parse(text: &str) -> Result<Value, JsonError> {
+        Value::parse_with_depth(text, MAX_DEPTH)
+    }
+
+    /// Parse a JSON document, nested at most `max_depth` deep.
+    pub fn parse_with_depth(text: &str, max_depth: usize) -> Result<Value, JsonError> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            index: 0,
+            max_depth,
+        };
+
+        parser.skip_whitespace();
+
+        let value = parser.value(0)?;
+
+        parser.skip_whitespace();
+
+        if parser.index < parser.bytes.len() {
+            return Err(parser.error("Unexpected data after the document"));
+        }
+
+        Ok(value)
+    }
+}
+
+/// Compact JSON, numbers that are not finite written as `null`.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(value) => write_string(f, value),
+            Value::Array(elements) => {
+                f.write_char('[')?;
+
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    write!(f, "{}", element)?;
+                }
+
+                f.write_char(']')
+            }
+            Value::Object(members) => {
+                f.write_char('{')?;
+
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Number::Int(value) => write!(f, "{}", value),
+            Number::UInt(value) => write!(f, "{}", value),
+            // `Debug` keeps `.0` on integral values and switches to exponents for
+            // very large or small ones, both valid JSON
+            Number::Float(value) if value.is_finite() => write!(f, "{:?}", value),
+            Number::Float(_) => write!(f, "null"),
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, text: &str) -> FmtResult {
+    f.write_char('"')?;
+
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\u{c}' => f.write_str("\\f")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+
+    f.write_char('"')
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Number(Number::Int(value as i64))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Number(Number::Int(value))
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        match i64::try_from(value) {
+            Ok(value) => Value::Number(Number::Int(value)),
+            Err(_) => Value::Number(Number::UInt(value)),
+        }
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::from(value as u64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(Number::Float(value))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Why a document could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    /// Byte offset in the document.
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} at byte {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    index: usize,
+    max_depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError {
+            position: self.index,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.index).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.index += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str, value: Value) -> Result<Value, JsonError> {
+        if !self.bytes[self.index..].starts_with(literal.as_bytes()) {
+            return Err(self.error("Invalid literal"));
+        }
+
+        self.index += literal.len();
+
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, JsonError> {
+        match self.peek() {
+            Some(b'n') => self.expect("null", Value::Null),
+            Some(b't') => self.expect("true", Value::Bool(true)),
+            Some(b'f') => self.expect("false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b'[' | b'{') if depth >= self.max_depth => {
+                Err(self.error("Document nested too deep"))
+            }
+            Some(b'[') => self.array(depth + 1),
+            Some(b'{') => self.object(depth + 1),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of the document")),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, JsonError> {
+        let mut elements = vec![];
+
+        self.index += 1;
+        self.skip_whitespace();
+
+        if self.peek() == Some(b']') {
+            self.index += 1;
+
+            return Ok(Value::Array(elements));
+        }
+
+        loop {
+            self.skip_whitespace();
+            elements.push(self.value(depth)?);
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(b',') => self.index += 1,
+                Some(b']') => {
+                    self.index += 1;
+
+                    return Ok(Value::Array(elements));
+                }
+                _ => return Err(self.error("Expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, JsonError> {
+        let mut members = vec![];
+
+        self.index += 1;
+        self.skip_whitespace();
+
+        if self.peek() == Some(b'}') {
+            self.index += 1;
+
+            return Ok(Value::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+
+            if self.peek() != Some(b'"') {
+                return Err(self.error("Expected a member name"));
+            }
+
+            let name = self.string()?;
+
+            self.skip_whitespace();
+
+            if self.peek() != Some(b':') {
+                return Err(self.error("Expected `:`"));
+            }
+
+            self.index += 1;
+            self.skip_whitespace();
+            members.push((name, self.value(depth)?));
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(b',') => self.index += 1,
+                Some(b'}') => {
+                    self.index += 1;
+
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("Expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        let mut value = String::new();
+
+        self.index += 1;
+
+        loop {
+            // Copy everything up to the next quote, escape or control character at once
+            let start = self.index;
+
+            while matches!(self.peek(), Some(byte) if byte != b'"' && byte != b'\\' && byte >= 0x20)
+            {
+                self.index += 1;
+            }
+
+            // The input is a `&str` and the run stops on ASCII, so this is whole characters
+            value.push_str(std::str::from_utf8(&self.bytes[start..self.index]).unwrap_or_default());
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.index += 1;
+
+                    return Ok(value);
+                }
+                Some(b'\\') => {
+                    self.index += 1;
+                    value.push(self.escape()?);
+                }
+                Some(_) => return Err(self.error("Control character in a string")),
+                None => return Err(self.error("Unclosed string")),
+            }
+        }
+    }
+
+    /// The character of the escape sequence after a `\`.
+    fn escape(&mut self) -> Result<char, JsonError> {
+        let escaped = self.peek().ok_or_else(|| self.error("Unclosed string"))?;
+
+        self.index += 1;
+
+        Ok(match escaped {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let unit = self.hex()?;
+
+                // Characters outside the BMP come as a surrogate pair, `😀`
+                let code = match unit {
+                    0xd800..=0xdbff => {
+                        if !self.bytes[self.index..].starts_with(b"\\u") {
+                            return Err(self.error("Unpaired surrogate"));
+                        }
+
+                        self.index += 2;
+
+                        let low = self.hex()?;
+
+                        if !(0xdc00..=0xdfff).contains(&low) {
+                            return Err(self.error("Unpaired surrogate"));
+                        }
+
+                        0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                    }
+                    0xdc00..=0xdfff => return Err(self.error("Unpaired surrogate")),
+                    unit => unit,
+                };
+
+                char::from_u32(code).ok_or_else(|| self.error("Invalid escape"))?
+            }
+            _ => return Err(self.error("Invalid escape")),
+        })
+    }
+
+    /// The four hexadecimal digits of a `\u` escape.
+    fn hex(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .bytes
+            .get(self.index..self.index + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("Invalid \\u escape"))?;
+        let unit = u32::from_str_radix(digits, 16).map_err(|_| self.error("Invalid \\u escape"))?;
+
+        self.index += 4;
+
+        Ok(unit)
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.index;
+        let digits = |parser: &mut Parser| {
+            let start = parser.index;
+
+            while parser.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                parser.index += 1;
+            }
+
+            parser.index - start
+        };
+
+        if self.peek() == Some(b'-') {
+            self.index += 1;
+        }
+
+        // No leading zeros: `0`, `0.5`, but not `01`
+        match self.peek() {
+            Some(b'0') => self.index += 1,
+            Some(b'1'..=b'9') => {
+                digits(self);
+            }
+            _ => return Err(self.error("Invalid number")),
+        }
+
+        let mut integer = true;
+
+        if self.peek() == Some(b'.') {
+            self.index += 1;
+            integer = false;
+
+            if digits(self) == 0 {
+                return Err(self.error("Invalid number"));
+            }
+        }
+
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.index += 1;
+            integer = false;
+
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.index += 1;
+            }
+
+            if digits(self) == 0 {
+                return Err(self.error("Invalid number"));
+            }
+        }
+
+        // Only ASCII was consumed
+        let text = std::str::from_utf8(&self.bytes[start..self.index]).unwrap_or_default();
+
+        if integer {
+            if let Ok(value) = text.parse::<i64>() {
+                return Ok(Value::Number(Number::Int(value)));
+            }
+
+            if let Ok(value) = text.parse::<u64>() {
+                return Ok(Value::Number(Number::UInt(value)));
+            }
+        }
+
+        match text.parse::<f64>() {
+            Ok(value) => Ok(Value::Number(Number::Float(value))),
+            Err(_) => Err(self.error("Invalid number")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"name":"café","tags":["a","b"],"count":3,"ratio":0.5,"ok":true,"none":null,"name":"again"}"#;
+        let value = Value::parse(text).unwrap();
+
+        assert_eq!(value.get("name").and_then(Value::as_str), Some("café"));
+        assert_eq!(value.get("count").and_then(Value::as_i64), Some(3));
+        assert_eq!(value.as_object().unwrap().len(), 7);
+        assert_eq!(Value::parse(&value.to_string()).unwrap(), value);
+
+        let built = Value::object()
+            .insert(
+                "list",
+                Value::array().push(1).push("two").push(None::<bool>),
+            )
+            .insert("nested", Value::object().insert("x", 1.5));
+
+        assert_eq!(
+            built.to_string(),
+            r#"{"list":[1,"two",null],"nested":{"x":1.5}}"#
+        );
+        assert_eq!(Value::parse(&built.to_string()).unwrap(), built);
+    }
+
+    #[test]
+    fn string_escapes() {
+        let value = Value::parse(r#""\u0000 \"q\" \\ \n \t \/ 😀""#).unwrap();
+
+        assert_eq!(value.as_str(), Some("\0 \"q\" \\ \n \t / 😀"));
+        // Control characters escaped, anything else written as is
+        assert_eq!(value.to_string(), r#""\u0000 \"q\" \\ \n \t / 😀""#);
+        assert_eq!(Value::parse("\"😀\"").unwrap().as_str(), Some("😀"));
+
+        // A lone surrogate, an unknown escape, a raw control character
+        assert!(Value::parse(r#""\ud83d""#).is_err());
+        assert!(Value::parse(r#""\x41""#).is_err());
+        assert!(Value::parse("\"\u{1}\"").is_err());
+    }
+
+    #[test]
+    fn big_numbers() {
+        assert_eq!(
+            Value::parse("9223372036854775807").unwrap(),
+            Value::Number(Number::Int(i64::MAX))
+        );
+        assert_eq!(
+            Value::parse("18446744073709551615").unwrap(),
+            Value::Number(Number::UInt(u64::MAX))
+        );
+        assert_eq!(
+            Value::parse("18446744073709551615").unwrap().to_string(),
+            "18446744073709551615"
+        );
+        // Past u64, precision is lost but the number parses
+        assert_eq!(
+            Value::parse("-9223372036854775809").unwrap(),
+            Value::Number(Number::Float(-9223372036854775809.0))
+        );
+        assert_eq!(Value::parse("1e400").unwrap().to_string(), "null");
+        assert!(Value::parse("01").is_err());
+        assert!(Value::parse("1.").is_err());
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Value::parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(Value::parse(&nested(100_000)).is_err());
+
+        assert!(Value::parse_with_depth(r#"{"a":{"b":1}}"#, 2).is_ok());
+        assert!(Value::parse_with_depth(r#"{"a":{"b":[]}}"#, 2).is_err());
+    }
+}
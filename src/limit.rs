@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, Shutdown, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Open connections, in all and of each client, see `ServerOptions::max_connections`
+/// and `ServerOptions::max_connections_per_ip`.
+#[derive(Debug, Default)]
+pub struct ConnectionCounts {
+    total: AtomicUsize,
+    counts: Mutex<HashMap<IpAddr, usize>>,
+    /// Connections `close_all` can reach, by slot.
+    streams: Mutex<HashMap<u64, TcpStream>>,
+    next_id: AtomicU64,
+}
+
+/// Which limit refused a connection in `ConnectionCounts::acquire`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitReached {
+    /// The server has `max_connections` open.
+    Server,
+    /// The client has `max_connections_per_ip` open.
+    Client,
+}
+
+impl ConnectionCounts {
+    /// Count one more connection, refused past `max_total` open ones, or past
+    /// `max` from the client when given `(ip, max)`.
+    pub fn acquire(
+        counts: &Arc<ConnectionCounts>,
+        max_total: Option<usize>,
+        client: Option<(IpAddr, usize)>,
+    ) -> Result<ConnectionSlot, LimitReached> {
+        let max_total = max_total.unwrap_or(usize::MAX);
+
+        counts
+            .total
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |total| {
+                (total < max_total).then_some(total + 1)
+            })
+            .map_err(|_| LimitReached::Server)?;
+
+        // From here, dropping the slot gives the connection back
+        let mut slot = ConnectionSlot {
+            counts: Arc::clone(counts),
+            ip: None,
+            stream: None,
+        };
+
+        if let Some((ip, max)) = client {
+            let mut map = counts.counts.lock().unwrap_or_else(|err| err.into_inner());
+            let count = map.entry(ip).or_insert(0);
+
+            if *count >= max {
+                return Err(LimitReached::Client);
+            }
+
+            *count += 1;
+            slot.ip = Some(ip);
+        }
+
+        Ok(slot)
+    }
+
+    /// Connections open on the server.
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Acquire)
+    }
+
+    /// Connections open under `ip`, a key of `client_key`.
+    pub fn get(&self, ip: IpAddr) -> usize {
+        let map = self.counts.lock().unwrap_or_else(|err| err.into_inner());
+
+        map.get(&ip).copied().unwrap_or(0)
+    }
+
+    /// Shut down every connection given to `ConnectionSlot::track`, whatever
+    /// it is doing: reads and writes on it fail from then on.
+    pub fn close_all(&self) {
+        let streams = self.streams.lock().unwrap_or_else(|err| err.into_inner());
+
+        for stream in streams.values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// A connection counted in `ConnectionCounts`, until dropped along with it.
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    counts: Arc<ConnectionCounts>,
+    /// The key the connection is counted under for its client, if limited.
+    pub ip: Option<IpAddr>,
+    /// The key of the connection in `ConnectionCounts::streams`, if tracked.
+    stream: Option<u64>,
+}
+
+impl ConnectionSlot {
+    /// Let `ConnectionCounts::close_all` shut `stream` down while it is open.
+    pub fn track(&mut self, stream: &TcpStream) {
+        let Ok(stream) = stream.try_clone() else {
+            return;
+        };
+        let id = self.counts.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut streams = self
+            .counts
+            .streams
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        streams.insert(id, stream);
+        self.stream = Some(id);
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        if let Some(id) = self.stream {
+            let mut streams = self
+                .counts
+                .streams
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+
+            streams.remove(&id);
+        }
+
+        if let Some(ip) = self.ip {
+            let mut map = self
+                .counts
+                .counts
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+
+            if let Some(count) = map.get_mut(&ip) {
+                *count -= 1;
+
+                if *count == 0 {
+                    map.remove(&ip);
+                }
+            }
+        }
+
+        self.counts.total.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// The key `ip` is counted under: IPv4 clients of an IPv6 socket as IPv4, and
+/// IPv6 clients by their /64 network with `per_subnet`, as a single host
+/// usually gets a whole one.
+pub fn client_key(ip: IpAddr, per_subnet: bool) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ip) if per_subnet => {
+            let network = u128::from(ip) & !(u64::MAX as u128);
+
+            IpAddr::V6(Ipv6Addr::from(network))
+        }
+        ip => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn slots_counted_until_dropped() {
+        let counts = Arc::new(ConnectionCounts::default());
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let acquire = |ip| ConnectionCounts::acquire(&counts, Some(3), Some((ip, 2)));
+
+        let first = acquire(ip).unwrap();
+        let second = acquire(ip).unwrap();
+
+        assert_eq!(acquire(ip).unwrap_err(), LimitReached::Client);
+        assert_eq!((counts.get(ip), counts.total()), (2, 2));
+
+        let third = acquire(other).unwrap();
+
+        assert_eq!(acquire(other).unwrap_err(), LimitReached::Server);
+
+        drop(first);
+
+        assert_eq!((counts.get(ip), counts.total()), (1, 2));
+        assert!(acquire(ip).is_ok());
+
+        drop((second, third));
+
+        assert_eq!(
+            (counts.get(ip), counts.get(other), counts.total()),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn client_keys() {
+        let ipv6: IpAddr = "2001:db8:1:2:aaaa:bbbb:cccc:dddd".parse().unwrap();
+
+        assert_eq!(
+            client_key(ipv6, true),
+            "2001:db8:1:2::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(client_key(ipv6, false), ipv6);
+        // An IPv4 client of an IPv6 socket
+        assert_eq!(
+            client_key("::ffff:192.0.2.7".parse().unwrap(), true),
+            "192.0.2.7".parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use crate::common::{Method, Status};
+use crate::json::Value;
+use crate::request::RequestTimings;
+use crate::response::CompressionStats;
+use chrono::{DateTime, Local};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::fs::OpenOptions;
+use std::io::{Error as IoError, LineWriter, Result as IoResult, Write};
+use std::net::SocketAddr;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A request the server answered, handed to `ServerOptions::logger`.
+#[derive(Debug, Clone)]
+pub struct LogEvent {
+    /// When the request was received.
+    pub time: DateTime<Local>,
+    pub peer: Option<SocketAddr>,
+    pub method: Method,
+    /// The URL of the request, see `ServerOptions::log_canonical`.
+    pub uri: String,
+    /// The request line as received, `GET /search?q=rust HTTP/1.1`.
+    pub request_line: String,
+    pub status: Option<Status>,
+    /// From receiving the request to sending the response (its head when streamed).
+    pub duration: Option<Duration>,
+    /// Receiving the request line and headers, see `RequestTimings::head`.
+    pub head_duration: Option<Duration>,
+    /// Size of the response body, `None` when streamed.
+    pub body_size: Option<usize>,
+    /// Bytes of the request as received, head and body.
+    pub bytes_received: usize,
+    /// Bytes of the response as sent, compressed, `None` when streamed.
+    pub bytes_sent: Option<usize>,
+    /// How the body was compressed, if it was.
+    pub compression: Option<CompressionStats>,
+    /// The `Referer` header of the request.
+    pub referer: Option<String>,
+    /// The `User-Agent` header of the request.
+    pub user_agent: Option<String>,
+}
+
+/// How `Logger::formatted` writes each event.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LogFormat {
+    /// Apache Common Log Format, see `LogEvent::common_log_format`.
+    #[default]
+    Common,
+    /// Apache Combined Log Format, see `LogEvent::combined_log_format`.
+    Combined,
+    /// One JSON object per line, see `LogEvent::to_json`.
+    Json,
+}
+
+impl LogEvent {
+    /// The line of an Apache access log in Common Log Format:
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "GET / HTTP/1.1" 200 2326`.
+    pub fn common_log_format(&self) -> String {
+        let dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+        format!(
+            "{} - - [{}] \"{}\" {} {}",
+            dash(self.peer.map(|peer| peer.ip().to_string())),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            escape(&self.request_line),
+            dash(self.status.as_ref().map(|status| status.code().to_string())),
+            dash(self.body_size.map(|size| size.to_string()))
+        )
+    }
+
+    /// The Common Log Format line followed by the quoted `Referer` and `User-Agent`:
+    /// `... 200 2326 "http://example.com/" "Mozilla/5.0"`.
+    pub fn combined_log_format(&self) -> String {
+        let quote = |value: &Option<String>| match value {
+            Some(value) => format!("\"{}\"", escape(value)),
+            None => "\"-\"".to_string(),
+        };
+
+        format!(
+            "{} {} {}",
+            self.common_log_format(),
+            quote(&self.referer),
+            quote(&self.user_agent)
+        )
+    }
+
+    /// The event as a JSON object, missing fields as `null`, the duration in milliseconds.
+    pub fn to_json(&self) -> Value {
+        Value::object()
+            .insert("time", self.time.to_rfc3339())
+            .insert("peer", self.peer.map(|peer| peer.ip().to_string()))
+            .insert("method", self.method.to_string())
+            .insert("uri", self.uri.as_str())
+            .insert("request_line", self.request_line.as_str())
+            .insert(
+                "status",
+                self.status.as_ref().map(|status| status.code() as u64),
+            )
+            .insert(
+                "duration_ms",
+                self.duration
+                    .map(|duration| duration.as_secs_f64() * 1000.0),
+            )
+            .insert(
+                "head_ms",
+                self.head_duration
+                    .map(|duration| duration.as_secs_f64() * 1000.0),
+            )
+            .insert("body_size", self.body_size)
+            .insert("bytes_received", self.bytes_received)
+            .insert("bytes_sent", self.bytes_sent)
+            .insert(
+                "compression",
+                self.compression.map(|stats| stats.header_value()),
+            )
+            .insert("referer", self.referer.clone())
+            .insert("user_agent", self.user_agent.clone())
+    }
+
+    pub fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Common => self.common_log_format(),
+            LogFormat::Combined => self.combined_log_format(),
+            LogFormat::Json => self.to_json().to_string(),
+        }
+    }
+}
+
+/// `value` for a quoted field of an Apache log: quotes and backslashes escaped
+/// with a backslash, control characters as `\xhh`.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// `[<time>] GET http://localhost/ 127.0.0.1:50000 200`
+impl Display for LogEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "[{:?}] {} {}",
+            self.time,
+            self.method.to_string(),
+            self.uri
+        )?;
+
+        if let Some(peer) = self.peer {
+            write!(f, " {}", peer)?;
+        }
+
+        if let Some(ref status) = self.status {
+            write!(f, " {}", status.code())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A request that took longer than `ServerOptions::slow_request_threshold` to
+/// receive, handed to `Logger::with_slow_requests` before the application gets it.
+#[derive(Debug, Clone)]
+pub struct SlowRequest {
+    pub peer: Option<SocketAddr>,
+    pub method: Method,
+    pub uri: String,
+    pub timings: RequestTimings,
+}
+
+impl SlowRequest {
+    /// The warning as a JSON object, each phase in milliseconds.
+    pub fn to_json(&self) -> Value {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+        Value::object()
+            .insert("warning", "slow_request")
+            .insert("peer", self.peer.map(|peer| peer.to_string()))
+            .insert("method", self.method.to_string())
+            .insert("uri", self.uri.as_str())
+            .insert("wait_ms", ms(self.timings.wait()))
+            .insert("head_ms", ms(self.timings.head()))
+            .insert("body_ms", ms(self.timings.body()))
+            .insert("receive_ms", ms(self.timings.receive()))
+    }
+}
+
+/// `Slow request from 127.0.0.1:50000: GET http://localhost/ (wait 2 ms, head 612 ms, body 0 ms)`
+impl Display for SlowRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Slow request")?;
+
+        if let Some(peer) = self.peer {
+            write!(f, " from {}", peer)?;
+        }
+
+        write!(
+            f,
+            ": {} {} (wait {} ms, head {} ms, body {} ms)",
+            self.method.to_string(),
+            self.uri,
+            self.timings.wait().as_millis(),
+            self.timings.head().as_millis(),
+            self.timings.body().as_millis()
+        )
+    }
+}
+
+type ErrorLog = Arc<dyn Fn(&IoError) + Send + Sync>;
+type SlowRequestLog = Arc<dyn Fn(&SlowRequest) + Send + Sync>;
+
+/// Where the server sends a `LogEvent` for each answered request, the errors
+/// it handles without returning them, and slow requests.
+#[derive(Clone)]
+pub struct Logger {
+    log: Arc<dyn Fn(&LogEvent) + Send + Sync>,
+    errors: Option<ErrorLog>,
+    slow_requests: Option<SlowRequestLog>,
+}
+
+impl Logger {
+    pub fn new(log: impl Fn(&LogEvent) + Send + Sync + 'static) -> Logger {
+        Logger {
+            log: Arc::new(log),
+            errors: None,
+            slow_requests: None,
+        }
+    }
+
+    /// Also hand `errors` each error the server handles without returning it:
+    /// failed accepts it retries (an aborted connection, running out of file
+    /// descriptors, ...), TCP options it fails to set on a connection and requests
+    /// `Server::serve` fails to read or answer.
+    pub fn with_errors(mut self, errors: impl Fn(&IoError) + Send + Sync + 'static) -> Logger {
+        self.errors = Some(Arc::new(errors));
+
+        self
+    }
+
+    /// Also hand `slow_requests` each request slower to receive than
+    /// `ServerOptions::slow_request_threshold`.
+    pub fn with_slow_requests(
+        mut self,
+        slow_requests: impl Fn(&SlowRequest) + Send + Sync + 'static,
+    ) -> Logger {
+        self.slow_requests = Some(Arc::new(slow_requests));
+
+        self
+    }
+
+    /// Print each event, retried error and slow request on stdout.
+    pub fn stdout() -> Logger {
+        Logger::new(|event| println!("{}", event))
+            .with_errors(|err| println!("Error ({:?}): {}", err.kind(), err))
+            .with_slow_requests(|slow| println!("{}", slow))
+    }
+
+    /// Write each event to `writer` in Common Log Format, one per line.
+    pub fn common_log_format(writer: impl Write + Send + 'static) -> Logger {
+        Logger::formatted(LogFormat::Common, writer)
+    }
+
+    /// Write each event to `writer` in `format`, one per line.
+    pub fn formatted(format: LogFormat, writer: impl Write + Send + 'static) -> Logger {
+        let writer = Mutex::new(writer);
+
+        Logger::new(move |event| {
+            let mut writer = writer.lock().unwrap_or_else(|err| err.into_inner());
+
+            let _ = writeln!(writer, "{}", event.format(format));
+        })
+    }
+
+    /// Append each event to the file at `path` in `format`, written line by line.
+    pub fn file<P: AsRef<Path>>(path: P, format: LogFormat) -> IoResult<Logger> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Logger::formatted(format, LineWriter::new(file)))
+    }
+
+    /// Hand `event` to the logger. A panic in the logger is caught, so that it
+    /// never takes the server down.
+    pub fn log(&self, event: &LogEvent) {
+        let _ = catch_unwind(AssertUnwindSafe(|| (self.log)(event)));
+    }
+
+    /// Hand an error the server handled to the logger, if it takes them.
+    pub fn log_error(&self, err: &IoError) {
+        if let Some(ref errors) = self.errors {
+            let _ = catch_unwind(AssertUnwindSafe(|| errors(err)));
+        }
+    }
+
+    /// Hand a slow request to the logger, if it takes them.
+    pub fn log_slow_request(&self, slow: &SlowRequest) {
+        if let Some(ref slow_requests) = self.slow_requests {
+            let _ = catch_unwind(AssertUnwindSafe(|| slow_requests(slow)));
+        }
+    }
+}
+
+impl Debug for Logger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Logger")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn event() -> LogEvent {
+        LogEvent {
+            time: Local.timestamp_opt(971211336, 0).unwrap(),
+            peer: Some(SocketAddr::from(([127, 0, 0, 1], 50000))),
+            method: Method::Get,
+            uri: "http://localhost/search?q=rust".to_string(),
+            request_line: "GET /search?q=rust HTTP/1.1".to_string(),
+            status: Some(Status::Ok),
+            duration: Some(Duration::from_millis(12)),
+            head_duration: None,
+            body_size: Some(2326),
+            bytes_received: 120,
+            bytes_sent: Some(2400),
+            compression: None,
+            referer: None,
+            user_agent: Some(r#"Mozilla/5.0 "quoted" \ agent"#.to_string()),
+        }
+    }
+
+    #[test]
+    fn access_log_formats() {
+        let event = event();
+        let time = event.time.format("%d/%b/%Y:%H:%M:%S %z");
+        let common = format!(
+            "127.0.0.1 - - [{}] \"GET /search?q=rust HTTP/1.1\" 200 2326",
+            time
+        );
+
+        assert_eq!(event.format(LogFormat::Common), common);
+        assert_eq!(
+            event.format(LogFormat::Combined),
+            format!(r#"{} "-" "Mozilla/5.0 \"quoted\" \\ agent""#, common)
+        );
+
+        let json = Value::parse(&event.format(LogFormat::Json)).unwrap();
+        let names: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        assert_eq!(
+            &names[..5],
+            ["time", "peer", "method", "uri", "request_line"]
+        );
+        assert_eq!(
+            json.get("user_agent").and_then(Value::as_str),
+            Some(r#"Mozilla/5.0 "quoted" \ agent"#)
+        );
+        assert!(json.get("referer").unwrap().is_null());
+        assert_eq!(json.get("status").and_then(Value::as_i64), Some(200));
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        let event = LogEvent {
+            request_line: "GET /\x1b[31m HTTP/1.1".to_string(),
+            ..event()
+        };
+
+        assert!(event
+            .format(LogFormat::Common)
+            .contains(r#""GET /\x1b[31m HTTP/1.1""#));
+    }
+}
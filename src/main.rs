@@ -1,83 +1,123 @@
 pub mod accept;
+pub mod auth;
+pub mod charset;
 pub mod common;
+pub mod config;
 pub mod cookie;
+pub mod date;
+pub mod digest;
+pub mod download;
+pub mod drain;
+pub mod encoding;
+pub mod forwarded;
+pub mod host;
+pub mod json;
+pub mod limit;
+pub mod log;
+pub mod maintenance;
 pub mod mime;
+pub mod origin;
+pub mod pagination;
+pub mod path;
+pub mod pool;
+pub mod problem;
+pub mod quota;
+pub mod range;
+pub mod record;
+pub mod redirect;
 pub mod request;
 pub mod response;
 pub mod search;
+#[cfg(feature = "serde")]
+pub mod serde_impls;
 pub mod server;
 
 use common::{Cache, Header, Status};
+use config::Config;
 use mime::Mime;
 use request::Request;
 use response::{BodyEncoding, Response, ResponseBody};
-use server::{Server, ServerOptions};
+use server::{PortFallback, Server, ServerOptions};
 use std::env::args;
-use std::io::Error as IoError;
-use std::thread::sleep;
-use std::time::Duration;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = args().collect();
+    let program = args().next().unwrap_or_default();
+    let mut args: Vec<String> = args().skip(1).collect();
+    let verbose = args.iter().any(|arg| arg == "--verbose");
+    let dev = args.iter().any(|arg| arg == "--dev");
+    // Serve on a port the system picks once the requested ones are in use
+    let any_port = args.iter().any(|arg| arg == "--any-port");
 
-    if args.len() != 2 {
-        println!("Usage: {} <port>", args[0]);
-        return Ok(());
-    }
+    args.retain(|arg| arg != "--verbose" && arg != "--dev" && arg != "--any-port");
 
-    let mut port: u16 = match args[1].parse() {
-        Ok(port) => port,
-        Err(_) => {
-            println!("Invalid port: {}", args[1]);
+    let config = match args.as_slice() {
+        // The config file picks its own profile
+        [flag, path] if flag == "--config" && !dev => match Config::from_file(path) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Invalid config file: {}", e);
+                return Ok(());
+            }
+        },
+        [port] if !port.starts_with("--") => match port.parse() {
+            Ok(port) => Config {
+                port,
+                options: match dev {
+                    true => ServerOptions::development(),
+                    false => ServerOptions::hardened().with_stdout_log(),
+                },
+                ..Config::default()
+            },
+            Err(_) => {
+                println!("Invalid port: {}", port);
+                return Ok(());
+            }
+        },
+        _ => {
+            println!(
+                "Usage: {} (<port> [--dev] | --config <file>) [--any-port] [--verbose]",
+                program
+            );
             return Ok(());
         }
     };
-    let mut tries = 1;
 
-    let server: Server = loop {
-        match Server::bind_v4(port, Some(ServerOptions { log: true })) {
-            Ok(listener) => break listener,
-            Err(e) => {
-                println!("Failed to bind to port {}: {:?} ({}/{})", port, e, tries, 5);
-                tries += 1;
-
-                if tries > 5 {
-                    port += 1;
-                    tries = 1;
-                    continue;
-                } else {
-                    sleep(Duration::from_secs(1));
-                }
-            }
+    let fallback = PortFallback {
+        ephemeral: any_port,
+        ..PortFallback::default()
+    };
+    let (server, port) = match Server::quickstart_with(config.port, Some(config.options), fallback)
+    {
+        Ok(bound) => bound,
+        Err(e) => {
+            println!("Failed to bind to port {}: {}", config.port, e);
+            return Ok(());
         }
     };
     println!("Listening on port {}", port);
 
-    for request in server.requests() {
-        if let None = request {
-            continue;
-        }
+    for warning in server.preflight() {
+        println!("{}", warning);
+    }
 
-        if let Some(mut request) = request {
-            if let Err(err) = handle_request(&mut request) {
-                println!("Error: {}", err);
-            }
-        }
+    if verbose {
+        print!("{}", server.info());
     }
 
+    server.serve(handle_request);
+
     return Ok(());
 }
 
-fn handle_request(request: &mut Request) -> Result<usize, IoError> {
+fn handle_request(request: &mut Request) -> Response {
     let mut response = Response::empty();
 
     response
         .set_status(Status::Ok)
         .add_header(Header::ContentType(Mime::text("plain")))
-        .add_header(Header::Server("JulMan-Http/1.0".to_string()))
         .add_header(Header::CacheControl(vec![Cache::NoStore]))
         .set_body(ResponseBody::Text(format!("{:#?}", request)))
         .set_body_encoding(Some(BodyEncoding::Brotli), None);
 
-    return request.respond(response);
+    response
 }
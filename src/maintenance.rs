@@ -0,0 +1,101 @@
+use crate::common::{Cache, Header, Status};
+use crate::date::RetryAfter;
+use crate::mime::Mime;
+use crate::request::Request;
+use crate::response::{Response, ResponseBody};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// What requests get while the server is in maintenance, see `Server::set_maintenance`.
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    pub retry_after: RetryAfter,
+    /// Shown to the client, as is or in an HTML page when it prefers HTML.
+    pub message: String,
+    /// Paths still handed to the application (`/healthz`), compared exactly.
+    pub allow_paths: Vec<String>,
+}
+
+impl MaintenanceConfig {
+    /// The `503 Service Unavailable` answered to `request`.
+    pub fn response(&self, request: &Request) -> Response {
+        let mut response = Response::empty();
+
+        response
+            .set_status(Status::ServiceUnavailable)
+            .add_header(Header::RetryAfter(self.retry_after))
+            .add_header(Header::CacheControl(vec![Cache::NoStore]))
+            .add_header(Header::Vary(vec!["Accept".to_string()]));
+
+        // HTML only when preferred over plain text, `*/*` gets plain text
+        let html = request
+            .accept()
+            .negotiate(&[Mime::text("plain"), Mime::text("html")])
+            .is_some_and(|mime| mime.subtype() == "html");
+
+        if html {
+            let message = escape_html(&self.message);
+
+            response
+                .set_body(ResponseBody::Text(format!(
+                    "<!DOCTYPE html>\n<html><head><title>Maintenance</title></head>\
+                     <body><h1>Maintenance</h1><p>{}</p></body></html>\n",
+                    message
+                )))
+                .set_content_type(Mime::text("html"));
+        } else {
+            response
+                .set_body(ResponseBody::Text(self.message.clone()))
+                .set_content_type(Mime::text("plain"));
+        }
+
+        response
+    }
+}
+
+/// The maintenance switch of a server. Clones share it, so one can be moved into
+/// an admin handler to turn maintenance on and off while the server runs.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    enabled: Arc<AtomicBool>,
+    config: Arc<Mutex<Option<MaintenanceConfig>>>,
+}
+
+impl Maintenance {
+    /// Turn maintenance on with `config`, or off with `None`. Requests already
+    /// handed to the application are not affected.
+    pub fn set(&self, config: Option<MaintenanceConfig>) {
+        let enabled = config.is_some();
+
+        *self.config.lock().unwrap_or_else(|err| err.into_inner()) = config;
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// The response to send instead of handing `request` to the application,
+    /// `None` outside maintenance or for an allowed path.
+    pub fn check(&self, request: &Request) -> Option<Response> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let config = self.config.lock().unwrap_or_else(|err| err.into_inner());
+        let config = config.as_ref()?;
+
+        if config.allow_paths.contains(&request.uri.path) {
+            return None;
+        }
+
+        Some(config.response(request))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,3 +1,8 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::Path;
+
 #[derive(Debug, Clone)]
 pub enum Mime {
     Custom(String, String, Option<(String, String)>),
@@ -74,9 +79,12 @@ impl Mime {
                     Some(param) => param.to_string(),
                     None => return Err("Invalid MIME type".to_string()),
                 };
-                let mut param_split = param.split("=");
-                let key = param_split.next().unwrap().to_string();
-                let value = param_split.next().unwrap().to_string();
+                let mut param_split = param.splitn(2, '=');
+                let key = param_split.next().unwrap().trim().to_lowercase();
+                let value = match param_split.next() {
+                    Some(value) => value.trim().trim_matches('"').to_string(),
+                    None => return Err("Invalid MIME type".to_string()),
+                };
 
                 parameters = (Some(key), Some(value));
             }
@@ -92,6 +100,68 @@ impl Mime {
         Ok(Mime::new(type_, subtype, parameters))
     }
 
+    pub fn parameter(&self) -> Option<&(String, String)> {
+        match self {
+            Mime::Custom(_, _, parameters) => parameters.as_ref(),
+            Mime::Text(_, parameters)
+            | Mime::Application(_, parameters)
+            | Mime::Audio(_, parameters)
+            | Mime::Image(_, parameters)
+            | Mime::Message(_, parameters)
+            | Mime::Model(_, parameters)
+            | Mime::Video(_, parameters) => parameters.as_ref(),
+        }
+    }
+
+    pub fn type_(&self) -> &str {
+        match self {
+            Mime::Custom(type_, _, _) => type_.as_str(),
+            Mime::Text(_, _) => "text",
+            Mime::Application(_, _) => "application",
+            Mime::Audio(_, _) => "audio",
+            Mime::Image(_, _) => "image",
+            Mime::Message(_, _) => "message",
+            Mime::Model(_, _) => "model",
+            Mime::Video(_, _) => "video",
+        }
+    }
+
+    pub fn subtype(&self) -> &str {
+        match self {
+            Mime::Custom(_, subtype, _)
+            | Mime::Text(subtype, _)
+            | Mime::Application(subtype, _)
+            | Mime::Audio(subtype, _)
+            | Mime::Image(subtype, _)
+            | Mime::Message(subtype, _)
+            | Mime::Model(subtype, _)
+            | Mime::Video(subtype, _) => subtype.as_str(),
+        }
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        match self.parameter() {
+            Some((key, value)) if key.eq_ignore_ascii_case("charset") => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The same type with a `charset` parameter, in place of any other one.
+    pub fn with_charset(self, charset: &str) -> Mime {
+        let parameter = Some(("charset".to_string(), charset.to_string()));
+
+        match self {
+            Mime::Custom(type_, subtype, _) => Mime::Custom(type_, subtype, parameter),
+            Mime::Text(subtype, _) => Mime::Text(subtype, parameter),
+            Mime::Application(subtype, _) => Mime::Application(subtype, parameter),
+            Mime::Audio(subtype, _) => Mime::Audio(subtype, parameter),
+            Mime::Image(subtype, _) => Mime::Image(subtype, parameter),
+            Mime::Message(subtype, _) => Mime::Message(subtype, parameter),
+            Mime::Model(subtype, _) => Mime::Model(subtype, parameter),
+            Mime::Video(subtype, _) => Mime::Video(subtype, parameter),
+        }
+    }
+
     pub fn custom(type_: &str, subtype: &str) -> Mime {
         Mime::Custom(String::from(type_), String::from(subtype), None)
     }
@@ -166,3 +236,112 @@ impl ToString for Mime {
         out
     }
 }
+
+/// Extension to MIME type associations on top of the built-in table of
+/// `Mime::from_extension`, e.g. loaded from `/etc/mime.types`.
+#[derive(Debug, Clone, Default)]
+pub struct MimeRegistry {
+    types: HashMap<String, Mime>,
+}
+
+impl MimeRegistry {
+    pub fn new() -> MimeRegistry {
+        MimeRegistry::default()
+    }
+
+    /// Load a file in the `mime.types` format.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> IoResult<MimeRegistry> {
+        Ok(MimeRegistry::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse the `mime.types` format: a type followed by its extensions on each
+    /// line, `#` starting a comment. Lines that do not start with a valid type
+    /// are skipped.
+    pub fn parse(raw: &str) -> MimeRegistry {
+        let mut registry = MimeRegistry::new();
+
+        for line in raw.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+
+            let mime = match fields.next() {
+                Some(type_) if type_.contains('/') => match Mime::parse(&type_.to_string()) {
+                    Ok(mime) => mime,
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+
+            for extension in fields {
+                registry.insert(extension, mime.clone());
+            }
+        }
+
+        registry
+    }
+
+    /// Associate `extension` (with or without its leading dot) to `mime`,
+    /// overriding the built-in table.
+    pub fn insert(&mut self, extension: &str, mime: Mime) -> &mut Self {
+        self.types
+            .insert(extension.trim_start_matches('.').to_lowercase(), mime);
+
+        self
+    }
+
+    pub fn lookup(&self, extension: &str) -> Option<Mime> {
+        let extension = extension.trim_start_matches('.').to_lowercase();
+
+        match self.types.get(&extension) {
+            Some(mime) => Some(mime.clone()),
+            None => Mime::from_extension(&extension, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = "\
+# MIME type         Extensions
+application/wasm    wasm
+image/webp          webp  WEBP
+
+video/mp4           mp4 m4v   # the built-in table says video/mp3
+not-a-type          foo
+text/markdown
+";
+
+    #[test]
+    fn mime_types_file() {
+        let path = std::env::temp_dir().join(format!("mime-{}.types", std::process::id()));
+
+        fs::write(&path, FIXTURE).unwrap();
+
+        let registry = MimeRegistry::from_file(&path);
+
+        fs::remove_file(&path).unwrap();
+
+        let mut registry = registry.unwrap();
+        let lookup = |registry: &MimeRegistry, extension| {
+            registry.lookup(extension).map(|mime| mime.to_string())
+        };
+
+        assert_eq!(
+            lookup(&registry, "wasm").as_deref(),
+            Some("application/wasm")
+        );
+        assert_eq!(lookup(&registry, ".WebP").as_deref(), Some("image/webp"));
+        assert_eq!(lookup(&registry, "m4v").as_deref(), Some("video/mp4"));
+        // Overridden, then from the built-in table
+        assert_eq!(lookup(&registry, "mp4").as_deref(), Some("video/mp4"));
+        assert_eq!(lookup(&registry, "html").as_deref(), Some("text/html"));
+        assert_eq!(lookup(&registry, "foo"), None);
+
+        registry.insert(".html", Mime::text("x-custom"));
+
+        assert_eq!(lookup(&registry, "html").as_deref(), Some("text/x-custom"));
+        assert!(MimeRegistry::from_file("/nonexistent/mime.types").is_err());
+    }
+}
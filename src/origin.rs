@@ -0,0 +1,266 @@
+use crate::common::{Header, Method, Status};
+use crate::mime::Mime;
+use crate::request::Request;
+use crate::response::{Response, ResponseBody};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The value of an `Origin` header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    /// `null`, sent by sandboxed documents, `file:` pages and after some cross-origin redirects.
+    Null,
+    Tuple {
+        /// Lowercase scheme, `https`
+        scheme: String,
+        /// Lowercase host, IPv6 addresses keep their brackets
+        host: String,
+        /// The port if written, see `port_or_default`
+        port: Option<u16>,
+    },
+}
+
+impl Origin {
+    /// Parse `scheme://host[:port]`, or `null`.
+    pub fn parse(raw: &str) -> Option<Origin> {
+        let raw = raw.trim();
+
+        if raw == "null" {
+            return Some(Origin::Null);
+        }
+
+        let (scheme, authority) = raw.split_once("://")?;
+
+        if scheme.is_empty() || authority.contains(['/', '?', '#', '@']) {
+            return None;
+        }
+
+        let (host, port) = split_host_port(authority)?;
+
+        Some(Origin::Tuple {
+            scheme: scheme.to_lowercase(),
+            host,
+            port,
+        })
+    }
+
+    /// The port of the origin, the default one of its scheme when not written.
+    pub fn port_or_default(&self) -> Option<u16> {
+        match self {
+            Origin::Null => None,
+            Origin::Tuple { scheme, port, .. } => port.or(default_port(scheme)),
+        }
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Origin::Null => write!(f, "null"),
+            Origin::Tuple {
+                scheme,
+                host,
+                port: Some(port),
+            } => write!(f, "{}://{}:{}", scheme, host, port),
+            Origin::Tuple { scheme, host, .. } => write!(f, "{}://{}", scheme, host),
+        }
+    }
+}
+
+/// The port used when a URL of `scheme` doesn't write one.
+pub fn default_port(scheme: &str) -> Option<u16> {
+    match scheme.to_lowercase().as_str() {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        _ => None,
+    }
+}
+
+/// Whether `authority` is `host[:port]` with `host` a registered name, an IPv4
+/// address or a bracketed IPv6 one (RFC 3986 §3.2.2). Userinfo (`user@host`) is not.
+pub fn is_valid_authority(authority: &str) -> bool {
+    let Some((host, _)) = split_host_port(authority) else {
+        return false;
+    };
+
+    if host.starts_with('[') {
+        return is_ip(&host);
+    }
+
+    host.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=%".contains(c))
+}
+
+/// Whether `host`, without port, is an IPv4 address or a bracketed IPv6 one.
+pub fn is_ip(host: &str) -> bool {
+    match host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+    {
+        Some(ipv6) => ipv6.parse::<Ipv6Addr>().is_ok(),
+        None => host.parse::<Ipv4Addr>().is_ok(),
+    }
+}
+
+/// Split `host[:port]` (`[::1]:8080` for IPv6), lowercasing the host.
+pub fn split_host_port(authority: &str) -> Option<(String, Option<u16>)> {
+    let (host, port) = match authority.rfind(':') {
+        // The colon is part of an IPv6 address without a port
+        Some(index) if authority[index..].contains(']') => (authority, None),
+        Some(index) => (
+            &authority[..index],
+            Some(authority[(index + 1)..].parse().ok()?),
+        ),
+        None => (authority, None),
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    Some((host.to_lowercase(), port))
+}
+
+/// A `403 Forbidden` for requests that may change state (anything but `GET`,
+/// `HEAD` and `OPTIONS`) sent from another origin than the request `Host`,
+/// `None` when the request may go on.
+///
+/// Requests without an `Origin` header pass, as browsers always send one for
+/// cross-origin writes. The scheme expected is the one of `request.uri`, use
+/// `Request::is_same_origin` directly behind a TLS-terminating proxy.
+pub fn reject_cross_origin_writes(request: &Request) -> Option<Response> {
+    if matches!(request.method, Method::Get | Method::Head | Method::Options)
+        || request.get_header("Origin").is_none()
+        || request.is_same_origin(&request.uri.scheme)
+    {
+        return None;
+    }
+
+    let mut response = Response::empty();
+
+    response
+        .set_status(Status::Forbidden)
+        .set_body(ResponseBody::Text(
+            "Cross-origin request rejected".to_string(),
+        ))
+        .add_header(Header::ContentType(Mime::Text("plain".to_string(), None)));
+
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::handle_connection;
+    use crate::server::ServerOptions;
+    use std::io::Write;
+    use std::net::{Shutdown, TcpListener, TcpStream};
+
+    /// A request with `head` (request line and headers), read over a loopback connection.
+    fn request(head: &str) -> Request {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        write!(client, "{}\r\n\r\n", head).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        handle_connection(listener.accept().unwrap().0, &ServerOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn origins() {
+        let tuple = |scheme: &str, host: &str, port| Origin::Tuple {
+            scheme: scheme.to_string(),
+            host: host.to_string(),
+            port,
+        };
+
+        assert_eq!(Origin::parse("null"), Some(Origin::Null));
+        assert_eq!(
+            Origin::parse("HTTPS://Example.com"),
+            Some(tuple("https", "example.com", None))
+        );
+        assert_eq!(
+            Origin::parse("http://[::1]:8080"),
+            Some(tuple("http", "[::1]", Some(8080)))
+        );
+
+        for raw in [
+            "example.com",
+            "https://",
+            "https://a.com/path",
+            "https://u@a.com",
+            "http://a.com:x",
+        ] {
+            assert_eq!(Origin::parse(raw), None, "{}", raw);
+        }
+
+        assert_eq!(
+            Origin::parse("https://a.com").unwrap().port_or_default(),
+            Some(443)
+        );
+        assert_eq!(
+            Origin::parse("http://a.com:8080").unwrap().to_string(),
+            "http://a.com:8080"
+        );
+    }
+
+    #[test]
+    fn same_origin_checks() {
+        let cases = [
+            ("Host: example.com\r\nOrigin: http://example.com", true),
+            ("Host: example.com\r\nOrigin: http://example.com:80", true),
+            ("Host: example.com:80\r\nOrigin: http://example.com", true),
+            (
+                "Host: example.com:8080\r\nOrigin: http://example.com:8080",
+                true,
+            ),
+            ("Host: Example.com\r\nOrigin: http://EXAMPLE.com", true),
+            (
+                "Host: example.com:8080\r\nOrigin: http://example.com",
+                false,
+            ),
+            ("Host: example.com\r\nOrigin: https://example.com", false),
+            ("Host: example.com\r\nOrigin: http://evil.com", false),
+            ("Host: example.com\r\nOrigin: null", false),
+            ("Host: example.com", false),
+        ];
+
+        for (headers, same) in cases {
+            let request = request(&format!("POST / HTTP/1.1\r\n{}", headers));
+
+            assert_eq!(request.is_same_origin("http"), same, "{}", headers);
+        }
+
+        // Behind a TLS-terminating proxy
+        let request =
+            request("POST / HTTP/1.1\r\nHost: example.com\r\nOrigin: https://example.com");
+
+        assert!(request.is_same_origin("https"));
+    }
+
+    #[test]
+    fn cross_origin_writes_rejected() {
+        let rejected =
+            |head: &str| reject_cross_origin_writes(&request(head)).map(|r| r.status.code());
+
+        assert_eq!(
+            rejected("POST / HTTP/1.1\r\nHost: example.com\r\nOrigin: http://evil.com"),
+            Some(403)
+        );
+        assert_eq!(
+            rejected("DELETE / HTTP/1.1\r\nHost: example.com\r\nOrigin: null"),
+            Some(403)
+        );
+        assert_eq!(
+            rejected("POST / HTTP/1.1\r\nHost: example.com\r\nOrigin: http://example.com"),
+            None
+        );
+        // Without an Origin, and for safe methods
+        assert_eq!(rejected("POST / HTTP/1.1\r\nHost: example.com"), None);
+        assert_eq!(
+            rejected("GET / HTTP/1.1\r\nHost: example.com\r\nOrigin: http://evil.com"),
+            None
+        );
+    }
+}
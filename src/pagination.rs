@@ -0,0 +1,235 @@
+use crate::common::{Header, Uri};
+use crate::search::SearchParams;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The query parameter holding the page number, from 1.
+pub const PAGE_PARAM: &str = "page";
+/// The query parameter holding the number of items per page.
+pub const PER_PAGE_PARAM: &str = "per_page";
+
+/// A page of a paginated collection, read from `?page=3&per_page=20`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pagination {
+    /// The page number, from 1.
+    pub page: u64,
+    pub per_page: u64,
+    /// Items in the whole collection, if known.
+    pub total: Option<u64>,
+}
+
+/// Why `Pagination::from_query` refused a query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaginationError {
+    /// `page` is not a number from 1, which is given.
+    InvalidPage(String),
+    /// `per_page` is not a number from 1, which is given.
+    InvalidPerPage(String),
+}
+
+impl Display for PaginationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            PaginationError::InvalidPage(page) => write!(f, "Invalid page: {:?}", page),
+            PaginationError::InvalidPerPage(per_page) => {
+                write!(f, "Invalid number of items per page: {:?}", per_page)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaginationError {}
+
+/// One `<target>; rel="next"` of a `Link` header (RFC 8288).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub target: String,
+    pub rel: String,
+}
+
+impl Display for Link {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "<{}>; rel=\"{}\"", self.target, self.rel)
+    }
+}
+
+impl Pagination {
+    /// The page asked for by `query`, page 1 and `default_per_page` items when
+    /// missing, `per_page` lowered to `max_per_page` when larger.
+    pub fn from_query(
+        query: &SearchParams,
+        default_per_page: u64,
+        max_per_page: u64,
+    ) -> Result<Pagination, PaginationError> {
+        let number = |name: &str| {
+            query
+                .get(name)
+                .and_then(|values| values.first())
+                .map(|value| (value.parse::<u64>().ok().filter(|n| *n > 0), value))
+        };
+
+        let page = match number(PAGE_PARAM) {
+            Some((Some(page), _)) => page,
+            Some((None, raw)) => return Err(PaginationError::InvalidPage(raw.clone())),
+            None => 1,
+        };
+        let per_page = match number(PER_PAGE_PARAM) {
+            Some((Some(per_page), _)) => per_page,
+            Some((None, raw)) => return Err(PaginationError::InvalidPerPage(raw.clone())),
+            None => default_per_page,
+        };
+
+        Ok(Pagination {
+            page,
+            per_page: per_page.clamp(1, max_per_page.max(1)),
+            total: None,
+        })
+    }
+
+    pub fn with_total(mut self, total: u64) -> Pagination {
+        self.total = Some(total);
+
+        self
+    }
+
+    /// Items before the page, to skip in the collection.
+    pub fn offset(&self) -> u64 {
+        self.page.saturating_sub(1).saturating_mul(self.per_page)
+    }
+
+    /// The number of the last page, 1 for an empty collection, `None` when
+    /// `total` is unknown.
+    pub fn last_page(&self) -> Option<u64> {
+        self.total
+            .map(|total| total.div_ceil(self.per_page.max(1)).max(1))
+    }
+
+    /// The `first`, `prev`, `next` and `last` links for `base`, each with its
+    /// `page` parameter substituted, and `per_page` once clamped. `prev` is left
+    /// out on the first page, `next` on the last one, `next` and `last` when
+    /// `total` is unknown.
+    pub fn links(&self, base: &Uri) -> Vec<Link> {
+        let link = |page: u64, rel: &str| {
+            let mut uri = base.clone();
+
+            uri.search.set(PAGE_PARAM, &page.to_string());
+
+            if uri.search.has(PER_PAGE_PARAM) {
+                uri.search.set(PER_PAGE_PARAM, &self.per_page.to_string());
+            }
+
+            Link {
+                target: uri.to_string(),
+                rel: rel.to_string(),
+            }
+        };
+        let last = self.last_page();
+        let mut links = vec![link(1, "first")];
+
+        if self.page > 1 {
+            // Past the end, the previous page is the last one
+            let prev = last.map_or(self.page - 1, |last| last.min(self.page - 1));
+
+            links.push(link(prev, "prev"));
+        }
+
+        if let Some(last) = last {
+            if self.page < last {
+                links.push(link(self.page + 1, "next"));
+            }
+
+            links.push(link(last, "last"));
+        }
+
+        links
+    }
+
+    /// The `Link` header with `links` for `base`.
+    pub fn link_header(&self, base: &Uri) -> Header {
+        Header::Link(self.links(base))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_of_a_middle_page() {
+        let uri = Uri::absolute(
+            "example.com".to_string(),
+            "/items?page=3&per_page=20".to_string(),
+        );
+        let pagination = Pagination::from_query(&uri.search, 10, 50)
+            .unwrap()
+            .with_total(95);
+
+        assert_eq!((pagination.page, pagination.per_page), (3, 20));
+        assert_eq!(pagination.offset(), 40);
+        assert_eq!(pagination.last_page(), Some(5));
+
+        let links: Vec<String> = pagination.links(&uri).iter().map(Link::to_string).collect();
+
+        assert_eq!(
+            links,
+            [
+                r#"<http://example.com/items?page=1&per_page=20>; rel="first""#,
+                r#"<http://example.com/items?page=2&per_page=20>; rel="prev""#,
+                r#"<http://example.com/items?page=4&per_page=20>; rel="next""#,
+                r#"<http://example.com/items?page=5&per_page=20>; rel="last""#,
+            ]
+        );
+
+        // Without a total, no next nor last
+        let rels: Vec<String> = Pagination {
+            total: None,
+            ..pagination
+        }
+        .links(&uri)
+        .into_iter()
+        .map(|link| link.rel)
+        .collect();
+
+        assert_eq!(rels, ["first", "prev"]);
+    }
+
+    #[test]
+    fn per_page_is_clamped() {
+        let uri = Uri::absolute(
+            "example.com".to_string(),
+            "/items?per_page=100000".to_string(),
+        );
+        let pagination = Pagination::from_query(&uri.search, 10, 50)
+            .unwrap()
+            .with_total(95);
+
+        assert_eq!((pagination.page, pagination.per_page), (1, 50));
+
+        // The links carry the clamped value
+        let links = pagination.links(&uri);
+
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[1].rel, "next");
+        assert_eq!(
+            links[1].target,
+            "http://example.com/items?per_page=50&page=2"
+        );
+
+        let defaults = Pagination::from_query(&SearchParams::empty(), 10, 50).unwrap();
+
+        assert_eq!((defaults.page, defaults.per_page), (1, 10));
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        let query = |raw: &str| SearchParams::parse(raw.to_string()).unwrap();
+
+        assert_eq!(
+            Pagination::from_query(&query("?page=0"), 10, 50),
+            Err(PaginationError::InvalidPage("0".to_string()))
+        );
+        assert_eq!(
+            Pagination::from_query(&query("?per_page=many"), 10, 50),
+            Err(PaginationError::InvalidPerPage("many".to_string()))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+/// Values taken from a path by `match_path`, in the order of the pattern.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Params {
+    pub params: Vec<(String, String)>,
+    /// What a trailing `*` matched, without its leading slash (`a/b.txt`).
+    pub wildcard: Option<String>,
+}
+
+impl Params {
+    /// The value of `:name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Match `path` against `pattern`, segment by segment:
+///
+/// - `users` only matches `users`, case included
+/// - `:id` matches any non-empty segment, kept as `id`
+/// - `*`, last in the pattern, matches what remains of the path, even nothing
+///
+/// Unless `strict_slashes`, `/users/` and `/users` are the same path.
+pub fn match_path(pattern: &str, path: &str, strict_slashes: bool) -> Option<Params> {
+    let pattern = segments(pattern, strict_slashes);
+    let path = segments(path, strict_slashes);
+    let mut params = Params::default();
+
+    for (index, segment) in pattern.iter().enumerate() {
+        if *segment == "*" && index == pattern.len() - 1 {
+            params.wildcard = Some(path.get(index..).unwrap_or_default().join("/"));
+
+            return Some(params);
+        }
+
+        let value = path.get(index)?;
+
+        match segment.strip_prefix(':') {
+            Some(name) if !value.is_empty() => {
+                params.params.push((name.to_string(), value.to_string()))
+            }
+            Some(_) => return None,
+            None if segment == value => {}
+            None => return None,
+        }
+    }
+
+    (pattern.len() == path.len()).then_some(params)
+}
+
+fn segments(path: &str, strict_slashes: bool) -> Vec<&str> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let path = match strict_slashes {
+        true => path,
+        false => path.strip_suffix('/').unwrap_or(path),
+    };
+
+    if path.is_empty() {
+        vec![]
+    } else {
+        path.split('/').collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_segments() {
+        assert!(match_path("/users", "/users", false).is_some());
+        assert!(match_path("/", "/", false).is_some());
+        assert!(match_path("/users", "/Users", false).is_none());
+        assert!(match_path("/users", "/users/42", false).is_none());
+        assert!(match_path("/users/42", "/users", false).is_none());
+    }
+
+    #[test]
+    fn named_segments() {
+        let params = match_path("/users/:id/posts/:post", "/users/42/posts/7", false).unwrap();
+
+        assert_eq!(params.get("id"), Some("42"));
+        assert_eq!(params.get("post"), Some("7"));
+        assert_eq!(params.wildcard, None);
+
+        // A named segment is never empty
+        assert!(match_path("/users/:id", "/users//", true).is_none());
+    }
+
+    #[test]
+    fn trailing_wildcard() {
+        let params = match_path("/static/*", "/static/css/site.css", false).unwrap();
+
+        assert_eq!(params.wildcard.as_deref(), Some("css/site.css"));
+        assert_eq!(
+            match_path("/static/*", "/static", false)
+                .unwrap()
+                .wildcard
+                .as_deref(),
+            Some("")
+        );
+
+        // Only last in the pattern
+        assert!(match_path("/*/edit", "/anything/edit", false).is_none());
+    }
+
+    #[test]
+    fn trailing_slashes() {
+        assert!(match_path("/users", "/users/", false).is_some());
+        assert!(match_path("/users/", "/users", false).is_some());
+        assert!(match_path("/users", "/users/", true).is_none());
+        assert!(match_path("/users/", "/users/", true).is_some());
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The worker threads of `Server::serve`: how many there should be, how many
+/// run, and the connections waiting for one. Clones share them, so one can be
+/// kept to resize the pool once the server is moved into `serve`.
+#[derive(Debug, Clone)]
+pub struct WorkerPool {
+    state: Arc<PoolState>,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    target: AtomicUsize,
+    live: AtomicUsize,
+    queued: AtomicUsize,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize) -> WorkerPool {
+        let pool = WorkerPool {
+            state: Arc::default(),
+        };
+
+        pool.set_workers(workers);
+
+        pool
+    }
+
+    /// Run `workers` threads, at least one. More are started at once, extra
+    /// ones exit once done with their current connection.
+    pub fn set_workers(&self, workers: usize) {
+        self.state.target.store(workers.max(1), Ordering::SeqCst);
+    }
+
+    /// The threads the pool should run.
+    pub fn workers(&self) -> usize {
+        self.state.target.load(Ordering::SeqCst)
+    }
+
+    /// The threads running, different from `workers` until a resize is over.
+    pub fn live(&self) -> usize {
+        self.state.live.load(Ordering::SeqCst)
+    }
+
+    /// Connections with a request waiting for a free worker.
+    pub fn queued(&self) -> usize {
+        self.state.queued.load(Ordering::SeqCst)
+    }
+
+    /// Count a worker as running until the returned guard is dropped.
+    pub fn start(&self) -> PoolWorker {
+        self.state.live.fetch_add(1, Ordering::SeqCst);
+
+        PoolWorker {
+            pool: self.clone(),
+            retired: false,
+        }
+    }
+
+    /// Count a connection as queued, until `dequeued`.
+    pub fn queue(&self) {
+        self.state.queued.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn dequeued(&self) {
+        self.state.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A running worker of `WorkerPool`, until dropped, even if it panics.
+#[derive(Debug)]
+pub struct PoolWorker {
+    pool: WorkerPool,
+    retired: bool,
+}
+
+impl PoolWorker {
+    /// Whether the pool runs more workers than it should, this one then no
+    /// longer counted and to exit.
+    pub fn retire(&mut self) -> bool {
+        let target = self.pool.workers();
+
+        self.retired = self
+            .pool
+            .state
+            .live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |live| {
+                (live > target).then_some(live - 1)
+            })
+            .is_ok();
+
+        self.retired
+    }
+}
+
+impl Drop for PoolWorker {
+    fn drop(&mut self) {
+        if !self.retired {
+            self.pool.state.live.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::charset::CharsetError;
+use crate::common::Status;
+use crate::digest::DigestError;
+use crate::json::{JsonError, Value};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// An error answered as `application/problem+json` (RFC 9457), see `Response::problem`.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// A URI naming the kind of problem, `about:blank` when the status says it all.
+    pub problem_type: String,
+    pub title: Option<String>,
+    pub status: Status,
+    /// What went wrong for this occurrence, for the client to read.
+    pub detail: Option<String>,
+    /// A URI naming this occurrence.
+    pub instance: Option<String>,
+    /// More members, sent after the standard ones.
+    pub extensions: Vec<(String, Value)>,
+}
+
+impl Problem {
+    /// An `about:blank` problem, titled with the reason phrase of `status`.
+    pub fn new(status: Status) -> Problem {
+        Problem {
+            problem_type: "about:blank".to_string(),
+            title: Some(status.reason()),
+            status,
+            detail: None,
+            instance: None,
+            extensions: vec![],
+        }
+    }
+
+    /// A `400 Bad Request` listing what is wrong with each field in an `errors` member:
+    /// `[{"field": "age", "detail": "must be a number"}]`.
+    pub fn validation(errors: &[FieldError]) -> Problem {
+        let errors: Vec<Value> = errors.iter().map(FieldError::to_value).collect();
+
+        Problem::new(Status::BadRequest)
+            .detail("The request has invalid fields")
+            .extension("errors", errors)
+    }
+
+    pub fn problem_type(mut self, problem_type: &str) -> Problem {
+        self.problem_type = problem_type.to_string();
+
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Problem {
+        self.title = Some(title.to_string());
+
+        self
+    }
+
+    pub fn detail(mut self, detail: &str) -> Problem {
+        self.detail = Some(detail.to_string());
+
+        self
+    }
+
+    pub fn instance(mut self, instance: &str) -> Problem {
+        self.instance = Some(instance.to_string());
+
+        self
+    }
+
+    pub fn extension(mut self, name: &str, value: impl Into<Value>) -> Problem {
+        self.extensions.push((name.to_string(), value.into()));
+
+        self
+    }
+
+    pub fn get_extension(&self, name: &str) -> Option<&Value> {
+        self.extensions
+            .iter()
+            .find(|(extension, _)| extension == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The JSON body, members with no value left out.
+    pub fn to_value(&self) -> Value {
+        let mut members = vec![("type".to_string(), Value::from(self.problem_type.as_str()))];
+
+        if let Some(ref title) = self.title {
+            members.push(("title".to_string(), Value::from(title.as_str())));
+        }
+
+        members.push(("status".to_string(), Value::from(self.status.code() as i64)));
+
+        if let Some(ref detail) = self.detail {
+            members.push(("detail".to_string(), Value::from(detail.as_str())));
+        }
+
+        if let Some(ref instance) = self.instance {
+            members.push(("instance".to_string(), Value::from(instance.as_str())));
+        }
+
+        members.extend(self.extensions.iter().cloned());
+
+        Value::Object(members)
+    }
+}
+
+/// The JSON body, see `to_value`.
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.to_value())
+    }
+}
+
+/// A body that doesn't decode is a bad request, an unknown charset an unsupported
+/// media type.
+impl From<CharsetError> for Problem {
+    fn from(err: CharsetError) -> Self {
+        let status = match err {
+            CharsetError::Unsupported(_) => Status::UnsupportedMediaType,
+            CharsetError::InvalidData { .. } => Status::BadRequest,
+        };
+        let problem = Problem::new(status).detail(&err.to_string());
+
+        match err {
+            CharsetError::InvalidData { position, .. } => problem.extension("position", position),
+            CharsetError::Unsupported(_) => problem,
+        }
+    }
+}
+
+impl From<JsonError> for Problem {
+    fn from(err: JsonError) -> Self {
+        Problem::new(Status::BadRequest)
+            .detail(&format!("Invalid JSON body: {}", err))
+            .extension("position", err.position)
+    }
+}
+
+impl From<DigestError> for Problem {
+    fn from(err: DigestError) -> Self {
+        Problem::new(Status::BadRequest).detail(&err.to_string())
+    }
+}
+
+/// What is wrong with one field of a request (a query parameter, a member of
+/// the body, ...), see `Problem::validation`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub detail: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, detail: &str) -> FieldError {
+        FieldError {
+            field: field.to_string(),
+            detail: detail.to_string(),
+        }
+    }
+
+    /// `{"field":"age","detail":"must be a number"}`
+    pub fn to_value(&self) -> Value {
+        Value::object()
+            .insert("field", self.field.as_str())
+            .insert("detail", self.detail.as_str())
+    }
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}: {}", self.field, self.detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Header;
+    use crate::response::Response;
+
+    #[test]
+    fn validation_problem() {
+        let problem = Problem::validation(&[
+            FieldError::new("age", "must be a number"),
+            FieldError::new("email", "is required"),
+        ])
+        .instance("/signup");
+
+        assert_eq!(
+            problem.to_string(),
+            r#"{"type":"about:blank","title":"Bad Request","status":400,"detail":"The request has invalid fields","instance":"/signup","errors":[{"field":"age","detail":"must be a number"},{"field":"email","detail":"is required"}]}"#
+        );
+
+        let response = Response::problem(problem);
+
+        assert_eq!(response.status.code(), 400);
+        assert_eq!(
+            response
+                .get_header("Content-Type")
+                .map(Header::value)
+                .as_deref(),
+            Some("application/problem+json")
+        );
+    }
+
+    #[test]
+    fn problems_from_errors() {
+        let problem = Problem::from(Value::parse("{\"a\": }").unwrap_err());
+
+        assert_eq!(problem.status.code(), 400);
+        assert!(problem.get_extension("position").is_some());
+
+        let response = Response::problem(
+            Problem::new(Status::Conflict)
+                .problem_type("https://example.com/problems/taken")
+                .title("Name taken")
+                .extension("name", "alice"),
+        );
+
+        assert_eq!(response.status.code(), 409);
+        assert_eq!(
+            response.body_bytes(),
+            br#"{"type":"https://example.com/problems/taken","title":"Name taken","status":409,"name":"alice"}"#
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::common::{Header, Status};
+use crate::date::RetryAfter;
+use crate::response::Response;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Add;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Bytes that went over the connection, as framed on the wire: heads, chunk
+/// sizes and compressed bodies included.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ByteCounts {
+    pub received: usize,
+    pub sent: usize,
+}
+
+impl ByteCounts {
+    pub fn total(&self) -> usize {
+        self.received + self.sent
+    }
+}
+
+impl Add for ByteCounts {
+    type Output = ByteCounts;
+
+    fn add(self, other: ByteCounts) -> ByteCounts {
+        ByteCounts {
+            received: self.received + other.received,
+            sent: self.sent + other.sent,
+        }
+    }
+}
+
+/// An allowance of bytes for each key (a client address, an API key, ...) per
+/// window of time, the window of a key starting with its first bytes.
+///
+/// Record the bytes of each answered request, e.g. from `ServerOptions::logger`
+/// with `LogEvent::bytes_received` and `bytes_sent`, and `check` new requests.
+#[derive(Debug)]
+pub struct Quota<K: Eq + Hash> {
+    pub limit: usize,
+    pub window: Duration,
+    usage: Mutex<HashMap<K, (Instant, usize)>>,
+}
+
+impl<K: Eq + Hash> Quota<K> {
+    pub fn new(limit: usize, window: Duration) -> Quota<K> {
+        Quota {
+            limit,
+            window,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count `bytes` against the allowance of `key`.
+    pub fn record(&self, key: K, bytes: usize) {
+        let mut usage = self.usage.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+
+        // Forget the windows that ended, so that the map doesn't grow with every key
+        usage.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+
+        usage.entry(key).or_insert((now, 0)).1 += bytes;
+    }
+
+    /// Bytes `key` may still use in its window.
+    pub fn remaining(&self, key: &K) -> usize {
+        self.current(key)
+            .map_or(self.limit, |(_, used)| self.limit.saturating_sub(used))
+    }
+
+    pub fn exceeded(&self, key: &K) -> bool {
+        self.remaining(key) == 0
+    }
+
+    /// A `429 Too Many Requests` with a `Retry-After` for the end of the window
+    /// once `key` used its allowance, `None` while it may go on.
+    pub fn check(&self, key: &K) -> Option<Response> {
+        let (start, used) = self.current(key)?;
+
+        if used < self.limit {
+            return None;
+        }
+
+        let retry = self.window.saturating_sub(start.elapsed());
+        let mut response = Response::empty();
+
+        response
+            .set_status(Status::TooManyRequests)
+            .add_header(Header::RetryAfter(RetryAfter::Seconds(
+                retry.as_secs_f64().ceil() as u64,
+            )));
+
+        Some(response)
+    }
+
+    /// The start of the window of `key` and the bytes used since, `None` when
+    /// it has none open.
+    fn current(&self, key: &K) -> Option<(Instant, usize)> {
+        let usage = self.usage.lock().unwrap_or_else(|err| err.into_inner());
+
+        usage
+            .get(key)
+            .copied()
+            .filter(|(start, _)| start.elapsed() < self.window)
+    }
+}
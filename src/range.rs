@@ -0,0 +1,307 @@
+use crate::mime::Mime;
+use std::collections::hash_map::RandomState;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::{BuildHasher, Hasher};
+
+/// An inclusive range of bytes, `start..=end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// What to do with the `Range` header of a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeRequest {
+    /// Send these ranges, sorted and not overlapping, with `206 Partial Content`.
+    Satisfiable(Vec<ByteRange>),
+    /// No range overlaps the representation, answer `416 Range Not Satisfiable`.
+    Unsatisfiable,
+    /// Send the whole representation: the header is malformed, not in bytes,
+    /// or asks for too many ranges.
+    Ignore,
+}
+
+impl RangeRequest {
+    /// Parse a `Range` header (`bytes=0-99, 1000-, -500`) against a representation
+    /// of `length` bytes.
+    ///
+    /// Overlapping and adjacent ranges are coalesced; when more than `max_ranges`
+    /// remain, the header is ignored.
+    pub fn parse(raw: &str, length: u64, max_ranges: usize) -> RangeRequest {
+        let Some((unit, specs)) = raw.split_once('=') else {
+            return RangeRequest::Ignore;
+        };
+
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return RangeRequest::Ignore;
+        }
+
+        let mut ranges: Vec<ByteRange> = vec![];
+        let mut any = false;
+
+        for spec in specs.split(',').map(|spec| spec.trim()) {
+            if spec.is_empty() {
+                continue;
+            }
+            any = true;
+
+            let Some((start, end)) = spec.split_once('-') else {
+                return RangeRequest::Ignore;
+            };
+
+            let range = match (start.trim(), end.trim()) {
+                // The last `suffix` bytes
+                ("", suffix) => match suffix.parse::<u64>() {
+                    Ok(0) => continue,
+                    Ok(_) if length == 0 => continue,
+                    Ok(suffix) => ByteRange {
+                        start: length.saturating_sub(suffix),
+                        end: length - 1,
+                    },
+                    Err(_) => return RangeRequest::Ignore,
+                },
+                (start, end) => {
+                    let Ok(start) = start.parse::<u64>() else {
+                        return RangeRequest::Ignore;
+                    };
+                    let end = match end {
+                        "" => None,
+                        end => match end.parse::<u64>() {
+                            Ok(end) if end >= start => Some(end),
+                            _ => return RangeRequest::Ignore,
+                        },
+                    };
+
+                    if start >= length {
+                        continue;
+                    }
+
+                    ByteRange {
+                        start,
+                        end: end.map_or(length - 1, |end| end.min(length - 1)),
+                    }
+                }
+            };
+
+            ranges.push(range);
+        }
+
+        if !any {
+            return RangeRequest::Ignore;
+        }
+
+        if ranges.is_empty() {
+            return RangeRequest::Unsatisfiable;
+        }
+
+        ranges.sort_by_key(|range| range.start);
+
+        let mut coalesced: Vec<ByteRange> = vec![];
+
+        for range in ranges {
+            match coalesced.last_mut() {
+                Some(last) if range.start <= last.end + 1 => last.end = last.end.max(range.end),
+                _ => coalesced.push(range),
+            }
+        }
+
+        if coalesced.len() > max_ranges {
+            return RangeRequest::Ignore;
+        }
+
+        RangeRequest::Satisfiable(coalesced)
+    }
+}
+
+/// A range unit of an `Accept-Ranges` header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeUnit {
+    Bytes,
+    /// `none`, ranges are not supported at all.
+    None,
+    Other(String),
+}
+
+impl RangeUnit {
+    /// Parse a comma-separated list of units, case-insensitive.
+    pub fn parse_list(raw: &str) -> Vec<RangeUnit> {
+        raw.split(',')
+            .map(|unit| unit.trim())
+            .filter(|unit| !unit.is_empty())
+            .map(|unit| match unit.to_lowercase().as_str() {
+                "bytes" => RangeUnit::Bytes,
+                "none" => RangeUnit::None,
+                _ => RangeUnit::Other(unit.to_string()),
+            })
+            .collect()
+    }
+}
+
+impl Display for RangeUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            RangeUnit::Bytes => write!(f, "bytes"),
+            RangeUnit::None => write!(f, "none"),
+            RangeUnit::Other(unit) => write!(f, "{}", unit),
+        }
+    }
+}
+
+/// The value of a `Content-Range` header: `bytes 0-99/1000`, or `bytes */1000`
+/// when answering `416 Range Not Satisfiable`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentRange {
+    pub range: Option<ByteRange>,
+    pub length: u64,
+}
+
+impl Display for ContentRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.range {
+            Some(range) => write!(f, "bytes {}-{}/{}", range.start, range.end, self.length),
+            None => write!(f, "bytes */{}", self.length),
+        }
+    }
+}
+
+/// A random boundary for a multipart body.
+pub fn boundary() -> String {
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+/// Build a `multipart/byteranges` body with one part per range of `data`.
+pub fn multipart(
+    data: &[u8],
+    ranges: &[ByteRange],
+    content_type: Option<&Mime>,
+    boundary: &str,
+) -> Vec<u8> {
+    let mut body = vec![];
+
+    for range in ranges {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(
+                format!("Content-Type: {}\r\n", content_type.to_string()).as_bytes(),
+            );
+        }
+
+        let content_range = ContentRange {
+            range: Some(*range),
+            length: data.len() as u64,
+        };
+
+        body.extend_from_slice(format!("Content-Range: {}\r\n\r\n", content_range).as_bytes());
+        body.extend_from_slice(&data[(range.start as usize)..=(range.end as usize)]);
+        body.extend_from_slice(b"\r\n");
+    }
+
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn range_headers() {
+        let cases = [
+            ("bytes=0-99", RangeRequest::Satisfiable(vec![range(0, 99)])),
+            (
+                "bytes=900-",
+                RangeRequest::Satisfiable(vec![range(900, 999)]),
+            ),
+            (
+                "bytes=-100",
+                RangeRequest::Satisfiable(vec![range(900, 999)]),
+            ),
+            (
+                "bytes=-5000",
+                RangeRequest::Satisfiable(vec![range(0, 999)]),
+            ),
+            (
+                "bytes=990-2000",
+                RangeRequest::Satisfiable(vec![range(990, 999)]),
+            ),
+            (
+                "BYTES=500-599, 0-99",
+                RangeRequest::Satisfiable(vec![range(0, 99), range(500, 599)]),
+            ),
+            // Overlapping and adjacent ranges are coalesced
+            (
+                "bytes=0-99, 50-149, 150-199, 300-",
+                RangeRequest::Satisfiable(vec![range(0, 199), range(300, 999)]),
+            ),
+            ("bytes=1000-", RangeRequest::Unsatisfiable),
+            ("bytes=1000-1099, -0", RangeRequest::Unsatisfiable),
+            ("items=0-9", RangeRequest::Ignore),
+            ("bytes=10-5", RangeRequest::Ignore),
+            ("bytes=a-b", RangeRequest::Ignore),
+            ("bytes=", RangeRequest::Ignore),
+            ("0-99", RangeRequest::Ignore),
+        ];
+
+        for (raw, expected) in cases {
+            assert_eq!(RangeRequest::parse(raw, 1000, 16), expected, "{}", raw);
+        }
+
+        // Past the cap the whole body is sent, unless coalescing brings it under
+        let many = (0..20)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 4))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        assert_eq!(
+            RangeRequest::parse(&format!("bytes={}", many), 1000, 16),
+            RangeRequest::Ignore
+        );
+        assert!(matches!(
+            RangeRequest::parse(&format!("bytes={}", many), 1000, 20),
+            RangeRequest::Satisfiable(ranges) if ranges.len() == 20
+        ));
+
+        let overlapping = vec!["0-10"; 20].join(",");
+
+        assert_eq!(
+            RangeRequest::parse(&format!("bytes={}", overlapping), 1000, 16),
+            RangeRequest::Satisfiable(vec![range(0, 10)])
+        );
+    }
+
+    #[test]
+    fn range_units_round_trip() {
+        assert_eq!(
+            RangeUnit::parse_list("Bytes, none,, items"),
+            [
+                RangeUnit::Bytes,
+                RangeUnit::None,
+                RangeUnit::Other("items".to_string())
+            ]
+        );
+
+        for raw in ["bytes", "none", "bytes, items"] {
+            let units = RangeUnit::parse_list(raw)
+                .iter()
+                .map(RangeUnit::to_string)
+                .collect::<Vec<_>>();
+
+            assert_eq!(units.join(", "), raw);
+        }
+
+        assert!(RangeUnit::parse_list(" ").is_empty());
+    }
+}
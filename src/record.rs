@@ -0,0 +1,468 @@
+use crate::common::{Cache, Header, Method, Status, Version};
+use crate::digest;
+use crate::json::Value;
+use crate::request::Request;
+use crate::response::Response;
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What a redacted header value is recorded as.
+pub const REDACTED: &str = "[redacted]";
+
+/// A body as recorded, its start only when larger than `Recorder::max_body_size`.
+#[derive(Debug, Clone, Default)]
+pub struct RecordedBody {
+    pub data: Vec<u8>,
+    /// Size of the whole body.
+    pub size: usize,
+    /// Whether `data` is only the start of the body.
+    pub truncated: bool,
+}
+
+impl RecordedBody {
+    pub fn capture(body: &[u8], max_size: usize) -> RecordedBody {
+        RecordedBody {
+            data: body[..body.len().min(max_size)].to_vec(),
+            size: body.len(),
+            truncated: body.len() > max_size,
+        }
+    }
+
+    /// `{ "size", "mimeType", "text" }`, `text` in base64 with `"encoding": "base64"`
+    /// when it isn't UTF-8, and `"_truncated": true` past the size cap.
+    fn to_har(&self, mime_type: &str) -> Value {
+        let content = Value::object()
+            .insert("size", self.size)
+            .insert("mimeType", mime_type);
+        let content = match std::str::from_utf8(&self.data) {
+            Ok(text) => content.insert("text", text),
+            Err(_) => content
+                .insert("text", digest::encode_base64(&self.data))
+                .insert("encoding", "base64"),
+        };
+
+        content.insert("_truncated", self.truncated)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub url: String,
+    pub version: Version,
+    /// Every header as received, sensitive ones redacted.
+    pub headers: Vec<(String, String)>,
+    /// The query string pairs in the order they were received.
+    pub query: Vec<(String, String)>,
+    pub body: RecordedBody,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedResponse {
+    pub status: Status,
+    /// Every header as sent, sensitive ones redacted.
+    pub headers: Vec<(String, String)>,
+    /// The body before compression, `None` when streamed with `Request::respond_head`.
+    pub body: Option<RecordedBody>,
+    /// Bytes of the body as sent, compressed, `None` when streamed.
+    pub body_size: Option<usize>,
+}
+
+/// One exchange kept by a `Recorder`.
+#[derive(Debug, Clone)]
+pub struct RecordEntry {
+    /// When the request was received.
+    pub started: DateTime<Local>,
+    /// From receiving the request to sending the response (its head when streamed).
+    pub duration: Duration,
+    pub peer: Option<SocketAddr>,
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+impl RecordEntry {
+    /// The exchange as an entry of a HAR (HTTP Archive) log.
+    pub fn to_har(&self) -> Value {
+        let pairs = |pairs: &[(String, String)]| {
+            pairs.iter().fold(Value::array(), |array, (name, value)| {
+                array.push(
+                    Value::object()
+                        .insert("name", name.as_str())
+                        .insert("value", value.as_str()),
+                )
+            })
+        };
+        let mime_type = |headers: &[(String, String)]| {
+            headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+                .map_or("", |(_, value)| value.as_str())
+                .to_string()
+        };
+        let time = self.duration.as_secs_f64() * 1000.0;
+        let request = &self.request;
+        let response = &self.response;
+
+        let mut har_request = Value::object()
+            .insert("method", request.method.to_string())
+            .insert("url", request.url.as_str())
+            .insert("httpVersion", request.version.to_string())
+            .insert("headers", pairs(&request.headers))
+            .insert("queryString", pairs(&request.query))
+            .insert("cookies", Value::array())
+            .insert("headersSize", -1)
+            .insert("bodySize", request.body.size);
+
+        if request.body.size > 0 {
+            har_request = har_request.insert(
+                "postData",
+                request.body.to_har(&mime_type(&request.headers)),
+            );
+        }
+
+        let content = match response.body {
+            Some(ref body) => body.to_har(&mime_type(&response.headers)),
+            None => Value::object()
+                .insert("size", -1)
+                .insert("mimeType", mime_type(&response.headers)),
+        };
+        let location = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Location"))
+            .map_or("", |(_, value)| value.as_str());
+
+        Value::object()
+            .insert("startedDateTime", self.started.to_rfc3339())
+            .insert("time", time)
+            .insert("_peer", self.peer.map(|peer| peer.to_string()))
+            .insert("request", har_request)
+            .insert(
+                "response",
+                Value::object()
+                    .insert("status", response.status.code() as u64)
+                    .insert("statusText", response.status.reason())
+                    .insert("httpVersion", request.version.to_string())
+                    .insert("headers", pairs(&response.headers))
+                    .insert("cookies", Value::array())
+                    .insert("content", content)
+                    .insert("redirectURL", location)
+                    .insert("headersSize", -1)
+                    .insert(
+                        "bodySize",
+                        response.body_size.map_or(-1, |size| size as i64),
+                    ),
+            )
+            .insert("cache", Value::object())
+            .insert(
+                "timings",
+                Value::object()
+                    .insert("send", 0)
+                    .insert("wait", time)
+                    .insert("receive", 0),
+            )
+    }
+}
+
+/// Keeps the last exchanges the server answered, requests and responses in
+/// full, see `ServerOptions::recorder`: a capture of what a client sent and
+/// got, e.g. to debug an integration. Clones share the switch and the
+/// entries, so one can be moved into an admin handler to dump them.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    enabled: Arc<AtomicBool>,
+    entries: Arc<Mutex<VecDeque<RecordEntry>>>,
+    /// Entries kept, the oldest dropped past it.
+    capacity: usize,
+    /// Bytes of each body kept, the rest dropped.
+    max_body_size: usize,
+    /// Headers recorded as `REDACTED`, compared case-insensitively.
+    redact: Vec<String>,
+}
+
+impl Recorder {
+    /// Keep the last `capacity` exchanges, bodies up to 64 KiB, with the
+    /// credentials in `Authorization`, `Proxy-Authorization`, `Cookie` and
+    /// `Set-Cookie` redacted.
+    pub fn new(capacity: usize) -> Recorder {
+        Recorder {
+            enabled: Arc::new(AtomicBool::new(true)),
+            entries: Arc::default(),
+            capacity,
+            max_body_size: 64 * 1024,
+            redact: [
+                "Authorization",
+                "Proxy-Authorization",
+                "Cookie",
+                "Set-Cookie",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+        }
+    }
+
+    pub fn max_body_size(mut self, size: usize) -> Recorder {
+        self.max_body_size = size;
+
+        self
+    }
+
+    /// Record the values of `headers` as `REDACTED`, instead of the default ones.
+    pub fn redact(mut self, headers: &[&str]) -> Recorder {
+        self.redact = headers.iter().map(|header| header.to_string()).collect();
+
+        self
+    }
+
+    /// Pause or resume recording, the entries already kept stay.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// The exchanges kept, oldest first.
+    pub fn entries(&self) -> Vec<RecordEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+
+        entries.iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+
+    /// The exchanges kept as a HAR (HTTP Archive) log, `{ "log": { "entries": [...] } }`.
+    pub fn to_har(&self) -> Value {
+        let entries = self
+            .entries()
+            .iter()
+            .fold(Value::array(), |array, entry| array.push(entry.to_har()));
+
+        Value::object().insert(
+            "log",
+            Value::object()
+                .insert("version", "1.2")
+                .insert(
+                    "creator",
+                    Value::object()
+                        .insert("name", "JulMan-Http")
+                        .insert("version", env!("CARGO_PKG_VERSION")),
+                )
+                .insert("entries", entries),
+        )
+    }
+
+    /// The HAR log as a JSON response, for an admin endpoint to dump the entries.
+    pub fn response(&self) -> Response {
+        let mut response = Response::json_value(self.to_har());
+
+        response.add_header(Header::CacheControl(vec![Cache::NoStore]));
+
+        response
+    }
+
+    /// Keep the exchange of `request`, answered at `status` with the raw `head`
+    /// of the response and its body, before compression, with the bytes of it
+    /// sent (`None` when streamed). Past `capacity` entries, the oldest is dropped.
+    pub fn record(
+        &self,
+        request: &Request,
+        started: DateTime<Local>,
+        status: Status,
+        head: &[u8],
+        body: Option<(&[u8], usize)>,
+    ) {
+        if !self.is_enabled() || self.capacity == 0 {
+            return;
+        }
+
+        let request_headers = request
+            .headers
+            .iter()
+            .map(|header| (header.name(), header.value()))
+            .collect();
+        // After the status line, none for HTTP/0.9
+        let head = String::from_utf8_lossy(head);
+        let response_headers = head
+            .split("\r\n")
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+            .collect();
+
+        let entry = RecordEntry {
+            started,
+            duration: (Local::now() - started).to_std().unwrap_or_default(),
+            peer: request.peer_addr,
+            request: RecordedRequest {
+                method: request.method.clone(),
+                url: request.uri.to_string(),
+                version: request.version.clone(),
+                headers: self.redacted(request_headers),
+                query: request
+                    .uri
+                    .search
+                    .pairs()
+                    .map(|pair| (pair.key().to_string(), pair.value().to_string()))
+                    .collect(),
+                body: RecordedBody::capture(&request.body_bytes, self.max_body_size),
+            },
+            response: RecordedResponse {
+                status,
+                headers: self.redacted(response_headers),
+                body: body.map(|(body, _)| RecordedBody::capture(body, self.max_body_size)),
+                body_size: body.map(|(_, size)| size),
+            },
+        };
+
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+
+        entries.push_back(entry);
+    }
+
+    fn redacted(&self, headers: Vec<(String, String)>) -> Vec<(String, String)> {
+        headers
+            .into_iter()
+            .map(|(name, value)| {
+                if self
+                    .redact
+                    .iter()
+                    .any(|redact| redact.eq_ignore_ascii_case(&name))
+                {
+                    (name, REDACTED.to_string())
+                } else {
+                    (name, value)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::ResponseBody;
+    use crate::server::{Server, ServerOptions};
+    use std::io::{Read, Write};
+    use std::net::{Ipv4Addr, TcpStream};
+    use std::thread::spawn;
+
+    #[test]
+    fn exchanges_recorded() {
+        let recorder = Recorder::new(2).max_body_size(8);
+        let options = ServerOptions {
+            recorder: Some(recorder.clone()),
+            ..ServerOptions::default()
+        };
+        let server = Server::bind((Ipv4Addr::LOCALHOST, 0), Some(options)).unwrap();
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let serving = spawn(move || {
+            server.serve(|request| {
+                let mut response = Response::empty();
+
+                response.set_body(ResponseBody::Text(format!("{} received", request.uri.path)));
+
+                response
+            })
+        });
+
+        for request in [
+            "GET /dropped HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            "POST /upload?name=a.txt HTTP/1.1\r\nHost: localhost\r\n\
+             Authorization: Bearer secret\r\nContent-Type: text/plain\r\n\
+             Content-Length: 20\r\nConnection: close\r\n\r\n0123456789abcdefghij",
+            "GET /status HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        ] {
+            let mut stream = TcpStream::connect(address).unwrap();
+
+            stream.write_all(request.as_bytes()).unwrap();
+            stream.read_to_end(&mut vec![]).unwrap();
+        }
+
+        drain.shutdown();
+        serving.join().unwrap();
+
+        // The oldest past the capacity is dropped
+        let entries = recorder.entries();
+
+        assert_eq!(entries.len(), 2);
+
+        let upload = &entries[0];
+
+        assert_eq!(upload.request.method.to_string(), "POST");
+        assert!(
+            upload.request.url.ends_with("/upload?name=a.txt"),
+            "{}",
+            upload.request.url
+        );
+        assert_eq!(
+            upload.request.query,
+            [("name".to_string(), "a.txt".to_string())]
+        );
+        // Unknown header names are kept lowercase
+        assert!(upload
+            .request
+            .headers
+            .contains(&("authorization".to_string(), REDACTED.to_string())));
+        assert_eq!(upload.request.body.data, b"01234567");
+        assert_eq!(upload.request.body.size, 20);
+        assert!(upload.request.body.truncated);
+
+        assert_eq!(upload.response.status.code(), 200);
+        assert!(upload
+            .response
+            .headers
+            .contains(&("Content-Length".to_string(), "16".to_string())));
+
+        let body = upload.response.body.as_ref().unwrap();
+
+        assert_eq!(body.data, b"/upload ");
+        assert!(body.truncated);
+        assert_eq!(upload.response.body_size, Some(16));
+
+        assert_eq!(entries[1].request.url, "http://localhost/status");
+        assert!(!entries[1].request.body.truncated);
+
+        let har = recorder.to_har();
+        let har_entries = har.get("log").and_then(|log| log.get("entries")).unwrap();
+        let har_request = har_entries
+            .at(0)
+            .and_then(|entry| entry.get("request"))
+            .unwrap();
+        let post_data = har_request.get("postData").unwrap();
+
+        assert_eq!(har_entries.as_array().unwrap().len(), 2);
+        assert_eq!(
+            har_request.get("method").and_then(Value::as_str),
+            Some("POST")
+        );
+        assert_eq!(
+            post_data.get("text").and_then(Value::as_str),
+            Some("01234567")
+        );
+        assert_eq!(
+            post_data.get("_truncated").and_then(Value::as_bool),
+            Some(true)
+        );
+        assert!(!har.to_string().contains("secret"));
+
+        recorder.clear();
+
+        assert!(recorder.entries().is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::common::Header;
+use crate::origin;
+use crate::request::Request;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Rules a `Location` target must follow, to avoid open redirects and
+/// `javascript:` / `data:` URLs built from user input.
+#[derive(Debug, Clone)]
+pub struct RedirectPolicy {
+    /// Only allow targets without a scheme nor an authority (`/path`, `page?x=1`).
+    pub allow_relative_only: bool,
+    /// Hosts absolute targets may point to, `None` to allow any host.
+    pub allowed_hosts: Option<Vec<String>>,
+    /// Schemes that are always rejected, compared case-insensitively.
+    pub forbid_schemes: Vec<String>,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        RedirectPolicy {
+            allow_relative_only: false,
+            allowed_hosts: None,
+            forbid_schemes: vec![
+                "javascript".to_string(),
+                "data".to_string(),
+                "vbscript".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedirectError {
+    /// The target contains characters that cannot appear in a header (CR, LF, ...).
+    InvalidTarget(String),
+    ForbiddenScheme(String),
+    /// The target is absolute while the policy only allows relative targets.
+    NotRelative(String),
+    HostNotAllowed(String),
+    /// The target is the URL of the request being answered.
+    Loop(String),
+}
+
+impl Display for RedirectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            RedirectError::InvalidTarget(target) => {
+                write!(f, "Invalid redirect target: {:?}", target)
+            }
+            RedirectError::ForbiddenScheme(scheme) => {
+                write!(f, "Redirect to a {}: URL is forbidden", scheme)
+            }
+            RedirectError::NotRelative(target) => {
+                write!(f, "Redirect target must be relative: {}", target)
+            }
+            RedirectError::HostNotAllowed(host) => {
+                write!(f, "Redirect to host {} is not allowed", host)
+            }
+            RedirectError::Loop(target) => write!(f, "Redirect loop to {}", target),
+        }
+    }
+}
+
+impl std::error::Error for RedirectError {}
+
+impl RedirectPolicy {
+    pub fn relative_only() -> RedirectPolicy {
+        RedirectPolicy {
+            allow_relative_only: true,
+            ..Default::default()
+        }
+    }
+
+    /// Check `target` against the policy. When `request` is given, a target
+    /// pointing back to the request URL is rejected as a loop.
+    pub fn check(&self, target: &str, request: Option<&Request>) -> Result<(), RedirectError> {
+        if target.is_empty() || target.chars().any(|c| c.is_control()) {
+            return Err(RedirectError::InvalidTarget(target.to_string()));
+        }
+
+        let (scheme, host) = split_target(target);
+
+        if let Some(ref scheme) = scheme {
+            if self
+                .forbid_schemes
+                .iter()
+                .any(|forbidden| forbidden.eq_ignore_ascii_case(scheme))
+            {
+                return Err(RedirectError::ForbiddenScheme(scheme.to_lowercase()));
+            }
+        }
+
+        if scheme.is_some() || host.is_some() {
+            if self.allow_relative_only {
+                return Err(RedirectError::NotRelative(target.to_string()));
+            }
+
+            if let Some(ref allowed_hosts) = self.allowed_hosts {
+                let host = host.as_deref().unwrap_or_default();
+
+                if !allowed_hosts
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(host))
+                {
+                    return Err(RedirectError::HostNotAllowed(host.to_string()));
+                }
+            }
+        }
+
+        if let Some(request) = request {
+            if is_same_url(target, host.as_deref(), request) {
+                return Err(RedirectError::Loop(target.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the scheme and the host (without userinfo nor port) of a target.
+///
+/// Backslashes count as slashes since browsers treat `/\evil.example` as `//evil.example`.
+fn split_target(target: &str) -> (Option<String>, Option<String>) {
+    let target = target.trim().replace('\\', "/");
+
+    let scheme = match target.find(':') {
+        Some(index)
+            if target[..index]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic())
+                && target[..index]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.') =>
+        {
+            Some(target[..index].to_string())
+        }
+        _ => None,
+    };
+
+    let rest = match scheme {
+        Some(ref scheme) => &target[(scheme.len() + 1)..],
+        None => target.as_str(),
+    };
+
+    let host = rest.strip_prefix("//").map(|authority| {
+        let authority = authority.split(['/', '?', '#']).next().unwrap_or_default();
+        let authority = authority.rsplit('@').next().unwrap_or_default();
+
+        match authority.strip_prefix('[') {
+            Some(ipv6) => format!("[{}]", ipv6.split(']').next().unwrap_or_default()),
+            None => authority.split(':').next().unwrap_or_default().to_string(),
+        }
+    });
+
+    (scheme, host)
+}
+
+fn is_same_url(target: &str, host: Option<&str>, request: &Request) -> bool {
+    let path = match host {
+        Some(host) => {
+            let request_host = match request.get_header("Host") {
+                Some(Header::Host(request_host)) => match origin::split_host_port(request_host) {
+                    Some((request_host, _)) => request_host,
+                    None => return false,
+                },
+                _ => return false,
+            };
+
+            if !host.eq_ignore_ascii_case(&request_host) {
+                return false;
+            }
+
+            let authority_start = target.find("//").map(|index| index + 2).unwrap_or(0);
+
+            match target[authority_start..].find('/') {
+                Some(index) => &target[(authority_start + index)..],
+                None => "/",
+            }
+        }
+        None => target,
+    };
+
+    path == format!("{}{}", request.uri.path, request.uri.search.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forbidden_schemes() {
+        let policy = RedirectPolicy::default();
+
+        for target in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            "data:text/html,x",
+        ] {
+            assert!(
+                matches!(
+                    policy.check(target, None),
+                    Err(RedirectError::ForbiddenScheme(_))
+                ),
+                "{}",
+                target
+            );
+        }
+
+        assert_eq!(policy.check("https://example.com/", None), Ok(()),);
+        assert!(matches!(
+            policy.check("/next\r\nSet-Cookie: x=1", None),
+            Err(RedirectError::InvalidTarget(_))
+        ));
+    }
+
+    #[test]
+    fn relative_only_and_allowed_hosts() {
+        let policy = RedirectPolicy::relative_only();
+
+        assert_eq!(policy.check("/account?tab=1", None), Ok(()));
+        assert_eq!(policy.check("settings", None), Ok(()));
+
+        // Browsers read `/\` and `//` as the start of an authority
+        for target in [
+            "https://evil.example/",
+            "//evil.example/",
+            "/\\evil.example/",
+        ] {
+            assert!(
+                matches!(
+                    policy.check(target, None),
+                    Err(RedirectError::NotRelative(_))
+                ),
+                "{}",
+                target
+            );
+        }
+
+        let policy = RedirectPolicy {
+            allowed_hosts: Some(vec!["example.com".to_string()]),
+            ..RedirectPolicy::default()
+        };
+
+        assert_eq!(policy.check("https://EXAMPLE.com:8443/a", None), Ok(()));
+        assert_eq!(
+            policy.check("https://example.com@evil.example/", None),
+            Err(RedirectError::HostNotAllowed("evil.example".to_string()))
+        );
+    }
+}
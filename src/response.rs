@@ -1,11 +1,20 @@
-use crate::common::{Header, Method, Status};
+use crate::common::{Cache, Connection, Header, Method, Status, Version, LIST_HEADERS};
 use crate::cookie::ResponseCookie;
+use crate::date::{HttpDate, DATE_CACHE};
+use crate::digest::{Digest, DigestAlgorithm};
+use crate::download::{self, DownloadPolicy};
+use crate::encoding;
+use crate::json::Value;
 use crate::mime::Mime;
+use crate::origin;
+use crate::problem::Problem;
+use crate::range::{self, ContentRange, RangeRequest, RangeUnit};
+use crate::redirect::{RedirectError, RedirectPolicy};
 use crate::request::Request;
-use brotli::CompressorReader;
-use flate2::write::{DeflateEncoder, GzEncoder};
-use flate2::Compression;
-use std::io::{Error as IoError, ErrorKind, Read, Write};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum ResponseBody {
@@ -38,6 +47,160 @@ pub struct Response {
     pub headers: Vec<Header>,
     pub body: ResponseBody,
     pub encoding: (Option<BodyEncoding>, Option<CompressionLevel>),
+    /// Encodings to try in order, see `set_encoding_preferences`.
+    pub encoding_preferences: Vec<(BodyEncoding, CompressionLevel)>,
+    pub digests: Vec<DigestAlgorithm>,
+}
+
+#[derive(Debug)]
+pub enum ResponseError {
+    AlreadyResponded,
+    /// The response cannot be sent as is (e.g. an `Upgrade` header without a `101` status).
+    Invalid(String),
+    /// The body could not be compressed with the negotiated encoding.
+    Encoding(BodyEncoding, IoError),
+    /// Writing the response to the connection failed.
+    Io(IoError),
+}
+
+impl Display for ResponseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ResponseError::AlreadyResponded => write!(f, "Request already responded"),
+            ResponseError::Invalid(reason) => write!(f, "Invalid response: {}", reason),
+            ResponseError::Encoding(encoding, err) => {
+                write!(
+                    f,
+                    "Failed to encode body with {}: {}",
+                    encoding.to_string(),
+                    err
+                )
+            }
+            ResponseError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResponseError::Encoding(_, err) | ResponseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for ResponseError {
+    fn from(err: IoError) -> Self {
+        ResponseError::Io(err)
+    }
+}
+
+impl From<ResponseError> for IoError {
+    fn from(err: ResponseError) -> Self {
+        match err {
+            ResponseError::Io(err) => err,
+            err => IoError::other(err),
+        }
+    }
+}
+
+/// How a fronting proxy is told to send a file itself, see `Response::internal_redirect`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SendfileStyle {
+    /// nginx `X-Accel-Redirect`, the path is a URI matched against an `internal` location.
+    XAccelRedirect,
+    /// Apache (mod_xsendfile) and lighttpd `X-Sendfile`, the path is a file on disk.
+    XSendfile,
+}
+
+impl SendfileStyle {
+    pub fn header_name(&self) -> &'static str {
+        match self {
+            SendfileStyle::XAccelRedirect => "X-Accel-Redirect",
+            SendfileStyle::XSendfile => "X-Sendfile",
+        }
+    }
+}
+
+/// Which `Server` header responses carry.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ServerHeaderPolicy {
+    /// `JulMan-Http/<crate version>`, unless the handler set its own.
+    #[default]
+    Default,
+    /// A custom value, unless the handler set its own.
+    Custom(String),
+    /// Never send a `Server` header, even one set by the handler.
+    Off,
+}
+
+/// Headers the server adds to (or strips from) every response when it is
+/// serialized, on top of what the handler set.
+#[derive(Debug, Clone)]
+pub struct ResponseDefaults {
+    pub server_header: ServerHeaderPolicy,
+    /// Bodies smaller than this are never compressed.
+    pub compression_threshold: usize,
+    /// Size of the internal buffer of the brotli encoder.
+    pub brotli_buffer_size: usize,
+    pub max_ranges: usize,
+    /// See `ServerOptions::security_headers`.
+    pub security_headers: bool,
+    /// Advertised in `Keep-Alive`, see `ServerOptions::keep_alive_timeout`.
+    pub keep_alive_timeout: Option<Duration>,
+    /// See `ServerOptions::keep_alive_max_requests`.
+    pub keep_alive_max_requests: Option<usize>,
+    /// See `ServerOptions::debug_headers`.
+    pub debug_headers: bool,
+}
+
+impl Default for ResponseDefaults {
+    fn default() -> Self {
+        ResponseDefaults {
+            server_header: ServerHeaderPolicy::Default,
+            compression_threshold: 256,
+            brotli_buffer_size: 32 * 1024,
+            max_ranges: 16,
+            security_headers: false,
+            keep_alive_timeout: None,
+            keep_alive_max_requests: None,
+            debug_headers: false,
+        }
+    }
+}
+
+impl ResponseDefaults {
+    pub fn apply(&self, headers: &mut Vec<Header>) {
+        let has_server = headers.iter().any(|h| matches!(h, Header::Server(_)));
+
+        match self.server_header {
+            ServerHeaderPolicy::Default if !has_server => headers.push(Header::Server(format!(
+                "JulMan-Http/{}",
+                env!("CARGO_PKG_VERSION")
+            ))),
+            ServerHeaderPolicy::Custom(ref server) if !has_server => {
+                headers.push(Header::Server(server.clone()))
+            }
+            ServerHeaderPolicy::Off => headers.retain(|h| !matches!(h, Header::Server(_))),
+            _ => {}
+        }
+
+        if !headers.iter().any(|h| matches!(h, Header::Date(_))) {
+            headers.push(Header::Date(DATE_CACHE.now()));
+        }
+
+        if self.security_headers {
+            for (name, value) in [
+                ("X-Content-Type-Options", "nosniff"),
+                ("X-Frame-Options", "SAMEORIGIN"),
+            ] {
+                if !headers.iter().any(|h| h.name().eq_ignore_ascii_case(name)) {
+                    headers.push(Header::Unknown(name.to_string(), value.to_string()));
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -65,12 +228,80 @@ impl CompressionLevel {
     }
 }
 
-fn push_str(vec: &mut Vec<u8>, data: &String) {
-    for c in data.chars() {
-        vec.push(c as u8);
+/// What compressing a response body took and gave, see `Response::to_bytes_with_stats`.
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionStats {
+    pub encoding: BodyEncoding,
+    pub level: u32,
+    /// Size of the body before compression.
+    pub original_size: usize,
+    pub compressed_size: usize,
+    /// Time spent compressing.
+    pub duration: Duration,
+}
+
+impl CompressionStats {
+    /// Compressed size over original size, 1 for an empty body.
+    pub fn ratio(&self) -> f64 {
+        if self.original_size == 0 {
+            return 1.0;
+        }
+
+        self.compressed_size as f64 / self.original_size as f64
+    }
+
+    /// `br;level=4;ratio=0.31;ms=2`, the value of the `X-Compression` debug header.
+    pub fn header_value(&self) -> String {
+        format!(
+            "{};level={};ratio={:.2};ms={}",
+            self.encoding.to_string(),
+            self.level,
+            self.ratio(),
+            self.duration.as_millis()
+        )
     }
 }
 
+fn push_str(vec: &mut Vec<u8>, data: &str) {
+    vec.extend_from_slice(data.as_bytes());
+}
+
+/// The path and query of the request line, as received: `uri.path` is decoded.
+/// The absolute form (`http://host/path`) loses its scheme and host.
+fn raw_path_and_query(request: &Request) -> String {
+    let head = String::from_utf8_lossy(&request.head_raw);
+    let line = head.lines().next().unwrap_or_default();
+    let target = line.split(' ').nth(1).unwrap_or("/");
+    let target = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |index| &rest[index..]),
+        None => target,
+    };
+
+    target.to_string()
+}
+
+/// Compress `data` with `encoding`, at the fast level unless `level` is given.
+fn encode(
+    data: &[u8],
+    encoding: BodyEncoding,
+    level: Option<CompressionLevel>,
+    brotli_buffer_size: usize,
+) -> Result<(Vec<u8>, CompressionStats), IoError> {
+    let level = level.unwrap_or(CompressionLevel::fast());
+    let start = Instant::now();
+    let encoded = encoding::compress_buffered(data, encoding, level, brotli_buffer_size)?;
+
+    let stats = CompressionStats {
+        encoding,
+        level: level.level(),
+        original_size: data.len(),
+        compressed_size: encoded.len(),
+        duration: start.elapsed(),
+    };
+
+    Ok((encoded, stats))
+}
+
 impl Response {
     pub fn new(
         status: Status,
@@ -83,6 +314,8 @@ impl Response {
             headers,
             body,
             encoding,
+            encoding_preferences: vec![],
+            digests: vec![],
         }
     }
 
@@ -92,9 +325,63 @@ impl Response {
             headers: Vec::new(),
             body: ResponseBody::None,
             encoding: (None, None),
+            encoding_preferences: vec![],
+            digests: vec![],
         }
     }
 
+    /// `value` as an `application/json` body.
+    pub fn json_value(value: Value) -> Response {
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Text(value.to_string()))
+            .set_content_type(Mime::application("json"));
+
+        response
+    }
+
+    /// `problem` as an `application/problem+json` body, with its status.
+    pub fn problem(problem: Problem) -> Response {
+        let mut response = Response::empty();
+
+        response
+            .set_status(problem.status.clone())
+            .set_body(ResponseBody::Text(problem.to_string()))
+            .set_content_type(Mime::application("problem+json"));
+
+        response
+    }
+
+    /// A file a user uploaded, as a download that the browser never renders:
+    /// `attachment` with `filename` sanitized, `X-Content-Type-Options: nosniff`,
+    /// and a type from `DownloadPolicy::default`.
+    ///
+    /// Read a file with `std::fs::read` first.
+    pub fn safe_download(body: Vec<u8>, filename: &str) -> Response {
+        Response::safe_download_with(body, filename, &DownloadPolicy::default())
+    }
+
+    /// Like `safe_download`, with the types of `policy`.
+    pub fn safe_download_with(body: Vec<u8>, filename: &str, policy: &DownloadPolicy) -> Response {
+        let filename = download::sanitize_filename(filename);
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Binary(body))
+            .set_content_type(policy.content_type(&filename))
+            .add_header(Header::Unknown(
+                "Content-Disposition".to_string(),
+                download::attachment_disposition(&filename),
+            ))
+            .add_header(Header::Unknown(
+                "X-Content-Type-Options".to_string(),
+                "nosniff".to_string(),
+            ));
+
+        response
+    }
+
     pub fn redirect(target: String, status: Option<Status>) -> Response {
         let mut response = Response::empty();
 
@@ -107,71 +394,308 @@ impl Response {
         response
     }
 
+    /// Same as `redirect`, but `target` must pass `policy` first.
+    ///
+    /// `request` is the request being answered, used to detect a redirect to itself.
+    pub fn redirect_checked(
+        target: String,
+        status: Option<Status>,
+        policy: &RedirectPolicy,
+        request: &Request,
+    ) -> Result<Response, RedirectError> {
+        policy.check(&target, Some(request))?;
+
+        Ok(Response::redirect(target, status))
+    }
+
+    /// Redirect to the page the client came from (`Referer` header) when it passes
+    /// `policy`, to `fallback` otherwise.
+    pub fn redirect_back(request: &Request, fallback: String, policy: &RedirectPolicy) -> Response {
+        let target = match request.get_header("Referer") {
+            Some(Header::Referer(referer)) if policy.check(referer, Some(request)).is_ok() => {
+                referer.clone()
+            }
+            _ => fallback,
+        };
+
+        Response::redirect(target, None)
+    }
+
+    /// Send `request` to the same path and query over HTTPS, for the plain HTTP side
+    /// of a server also reachable with TLS. The port of `Host` is dropped.
+    ///
+    /// A client asking for the upgrade (`Request::wants_https_upgrade`) gets a
+    /// `307 Temporary Redirect`, which keeps the method and body, with
+    /// `Vary: Upgrade-Insecure-Requests`; others get a `301 Moved Permanently`.
+    pub fn https_redirect(request: &Request) -> Response {
+        let host = match request.get_header("Host") {
+            Some(Header::Host(host)) => host.as_str(),
+            _ => request.uri.host.as_str(),
+        };
+        let host = origin::split_host_port(host).map_or_else(|| host.to_string(), |(host, _)| host);
+        let target = format!("https://{}{}", host, raw_path_and_query(request));
+
+        if !request.wants_https_upgrade() {
+            return Response::redirect(target, Some(Status::MovedPermanently));
+        }
+
+        let mut response = Response::redirect(target, Some(Status::TemporaryRedirect));
+
+        response.add_header(Header::Vary(vec!["Upgrade-Insecure-Requests".to_string()]));
+
+        response
+    }
+
+    /// A `301 Moved Permanently` to the same scheme, path and query on `host`
+    /// (`host[:port]`), see `HostCanonicalization`.
+    pub fn host_redirect(request: &Request, host: &str) -> Response {
+        let target = format!(
+            "{}://{}{}",
+            request.uri.scheme,
+            host,
+            raw_path_and_query(request)
+        );
+
+        Response::redirect(target, Some(Status::MovedPermanently))
+    }
+
+    /// Serialize the response for `request`.
+    ///
+    /// If the negotiated `Content-Encoding` fails, the response is sent
+    /// without it (identity body and matching headers), and `Unknown` headers
+    /// with an invalid name are left out; use `to_bytes` to get the error instead.
+    /// A response failing the `Upgrade` checks of `to_bytes` is replaced by a
+    /// `500 Internal Server Error`.
     pub fn to_vector(&self, request: &Request) -> Vec<u8> {
-        let mut headers: Vec<Header> = vec![];
+        match self.to_bytes(request) {
+            Ok(bytes) => bytes,
+            // Nothing to leave out to fix these, the handler got it wrong
+            Err(_) if self.check_upgrade().is_err() => {
+                let mut response = Response::empty();
 
-        for header in self.headers.iter() {
-            headers.push(header.clone());
+                response.set_status(Status::InternalServerError);
+                response.serialize(request, None)
+            }
+            Err(_) => self.serialize(request, None),
         }
+    }
 
-        let has_content_length = headers.iter().any(|h| match h {
-            Header::ContentLength(_) => true,
+    /// Serialize the response for `request`, failing if the body cannot be
+    /// encoded with the negotiated `Content-Encoding`, if an `Unknown` header
+    /// has an invalid name, or if only one of an `Upgrade` header and a
+    /// `101 Switching Protocols` status is set.
+    pub fn to_bytes(&self, request: &Request) -> Result<Vec<u8>, ResponseError> {
+        Ok(self.to_bytes_with_stats(request)?.0)
+    }
+
+    /// Like `to_bytes`, along what compressing the body took when it was. With
+    /// `ServerOptions::debug_headers`, they are also sent as an `X-Compression`
+    /// header, see `CompressionStats::header_value`.
+    pub fn to_bytes_with_stats(
+        &self,
+        request: &Request,
+    ) -> Result<(Vec<u8>, Option<CompressionStats>), ResponseError> {
+        self.check_upgrade()?;
+
+        if let Some(Header::Unknown(name, _)) = self
+            .headers
+            .iter()
+            .find(|h| matches!(h, Header::Unknown(name, _) if !Header::is_valid_name(name)))
+        {
+            return Err(ResponseError::Invalid(format!(
+                "Invalid header name: {:?}",
+                name
+            )));
+        }
+
+        let accepted = |encoding: &BodyEncoding| match request.get_header("accept-encoding") {
+            Some(Header::AcceptEncoding(ref accept_encodings)) => accept_encodings.accept(encoding),
             _ => false,
-        });
+        };
 
-        if !has_content_length {
-            match &self.body {
-                ResponseBody::Text(ref text) => {
-                    headers.push(Header::ContentLength(text.len() as u64))
-                }
-                ResponseBody::Binary(ref vec) => {
-                    headers.push(Header::ContentLength(vec.len() as u64))
+        // The server order wins among the encodings the client accepts, whatever their q
+        let chosen = match self.encoding {
+            (Some(encoding), level) if self.encoding_preferences.is_empty() => {
+                Some((encoding, level)).filter(|(encoding, _)| accepted(encoding))
+            }
+            _ => self
+                .encoding_preferences
+                .iter()
+                .find(|(encoding, _)| accepted(encoding))
+                .map(|(encoding, level)| (*encoding, Some(*level))),
+        };
+
+        let encoding = match chosen {
+            Some((encoding, level)) => {
+                let data = self.body_bytes();
+
+                if data.len() >= request.defaults.compression_threshold {
+                    match encode(&data, encoding, level, request.defaults.brotli_buffer_size) {
+                        Ok(encoded) => Some(encoded),
+                        Err(err) => return Err(ResponseError::Encoding(encoding, err)),
+                    }
+                } else {
+                    None
                 }
-                _ => {}
-            };
-        }
-
-        let support_encoding = if let (Some(encoding), _) = self.encoding {
-            let support_encoding = {
-                let accept_encodings = request.get_header("accept-encoding");
-
-                match accept_encodings {
-                    Some(ref accept_encodings) => match accept_encodings {
-                        Header::AcceptEncoding(ref accept_encodings) => {
-                            accept_encodings.accept(&encoding)
-                        }
-                        _ => false,
-                    },
-                    None => false,
+            }
+            None => None,
+        };
+
+        let stats = encoding.as_ref().map(|(_, stats)| *stats);
+
+        Ok((self.serialize(request, encoding), stats))
+    }
+
+    /// An `Upgrade` header is only allowed on a `101 Switching Protocols`, which
+    /// must name the protocol switched to with one (RFC 7230 §6.7).
+    fn check_upgrade(&self) -> Result<(), ResponseError> {
+        match (self.status.code(), self.get_header("Upgrade").is_some()) {
+            (101, false) => Err(ResponseError::Invalid(
+                "101 Switching Protocols response without an Upgrade header".to_string(),
+            )),
+            (101, true) | (_, false) => Ok(()),
+            (_, true) => Err(ResponseError::Invalid(
+                "Upgrade header is only allowed on a 101 Switching Protocols response".to_string(),
+            )),
+        }
+    }
+
+    /// The body as bytes, before any compression.
+    pub fn body_bytes(&self) -> Vec<u8> {
+        match &self.body {
+            ResponseBody::Text(text) => text.as_bytes().to_vec(),
+            ResponseBody::Binary(vec) => vec.clone(),
+            ResponseBody::None => vec![],
+        }
+    }
+
+    /// Build the response bytes, with `encoded` the already encoded body if any.
+    fn serialize(
+        &self,
+        request: &Request,
+        encoded: Option<(Vec<u8>, CompressionStats)>,
+    ) -> Vec<u8> {
+        let mut headers: Vec<Header> = self.headers.clone();
+
+        // Never emit a header name that could be read as something else downstream
+        headers.retain(|h| !matches!(h, Header::Unknown(name, _) if !Header::is_valid_name(name)));
+
+        let data = match encoded {
+            Some((data, stats)) => {
+                // A length set by the handler is the one of the identity body
+                headers.retain(|h| !matches!(h, Header::ContentLength(_)));
+                headers.push(Header::ContentEncoding(vec![stats.encoding]));
+
+                if request.defaults.debug_headers {
+                    headers.push(Header::Unknown(
+                        "X-Compression".to_string(),
+                        stats.header_value(),
+                    ));
                 }
-            };
 
-            if support_encoding {
-                headers.push(Header::ContentEncoding(vec![encoding]));
+                data
             }
+            None => self.body_bytes(),
+        };
 
-            support_encoding
-        } else {
-            false
+        // Compressed or not depending on `Accept-Encoding`, which caches must key on
+        let negotiated = self.encoding.0.is_some() || !self.encoding_preferences.is_empty();
+        let body_size = match &self.body {
+            ResponseBody::Text(text) => text.len(),
+            ResponseBody::Binary(vec) => vec.len(),
+            ResponseBody::None => 0,
         };
 
+        if negotiated && body_size >= request.defaults.compression_threshold {
+            headers.push(Header::Vary(vec!["Accept-Encoding".to_string()]));
+        }
+
+        // A length or chunks, or a body ending with the connection, as `respond_head` streams
+        let has_framing = headers.iter().any(|h| {
+            matches!(
+                h,
+                Header::ContentLength(_)
+                    | Header::TransferEncoding(_)
+                    | Header::Connection(Connection::Close)
+            )
+        });
+        // Without a body, the length still goes where one could be, or a kept-alive
+        // client could not find the end of the response (RFC 7230 §3.3.3)
+        let bodiless = matches!(request.method, Method::Head)
+            || matches!(self.status.code(), 100..=199 | 204 | 304);
+
+        if !has_framing && (!matches!(self.body, ResponseBody::None) || !bodiless) {
+            headers.push(Header::ContentLength(data.len() as u64));
+        }
+
+        for algorithm in self.digests.iter() {
+            let digest = Digest::new(*algorithm, &data);
+
+            headers.push(match algorithm {
+                DigestAlgorithm::Md5 => Header::ContentMd5(digest.value),
+                _ => Header::ReprDigest(vec![digest]),
+            });
+        }
+
+        request.defaults.apply(&mut headers);
+
+        // Whether the connection stays open, unless the handler already said
+        if !headers.iter().any(|h| matches!(h, Header::Connection(_))) {
+            headers.push(Header::Connection(if request.keep_alive {
+                Connection::KeepAlive
+            } else {
+                Connection::Close
+            }));
+
+            // How long the connection waits and how many more requests it takes
+            let defaults = &request.defaults;
+
+            if let (true, Some(timeout)) = (request.keep_alive, defaults.keep_alive_timeout) {
+                let max = defaults
+                    .keep_alive_max_requests
+                    .map(|max| max.saturating_sub(request.connection_requests));
+
+                headers.push(Header::KeepAlive(timeout, max));
+            }
+        }
+
+        // HTTP/1.0 caches only know `Pragma: no-cache` (RFC 7234 §5.4)
+        if matches!(request.version, Version::Http10)
+            && !headers.iter().any(|h| matches!(h, Header::Pragma(_)))
+            && headers.iter().any(|h| match h {
+                Header::CacheControl(cache) => cache
+                    .iter()
+                    .any(|c| matches!(c, Cache::NoCache | Cache::NoStore)),
+                _ => false,
+            })
+        {
+            headers.push(Header::Pragma(Cache::NoCache));
+        }
+
+        for name in LIST_HEADERS {
+            Header::merge_all(&mut headers, name);
+        }
+
         headers.sort_by(|a, b| a.name().cmp(&b.name()));
 
         let mut response: Vec<u8> = vec![];
 
-        push_str(
-            &mut response,
-            &format!(
-                "{} {}\r\n",
-                request.version.to_string(),
-                self.status.to_string()
-            ),
-        );
-        for header in &headers {
-            push_str(&mut response, &format!("{}", header.to_string()));
+        // HTTP/0.9 clients only read the body, up to the end of the connection
+        if request.version != Version::Http09 {
+            push_str(
+                &mut response,
+                &format!(
+                    "{} {}\r\n",
+                    request.version.to_string(),
+                    self.status.to_string()
+                ),
+            );
+            for header in &headers {
+                push_str(&mut response, &header.to_string());
+            }
+            push_str(&mut response, "\r\n");
         }
-        push_str(&mut response, &"\r\n".to_string());
-    
+
         let should_print_body = match (&request.method, &self.status) {
             (Method::Head, _) => false,
             (_, Status::NoContent) => false,
@@ -181,82 +705,7 @@ impl Response {
         };
 
         if should_print_body {
-            let mut data = match &self.body {
-                ResponseBody::Text(text) => text.chars().map(|c| c as u8).collect::<Vec<_>>(),
-                ResponseBody::Binary(vec) => vec.clone(),
-                ResponseBody::None => vec![],
-            };
-
-            if support_encoding {
-                if let (Some(encoding), level) = self.encoding {
-                    let res = match (encoding, level) {
-                        (BodyEncoding::Gzip, l) => {
-                            let level = Compression::new(
-                                (match l {
-                                    Some(l) => l,
-                                    None => CompressionLevel::fast(),
-                                })
-                                .level(),
-                            );
-
-                            let mut encoder: GzEncoder<Vec<u8>> = GzEncoder::new(Vec::new(), level);
-
-                            if let Ok(_) = encoder.write(&data) {
-                                encoder.finish()
-                            } else {
-                                Err(IoError::new(ErrorKind::Other, ""))
-                            }
-                        }
-                        (BodyEncoding::Deflate, l) => {
-                            let level = Compression::new(
-                                (match l {
-                                    Some(l) => l,
-                                    None => CompressionLevel::fast(),
-                                })
-                                .level(),
-                            );
-
-                            let mut encoder: DeflateEncoder<Vec<u8>> =
-                                DeflateEncoder::new(Vec::new(), level);
-
-                            if let Ok(_) = encoder.write(&data) {
-                                encoder.finish()
-                            } else {
-                                Err(IoError::new(ErrorKind::Other, ""))
-                            }
-                        }
-                        (BodyEncoding::Brotli, l) => {
-                            let level = (match l {
-                                Some(l) => l,
-                                None => CompressionLevel::fast(),
-                            })
-                            .level();
-
-                            let mut reader =
-                                CompressorReader::new(data.as_slice(), data.len(), level, 20);
-                            let mut buf = Vec::new();
-
-                            if let Ok(_) = reader.read_to_end(&mut buf) {
-                                Ok(buf)
-                            } else {
-                                Err(IoError::new(ErrorKind::Other, ""))
-                            }
-                        }
-                    };
-
-                    if let Ok(new_data) = res {
-                        data.clear();
-
-                        for d in new_data {
-                            data.push(d);
-                        }
-                    }
-                }
-            }
-
-            for u in data {
-                response.push(u);
-            }
+            response.extend_from_slice(&data);
         }
         response
     }
@@ -285,12 +734,256 @@ impl Response {
         self
     }
 
+    pub fn get_header(&self, name: &str) -> Option<&Header> {
+        self.headers
+            .iter()
+            .find(|header| header.name().to_lowercase() == name.to_lowercase())
+    }
+
+    pub fn remove_header(&mut self, name: &str) -> &mut Self {
+        self.headers
+            .retain(|header| header.name().to_lowercase() != name.to_lowercase());
+
+        self
+    }
+
+    /// Add a digest of the body, computed just before serialization over the
+    /// representation as it is sent (after any `Content-Encoding`).
+    ///
+    /// `Md5` is sent as `Content-MD5`, the other algorithms as `Repr-Digest`.
+    pub fn add_digest(&mut self, algorithm: DigestAlgorithm) -> &mut Self {
+        if !self.digests.contains(&algorithm) {
+            self.digests.push(algorithm);
+        }
+
+        self
+    }
+
+    /// Merge every `name` header of the response into a single comma separated line.
+    ///
+    /// This is done automatically when serializing for the headers listed in
+    /// `LIST_HEADERS`; use it to merge other headers known to be lists.
+    pub fn merge_list_header(&mut self, name: &str) -> &mut Self {
+        Header::merge_all(&mut self.headers, name);
+
+        self
+    }
+
+    /// Decline a protocol upgrade asked by the client (`Upgrade: h2c`, `websocket`, ...).
+    ///
+    /// The response keeps going through the normal path: any `101 Switching Protocols`
+    /// status is replaced by `200 OK` and the `Upgrade` header is removed.
+    pub fn upgrade_declined(&mut self) -> &mut Self {
+        if self.status.code() == 101 {
+            self.status = Status::Ok;
+        }
+
+        self.remove_header("Upgrade")
+    }
+
+    /// Add `Cache-Control: no-cache`, plus `Pragma: no-cache` for HTTP/1.0 clients.
+    pub fn no_cache(&mut self) -> &mut Self {
+        self.headers
+            .push(Header::CacheControl(vec![Cache::NoCache]));
+
+        self
+    }
+
+    /// Add `Cache-Control: no-store`, plus `Pragma: no-cache` for HTTP/1.0 clients.
+    pub fn no_store(&mut self) -> &mut Self {
+        self.headers
+            .push(Header::CacheControl(vec![Cache::NoStore]));
+
+        self
+    }
+
+    /// Set `Accept-Ranges: bytes`, replacing any other `Accept-Ranges`.
+    pub fn advertise_ranges(&mut self) -> &mut Self {
+        self.remove_header("Accept-Ranges")
+            .add_header(Header::AcceptRanges(vec![RangeUnit::Bytes]))
+    }
+
+    /// Answer the `Range` header of `request` from the body: `206 Partial Content`
+    /// with a single range or a `multipart/byteranges` body for several of them,
+    /// `416 Range Not Satisfiable` when none can be served.
+    ///
+    /// Call it once the full `200 OK` body is set; other responses and requests
+    /// without a usable `Range` only get `Accept-Ranges: bytes`. Partial bodies
+    /// are never compressed, ranges apply to the identity body.
+    pub fn ranged(&mut self, request: &Request) -> &mut Self {
+        self.advertise_ranges();
+
+        let raw = match request.get_header("Range") {
+            Some(Header::Range(raw)) if request.method == Method::Get => raw,
+            _ => return self,
+        };
+
+        if !matches!(self.status, Status::Ok) {
+            return self;
+        }
+
+        let data = self.body_bytes();
+        let length = data.len() as u64;
+
+        let ranges = match RangeRequest::parse(raw, length, request.defaults.max_ranges) {
+            RangeRequest::Ignore => return self,
+            RangeRequest::Unsatisfiable => {
+                self.remove_header("Content-Length")
+                    .set_status(Status::RequestedRangeNotSatisfiable)
+                    .set_body(ResponseBody::Binary(vec![]))
+                    .add_header(Header::ContentRange(ContentRange {
+                        range: None,
+                        length,
+                    }));
+                self.encoding.0 = None;
+                self.encoding_preferences.clear();
+
+                return self;
+            }
+            RangeRequest::Satisfiable(ranges) => ranges,
+        };
+
+        self.remove_header("Content-Length")
+            .set_status(Status::PartialContent);
+        self.encoding.0 = None;
+        self.encoding_preferences.clear();
+
+        if let [range] = ranges[..] {
+            return self
+                .set_body(ResponseBody::Binary(
+                    data[(range.start as usize)..=(range.end as usize)].to_vec(),
+                ))
+                .add_header(Header::ContentRange(ContentRange {
+                    range: Some(range),
+                    length,
+                }));
+        }
+
+        let content_type = match self.get_header("Content-Type") {
+            Some(Header::ContentType(mime)) => Some(mime.clone()),
+            _ => None,
+        };
+        let boundary = range::boundary();
+
+        self.remove_header("Content-Type")
+            .set_body(ResponseBody::Binary(range::multipart(
+                &data,
+                &ranges,
+                content_type.as_ref(),
+                &boundary,
+            )))
+            .set_content_type(Mime::new(
+                "multipart".to_string(),
+                "byteranges".to_string(),
+                Some(("boundary".to_string(), boundary)),
+            ))
+    }
+
+    /// Hand the transfer of `path` to a fronting proxy (nginx, Apache): the header
+    /// of `style` is set and the body is cleared, the proxy sending the file instead.
+    ///
+    /// The empty body is sent with `Content-Length: 0`, and `Content-Type` only if the
+    /// handler set one, the proxy replacing both with those of the file. Nothing serves
+    /// `path` when the server is reached directly, the client gets an empty response.
+    pub fn internal_redirect(
+        &mut self,
+        path: &str,
+        style: SendfileStyle,
+    ) -> Result<&mut Self, ResponseError> {
+        if path.is_empty() || path.contains(['\r', '\n']) {
+            return Err(ResponseError::Invalid(format!(
+                "Invalid internal redirect path: {:?}",
+                path
+            )));
+        }
+
+        self.remove_header("Content-Length")
+            .remove_header(style.header_name())
+            .set_body(ResponseBody::None)
+            .add_header(Header::Sendfile(style, path.to_string()));
+        self.encoding.0 = None;
+        self.encoding_preferences.clear();
+
+        Ok(self)
+    }
+
+    /// Mark the response as the version of a resource at `datetime`, chosen with
+    /// `Request::select_memento`.
+    pub fn memento(&mut self, datetime: HttpDate) -> &mut Self {
+        self.headers.push(Header::MementoDatetime(datetime));
+        self.headers
+            .push(Header::Vary(vec!["Accept-Datetime".to_string()]));
+
+        self
+    }
+
+    /// Choose the charset of the response among `available` from the client's
+    /// `Accept-Charset`, the first one winning when it has no preference, and
+    /// set it on the `Content-Type` (`text/plain` if none is set yet) along a
+    /// `Vary: Accept-Charset`. The body is for the caller to encode, e.g. with
+    /// `charset::encode`.
+    ///
+    /// When none is acceptable, the error is a ready to send `406 Not Acceptable`
+    /// listing the available charsets.
+    #[allow(clippy::result_large_err)]
+    pub fn negotiate_charset(
+        &mut self,
+        request: &Request,
+        available: &[&str],
+    ) -> Result<String, Response> {
+        let vary = Header::Vary(vec!["Accept-Charset".to_string()]);
+
+        let charset = match request.accept_charset().negotiate(available) {
+            Some(charset) => charset,
+            None => {
+                let mut response = Response::empty();
+
+                response
+                    .set_body(ResponseBody::Text(format!(
+                        "{}\nAvailable charsets: {}\n",
+                        Status::NotAcceptable.to_string(),
+                        available.join(", ")
+                    )))
+                    .set_status(Status::NotAcceptable)
+                    .set_content_type(Mime::text("plain"))
+                    .add_header(vary);
+
+                return Err(response);
+            }
+        };
+
+        let content_type = match self.get_header("Content-Type") {
+            Some(Header::ContentType(mime)) => mime.clone(),
+            _ => Mime::text("plain"),
+        };
+
+        self.remove_header("Content-Type")
+            .set_content_type(content_type.with_charset(&charset))
+            .add_header(vary);
+
+        Ok(charset)
+    }
+
     pub fn set_content_type(&mut self, content_type: Mime) -> &mut Self {
         self.headers.push(Header::ContentType(content_type));
 
         self
     }
 
+    /// Encodings to use in this order, the first one the client accepts (q > 0)
+    /// being used, identity when none is. The server order wins over the client
+    /// q-values, `gzip;q=1, br;q=0.5` still gets brotli if it comes first here.
+    ///
+    /// Takes over `set_body_encoding` when not empty.
+    pub fn set_encoding_preferences(
+        &mut self,
+        preferences: &[(BodyEncoding, CompressionLevel)],
+    ) -> &mut Self {
+        self.encoding_preferences = preferences.to_vec();
+
+        self
+    }
+
     /// Set the content encoding for the response.
     /// 
     /// Note: It will only set the encoding if the client 
@@ -305,3 +998,829 @@ impl Response {
         self
     }
 }
+
+/// How the body written through a `ResponseBodyWriter` is delimited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyFraming {
+    /// Exactly this many bytes remain, as declared by `Content-Length`.
+    Length(u64),
+    /// `Transfer-Encoding: chunked`.
+    Chunked,
+    /// No body may be sent (answer to a `HEAD` request), writes are dropped.
+    Discard,
+    /// The body ends with the connection, for clients without chunked encoding.
+    Close,
+}
+
+/// The body of a response whose head was already sent, see `Request::respond_head`.
+///
+/// Dropping the writer finishes the body, ignoring errors; call `finish` to get them.
+#[derive(Debug)]
+pub struct ResponseBodyWriter<'a> {
+    stream: &'a mut TcpStream,
+    framing: BodyFraming,
+    finished: bool,
+    /// Where the bytes written are counted, chunk framing included.
+    sent: Option<&'a mut usize>,
+}
+
+impl<'a> ResponseBodyWriter<'a> {
+    pub fn new(stream: &'a mut TcpStream, framing: BodyFraming) -> ResponseBodyWriter<'a> {
+        ResponseBodyWriter {
+            stream,
+            framing,
+            finished: false,
+            sent: None,
+        }
+    }
+
+    /// Like `new`, adding the bytes written to `sent`, see `Request::bytes`.
+    pub fn counted(
+        stream: &'a mut TcpStream,
+        framing: BodyFraming,
+        sent: &'a mut usize,
+    ) -> ResponseBodyWriter<'a> {
+        ResponseBodyWriter {
+            stream,
+            framing,
+            finished: false,
+            sent: Some(sent),
+        }
+    }
+
+    /// Write `bytes` to the connection as is, counting them.
+    fn send(&mut self, bytes: &[u8]) -> Result<(), IoError> {
+        self.stream.write_all(bytes)?;
+
+        if let Some(ref mut sent) = self.sent {
+            **sent += bytes.len();
+        }
+
+        Ok(())
+    }
+
+    pub fn framing(&self) -> BodyFraming {
+        self.framing
+    }
+
+    /// End the body: the last chunk for chunked bodies, closing the connection
+    /// for close-delimited ones, an error if fewer bytes than the declared
+    /// `Content-Length` were written.
+    pub fn finish(mut self) -> Result<(), IoError> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Result<(), IoError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        match self.framing {
+            BodyFraming::Length(0) | BodyFraming::Discard => Ok(()),
+            BodyFraming::Close => {
+                self.stream.flush()?;
+                self.stream.shutdown(Shutdown::Write)
+            }
+            BodyFraming::Length(remaining) => {
+                // The client would read the next response as the end of this body
+                let _ = self.stream.shutdown(Shutdown::Both);
+
+                Err(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("{} bytes short of the declared Content-Length", remaining),
+                ))
+            }
+            BodyFraming::Chunked => {
+                self.send(b"0\r\n\r\n")?;
+                self.stream.flush()
+            }
+        }
+    }
+}
+
+impl Write for ResponseBodyWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        if self.finished {
+            return Err(IoError::other("Response body already finished"));
+        }
+
+        match self.framing {
+            BodyFraming::Length(remaining) => {
+                if buf.len() as u64 > remaining {
+                    return Err(IoError::new(
+                        ErrorKind::InvalidInput,
+                        "Body longer than the declared Content-Length",
+                    ));
+                }
+
+                self.send(buf)?;
+                self.framing = BodyFraming::Length(remaining - buf.len() as u64);
+            }
+            // An empty chunk would end the body
+            BodyFraming::Chunked if buf.is_empty() => {}
+            BodyFraming::Chunked => {
+                let mut chunk = format!("{:x}\r\n", buf.len()).into_bytes();
+
+                chunk.extend_from_slice(buf);
+                chunk.extend_from_slice(b"\r\n");
+
+                self.send(&chunk)?;
+            }
+            BodyFraming::Discard => {}
+            BodyFraming::Close => self.send(buf)?,
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.stream.flush()
+    }
+}
+
+impl Drop for ResponseBodyWriter<'_> {
+    fn drop(&mut self) {
+        let _ = self.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::handle_connection;
+    use crate::server::ServerOptions;
+    use std::net::{Shutdown, TcpListener};
+
+    /// A `GET /` request with `headers`, read over a loopback connection.
+    fn request(headers: &str) -> Request {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        write!(
+            client,
+            "GET / HTTP/1.1\r\nHost: localhost\r\n{}\r\n",
+            headers
+        )
+        .unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        handle_connection(listener.accept().unwrap().0, &ServerOptions::default()).unwrap()
+    }
+
+    /// The `Content-Encoding` the response is sent with to a client sending `accept`.
+    fn chosen_encoding(response: &Response, accept: &str) -> Option<String> {
+        let request = request(&format!("Accept-Encoding: {}\r\n", accept));
+        let bytes = response.to_bytes(&request).unwrap();
+        let head = String::from_utf8_lossy(&bytes);
+
+        head.lines()
+            .find_map(|line| line.strip_prefix("Content-Encoding: "))
+            .map(str::to_string)
+    }
+
+    #[test]
+    fn encoding_from_server_preferences() {
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Text("compressible ".repeat(100)))
+            .set_encoding_preferences(&[
+                (BodyEncoding::Brotli, CompressionLevel::fast()),
+                (BodyEncoding::Gzip, CompressionLevel::fast()),
+            ]);
+
+        let cases = [
+            ("gzip, deflate, br", Some("br")),
+            ("gzip", Some("gzip")),
+            ("deflate", None),
+            ("br;q=0, gzip", Some("gzip")),
+            // The server order wins among the accepted ones, whatever their q
+            ("br;q=0.1, gzip;q=1", Some("br")),
+            ("*", Some("br")),
+            ("*, br;q=0", Some("gzip")),
+            ("identity", None),
+        ];
+
+        for (accept, expected) in cases {
+            assert_eq!(
+                chosen_encoding(&response, accept).as_deref(),
+                expected,
+                "Accept-Encoding: {}",
+                accept
+            );
+        }
+    }
+
+    #[test]
+    fn negotiated_encodings_vary() {
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Text("compressible ".repeat(100)))
+            .add_header(Header::Vary(vec!["Origin".to_string()]))
+            .set_encoding_preferences(&[(BodyEncoding::Gzip, CompressionLevel::fast())]);
+
+        // Compressed or not, the response depends on Accept-Encoding
+        for accept in ["gzip", "identity"] {
+            let bytes = serialized(
+                &response,
+                &request(&format!("Accept-Encoding: {}\r\n", accept)),
+            );
+
+            assert!(
+                bytes.contains("Vary: Origin, Accept-Encoding\r\n"),
+                "{}",
+                bytes
+            );
+            assert_eq!(bytes.matches("Vary:").count(), 1);
+        }
+
+        // Never compressed
+        let mut small = Response::empty();
+
+        small
+            .set_body(ResponseBody::Text("tiny".to_string()))
+            .set_encoding_preferences(&[(BodyEncoding::Gzip, CompressionLevel::fast())]);
+        response.encoding_preferences.clear();
+
+        for response in [&small, &response] {
+            assert!(!serialized(response, &request("Accept-Encoding: gzip\r\n"))
+                .contains("Accept-Encoding"));
+        }
+    }
+
+    /// `response` serialized for `request`, as text.
+    fn serialized(response: &Response, request: &Request) -> String {
+        let bytes = response.to_bytes(request).unwrap();
+
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    #[test]
+    fn empty_bodies_are_framed() {
+        let request = request("");
+
+        assert!(request.keep_alive);
+
+        let head = serialized(&Response::empty(), &request);
+
+        assert!(head.contains("Connection: keep-alive\r\n"), "{}", head);
+        assert!(head.contains("Content-Length: 0\r\n"), "{}", head);
+
+        // Statuses that never have a body
+        for status in [
+            Status::NoContent,
+            Status::NotModified,
+            Status::from_code(103),
+        ] {
+            let mut response = Response::empty();
+
+            response.set_status(status);
+
+            assert!(!serialized(&response, &request).contains("Content-Length"));
+        }
+
+        // A body ended by closing the connection
+        let mut response = Response::empty();
+
+        response.add_header(Header::Connection(Connection::Close));
+
+        assert!(!serialized(&response, &request).contains("Content-Length"));
+    }
+
+    #[test]
+    fn internal_redirect_headers() {
+        let request = request("");
+        let styles = [
+            (
+                SendfileStyle::XAccelRedirect,
+                "X-Accel-Redirect: /protected/report.pdf\r\n",
+            ),
+            (
+                SendfileStyle::XSendfile,
+                "X-Sendfile: /protected/report.pdf\r\n",
+            ),
+        ];
+
+        for (style, header) in styles {
+            let mut response = Response::empty();
+
+            response
+                .set_body(ResponseBody::Text("not for the client".to_string()))
+                .add_header(Header::ContentLength(18))
+                .internal_redirect("/protected/report.pdf", style)
+                .unwrap();
+
+            let bytes = serialized(&response, &request);
+
+            assert!(bytes.contains(header), "{}", bytes);
+            assert!(bytes.contains("Content-Length: 0\r\n"), "{}", bytes);
+            assert!(!bytes.contains("Content-Type"), "{}", bytes);
+            // The body set before is gone
+            assert!(bytes.ends_with("\r\n\r\n"), "{}", bytes);
+        }
+
+        // A content type set by the handler is kept
+        let mut response = Response::empty();
+
+        response
+            .set_content_type(Mime::new(
+                "application".to_string(),
+                "pdf".to_string(),
+                None,
+            ))
+            .internal_redirect("/protected/report.pdf", SendfileStyle::XAccelRedirect)
+            .unwrap();
+
+        assert!(serialized(&response, &request).contains("Content-Type: application/pdf\r\n"));
+
+        for path in ["", "/a\r\nSet-Cookie: x=1", "/a\nb"] {
+            assert!(matches!(
+                Response::empty().internal_redirect(path, SendfileStyle::XSendfile),
+                Err(ResponseError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn list_headers_merge_cookies_repeat() {
+        let request = request("");
+        let mut response = Response::empty();
+
+        response
+            .add_header(Header::Vary(vec!["Origin".to_string()]))
+            .add_cookie(ResponseCookie::session("a", "1", &request))
+            .add_header(Header::Vary(vec![
+                "Accept-Language".to_string(),
+                "origin".to_string(),
+            ]))
+            .add_cookie(ResponseCookie::session("b", "2", &request))
+            .add_header(Header::Unknown("X-Tags".to_string(), "red".to_string()))
+            .add_header(Header::Unknown("X-Tags".to_string(), "blue".to_string()));
+
+        let head = serialized(&response, &request);
+        let lines = |name: &str| head.lines().filter(|line| line.starts_with(name)).count();
+
+        assert!(
+            head.contains("Vary: Origin, Accept-Language\r\n"),
+            "{}",
+            head
+        );
+        assert_eq!(lines("Vary:"), 1);
+        assert_eq!(lines("Set-Cookie:"), 2);
+        // Unknown headers are only merged on request
+        assert_eq!(lines("X-Tags:"), 2);
+
+        response.merge_list_header("x-tags");
+
+        let head = serialized(&response, &request);
+
+        assert!(head.contains("X-Tags: red, blue\r\n"), "{}", head);
+    }
+
+    #[test]
+    fn digests_of_the_body() {
+        let request = request("");
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Text("hello world".to_string()))
+            .add_digest(DigestAlgorithm::Sha256)
+            .add_digest(DigestAlgorithm::Md5)
+            .add_digest(DigestAlgorithm::Sha256);
+
+        let head = serialized(&response, &request);
+
+        assert!(
+            head.contains(
+                "Repr-Digest: sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:\r\n"
+            ),
+            "{}",
+            head
+        );
+        assert!(
+            head.contains("Content-MD5: XrY7u+Ae7tCTyyK7j1rNww==\r\n"),
+            "{}",
+            head
+        );
+        assert_eq!(head.matches("Repr-Digest").count(), 1);
+    }
+
+    #[test]
+    fn checked_redirects() {
+        let scripted = request("Referer: javascript:alert(1)\r\n");
+        let policy = RedirectPolicy::default();
+
+        // Back to the request URL, with or without the host
+        for target in ["/", "http://localhost/"] {
+            assert!(matches!(
+                Response::redirect_checked(target.to_string(), None, &policy, &scripted),
+                Err(RedirectError::Loop(_))
+            ));
+        }
+
+        let response =
+            Response::redirect_checked("/login".to_string(), None, &policy, &scripted).unwrap();
+
+        assert!(serialized(&response, &scripted).contains("Location: /login\r\n"));
+
+        // A referer failing the policy gives the fallback
+        let response = Response::redirect_back(&scripted, "/home".to_string(), &policy);
+
+        assert!(serialized(&response, &scripted).contains("Location: /home\r\n"));
+
+        let cart = request("Referer: /cart?step=2\r\n");
+        let response = Response::redirect_back(&cart, "/home".to_string(), &policy);
+
+        assert!(serialized(&response, &cart).contains("Location: /cart?step=2\r\n"));
+
+        let off_host = request("Referer: https://evil.example/\r\n");
+        let response = Response::redirect_back(
+            &off_host,
+            "/home".to_string(),
+            &RedirectPolicy::relative_only(),
+        );
+
+        assert!(serialized(&response, &off_host).contains("Location: /home\r\n"));
+    }
+
+    #[test]
+    fn server_header_policies() {
+        let mut request = request("");
+        let mut handler_set = Response::empty();
+
+        handler_set.add_header(Header::Server("app/2".to_string()));
+
+        let server_lines = |response: &Response, request: &Request| {
+            serialized(response, request)
+                .lines()
+                .filter(|line| line.starts_with("Server:"))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let default = format!("Server: JulMan-Http/{}", env!("CARGO_PKG_VERSION"));
+        let cases = [
+            (ServerHeaderPolicy::Default, vec![default], "Server: app/2"),
+            (
+                ServerHeaderPolicy::Custom("edge".to_string()),
+                vec!["Server: edge".to_string()],
+                "Server: app/2",
+            ),
+        ];
+
+        for (policy, expected, handler) in cases {
+            request.defaults.server_header = policy;
+
+            assert_eq!(server_lines(&Response::empty(), &request), expected);
+            // The handler wins
+            assert_eq!(server_lines(&handler_set, &request), [handler]);
+        }
+
+        request.defaults.server_header = ServerHeaderPolicy::Off;
+
+        assert!(server_lines(&Response::empty(), &request).is_empty());
+        assert!(server_lines(&handler_set, &request).is_empty());
+    }
+
+    #[test]
+    fn pragma_for_http10_clients() {
+        let mut request = request("");
+        let pragma = |response: &Response, request: &Request| {
+            serialized(response, request)
+                .matches("Pragma: no-cache\r\n")
+                .count()
+        };
+
+        let mut no_store = Response::empty();
+
+        no_store.no_store();
+
+        let mut no_cache = Response::empty();
+
+        no_cache.no_cache();
+
+        let mut cached = Response::empty();
+
+        cached.add_header(Header::CacheControl(vec![Cache::MaxAge(60)]));
+
+        assert_eq!(pragma(&no_store, &request), 0);
+        assert_eq!(pragma(&no_cache, &request), 0);
+
+        request.version = Version::Http10;
+
+        assert_eq!(pragma(&no_store, &request), 1);
+        assert_eq!(pragma(&no_cache, &request), 1);
+        assert_eq!(pragma(&cached, &request), 0);
+
+        // Never twice
+        no_cache.add_header(Header::Pragma(Cache::NoCache));
+
+        assert_eq!(pragma(&no_cache, &request), 1);
+    }
+
+    #[test]
+    fn invalid_header_names_are_never_sent() {
+        let request = request("");
+        let mut response = Response::empty();
+
+        response
+            .add_header(Header::Unknown("X-Echo".to_string(), "1".to_string()))
+            .add_header(Header::Unknown(
+                "X-Echo: 1\r\nSet-Cookie".to_string(),
+                "admin=1".to_string(),
+            ));
+
+        assert!(matches!(
+            response.to_bytes(&request),
+            Err(ResponseError::Invalid(_))
+        ));
+
+        let lenient = String::from_utf8(response.to_vector(&request)).unwrap();
+
+        assert!(lenient.contains("X-Echo: 1\r\n"), "{}", lenient);
+        assert!(!lenient.contains("Set-Cookie"), "{}", lenient);
+    }
+
+    #[test]
+    fn small_bodies_are_not_compressed() {
+        let mut client = request("Accept-Encoding: br, gzip\r\n");
+        let encoded = |size: usize, client: &Request| {
+            let mut response = Response::empty();
+
+            response
+                .set_body(ResponseBody::Text("a".repeat(size)))
+                .set_body_encoding(Some(BodyEncoding::Brotli), None);
+
+            serialized(&response, client).contains("Content-Encoding: ")
+        };
+
+        assert!(!encoded(255, &client));
+        assert!(encoded(256, &client));
+
+        client.defaults.compression_threshold = 1024;
+
+        assert!(!encoded(1000, &client));
+        assert!(encoded(1024, &client));
+
+        client.defaults.compression_threshold = 0;
+
+        assert!(encoded(1, &client));
+    }
+
+    #[test]
+    fn multipart_byteranges() {
+        let data = (0..=255u8).cycle().take(2000).collect::<Vec<_>>();
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Binary(data.clone()))
+            .set_content_type(Mime::application("octet-stream"))
+            .ranged(&request("Range: bytes=1000-1099, 0-99, 50-149, -10\r\n"));
+
+        let bytes = response.to_bytes(&request("")).unwrap();
+        let split = bytes.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let (head, body) = (String::from_utf8_lossy(&bytes[..split]), &bytes[split..]);
+
+        assert!(
+            head.starts_with("HTTP/1.1 206 Partial Content\r\n"),
+            "{}",
+            head
+        );
+        assert!(
+            head.contains(&format!("Content-Length: {}\r\n", body.len())),
+            "{}",
+            head
+        );
+
+        let boundary = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Type: multipart/byteranges;boundary="))
+            .unwrap();
+
+        let closing = format!("--{}--\r\n", boundary);
+
+        assert!(body.ends_with(closing.as_bytes()));
+
+        // Each part: its headers, then exactly the bytes of its range
+        let delimiter = format!("--{}\r\n", boundary);
+        let mut rest = &body[..body.len() - closing.len()];
+        let mut parts = vec![];
+
+        while !rest.is_empty() {
+            assert!(rest.starts_with(delimiter.as_bytes()));
+            rest = &rest[delimiter.len()..];
+
+            let end = rest.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+            let headers = String::from_utf8_lossy(&rest[..end]).into_owned();
+            let content_range = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("Content-Range: bytes "))
+                .unwrap();
+            let (range, total) = content_range.split_once('/').unwrap();
+            let (start, last) = range.split_once('-').unwrap();
+            let (start, last) = (
+                start.parse::<usize>().unwrap(),
+                last.parse::<usize>().unwrap(),
+            );
+
+            assert!(
+                headers.contains("Content-Type: application/octet-stream"),
+                "{}",
+                headers
+            );
+            assert_eq!(total, "2000");
+
+            rest = &rest[end + 4..];
+            assert_eq!(&rest[..=last - start], &data[start..=last]);
+            assert_eq!(&rest[last - start + 1..last - start + 3], b"\r\n");
+            rest = &rest[last - start + 3..];
+
+            parts.push((start, last));
+        }
+
+        // Sorted, with the overlapping ones coalesced
+        assert_eq!(parts, [(0, 149), (1000, 1099), (1990, 1999)]);
+
+        // A single range is sent as is
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Binary(data.clone()))
+            .ranged(&request("Range: bytes=10-19\r\n"));
+
+        assert_eq!(response.body_bytes(), &data[10..20]);
+        assert_eq!(
+            response
+                .get_header("Content-Range")
+                .map(Header::value)
+                .as_deref(),
+            Some("bytes 10-19/2000")
+        );
+
+        // Unsatisfiable, and too many ranges for the cap
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Binary(data.clone()))
+            .ranged(&request("Range: bytes=5000-\r\n"));
+
+        assert_eq!(response.status.code(), 416);
+        assert_eq!(
+            response
+                .get_header("Content-Range")
+                .map(Header::value)
+                .as_deref(),
+            Some("bytes */2000")
+        );
+
+        let mut client = request("Range: bytes=0-0,2-2,4-4\r\n");
+        let mut response = Response::empty();
+
+        client.defaults.max_ranges = 2;
+        response
+            .set_body(ResponseBody::Binary(data.clone()))
+            .ranged(&client);
+
+        assert_eq!(response.status.code(), 200);
+        assert_eq!(response.body_bytes(), data);
+    }
+
+    #[test]
+    fn accept_ranges_advertised() {
+        let served = |range: &str| {
+            let mut response = Response::empty();
+
+            response
+                .set_body(ResponseBody::Text("0123456789".to_string()))
+                .add_header(Header::AcceptRanges(vec![RangeUnit::None]))
+                .ranged(&request(range));
+
+            serialized(&response, &request(""))
+        };
+
+        for (range, status) in [
+            ("", "200 OK"),
+            ("Range: bytes=2-4\r\n", "206 Partial Content"),
+            ("Range: bytes=0-0,5-5\r\n", "206 Partial Content"),
+            (
+                "Range: bytes=50-\r\n",
+                "416 Requested Range Not Satisfiable",
+            ),
+        ] {
+            let response = served(range);
+
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {}\r\n", status)),
+                "{}",
+                response
+            );
+            // The one set by the handler is replaced
+            assert_eq!(
+                response.matches("Accept-Ranges: ").count(),
+                1,
+                "{}",
+                response
+            );
+            assert!(
+                response.contains("Accept-Ranges: bytes\r\n"),
+                "{}",
+                response
+            );
+        }
+
+        assert!(served("Range: bytes=50-\r\n").contains("Content-Range: bytes */10\r\n"));
+
+        // Parsed on the request side
+        assert!(matches!(
+            request("Accept-Ranges: bytes\r\n").get_header("Accept-Ranges"),
+            Some(Header::AcceptRanges(units)) if units[..] == [RangeUnit::Bytes]
+        ));
+    }
+
+    #[test]
+    fn charset_negotiation() {
+        let available = ["utf-8", "iso-8859-1"];
+        let negotiated = |accept: &str| {
+            let request = request(accept);
+            let mut response = Response::empty();
+
+            response.set_content_type(Mime::text("html"));
+
+            response
+                .negotiate_charset(&request, &available)
+                .map(|charset| (charset, serialized(&response, &request)))
+                .map_err(|refused| serialized(&refused, &request))
+        };
+
+        // A legacy client preferring latin-1
+        let (charset, head) = negotiated("Accept-Charset: iso-8859-1, utf-8;q=0.5\r\n").unwrap();
+
+        assert_eq!(charset, "iso-8859-1");
+        assert!(
+            head.contains("Content-Type: text/html;charset=iso-8859-1\r\n"),
+            "{}",
+            head
+        );
+        assert!(head.contains("Vary: Accept-Charset\r\n"), "{}", head);
+
+        // One requiring UTF-8 only, or without any preference
+        for accept in ["Accept-Charset: utf-8, *;q=0\r\n", ""] {
+            let (charset, head) = negotiated(accept).unwrap();
+
+            assert_eq!(charset, "utf-8");
+            assert!(
+                head.contains("Content-Type: text/html;charset=utf-8\r\n"),
+                "{}",
+                head
+            );
+        }
+
+        let refused = negotiated("Accept-Charset: utf-16\r\n").unwrap_err();
+
+        assert!(
+            refused.starts_with("HTTP/1.1 406 Not Acceptable\r\n"),
+            "{}",
+            refused
+        );
+        assert!(refused.contains("Vary: Accept-Charset\r\n"), "{}", refused);
+        assert!(
+            refused.ends_with("Available charsets: utf-8, iso-8859-1\n"),
+            "{}",
+            refused
+        );
+    }
+
+    #[test]
+    fn failing_encoder() {
+        let request = request("Accept-Encoding: gzip\r\n");
+        let body = "a".repeat(1024);
+        let mut response = Response::empty();
+
+        response
+            .set_body(ResponseBody::Text(body.clone()))
+            .set_body_encoding(Some(BodyEncoding::Gzip), None);
+
+        crate::encoding::FAIL_COMPRESSION.set(true);
+
+        let failed = response.to_bytes(&request);
+        let fallback = String::from_utf8(response.to_vector(&request)).unwrap();
+
+        crate::encoding::FAIL_COMPRESSION.set(false);
+
+        assert!(matches!(
+            failed,
+            Err(ResponseError::Encoding(BodyEncoding::Gzip, _))
+        ));
+        // Never a Content-Encoding over the identity body
+        assert!(!fallback.contains("Content-Encoding"), "{}", fallback);
+        assert!(
+            fallback.contains("Content-Length: 1024\r\n"),
+            "{}",
+            fallback
+        );
+        assert!(fallback.ends_with(&format!("\r\n\r\n{}", body)));
+
+        assert_eq!(chosen_encoding(&response, "gzip").as_deref(), Some("gzip"));
+    }
+}
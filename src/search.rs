@@ -5,12 +5,13 @@ use urlencoding::{decode, encode};
 #[derive(Debug, Clone)]
 pub struct SearchParam(String, Vec<String>);
 
+/// Query parameters, merged by name, along with every pair in the order it was received.
 #[derive(Debug, Clone)]
-pub struct SearchParams(Vec<SearchParam>);
+pub struct SearchParams(Vec<SearchParam>, Vec<(String, String)>);
 
 impl SearchParams {
     pub fn empty() -> SearchParams {
-        SearchParams(vec![])
+        SearchParams(vec![], vec![])
     }
 
     pub fn parse(mut s: String) -> Result<SearchParams, ()> {
@@ -24,6 +25,10 @@ impl SearchParams {
         while let Some(param) = raws.next() {
             let parsed = SearchParam::parse(param)?;
 
+            values
+                .1
+                .push((parsed.name().clone(), parsed.value()[0].clone()));
+
             let filter = values
                 .0
                 .iter()
@@ -55,6 +60,10 @@ impl SearchParams {
     }
 
     pub fn push(&mut self, data: SearchParam) {
+        for value in data.value() {
+            self.1.push((data.name().clone(), value.clone()));
+        }
+
         let filter = self
             .0
             .iter()
@@ -66,7 +75,7 @@ impl SearchParams {
             let value = &self.0[index];
             let mut temp = value.1.clone();
 
-            temp.push(data.value()[0].clone());
+            temp.extend(data.value().iter().cloned());
 
             self.0[index] = SearchParam(value.name().clone(), temp);
         } else {
@@ -74,9 +83,32 @@ impl SearchParams {
         }
     }
 
+    /// Remove every value of `name`, if any.
     pub fn remove(&mut self, name: &str) {
-        let index = self.0.iter().position(|x| x.name() == name).unwrap();
-        self.0.remove(index);
+        self.0.retain(|x| x.name() != name);
+        self.1.retain(|(x, _)| x != name);
+    }
+
+    /// Replace every value of `name` with `value`, in place of the first one,
+    /// appended last if it was not present.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let Some(first) = self.1.iter().position(|(x, _)| x == name) else {
+            self.push(SearchParam::new(name.to_string(), vec![value.to_string()]));
+            return;
+        };
+
+        for param in self.0.iter_mut().filter(|x| x.name() == name) {
+            *param = SearchParam::new(name.to_string(), vec![value.to_string()]);
+        }
+
+        self.1[first].1 = value.to_string();
+
+        let mut index = 0;
+
+        self.1.retain(|(x, _)| {
+            index += 1;
+            index - 1 <= first || x != name
+        });
     }
 
     pub fn has(&self, name: &str) -> bool {
@@ -100,6 +132,40 @@ impl SearchParams {
             iter: self.0.iter(),
         }
     }
+
+    /// Every `name=value` pair in the order it was received, duplicates included,
+    /// e.g. for signature schemes that need `a=1&b=2&a=3` as is.
+    pub fn pairs(&self) -> Pairs<'_> {
+        Pairs {
+            iter: self.1.iter(),
+        }
+    }
+
+    /// Like `to_string`, with the pairs in the order they were received instead
+    /// of grouped by name.
+    pub fn to_string_ordered(&self) -> String {
+        if self.1.is_empty() {
+            return String::new();
+        }
+
+        let pairs = self
+            .1
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+            .collect::<Vec<_>>();
+
+        format!("?{}", pairs.join("&"))
+    }
+
+    /// Like `to_string_ordered`, with the pairs sorted by name then value, so
+    /// that the same parameters in any order give the same string.
+    pub fn to_string_sorted(&self) -> String {
+        let mut pairs = self.1.clone();
+
+        pairs.sort();
+
+        SearchParams(vec![], pairs).to_string_ordered()
+    }
 }
 
 impl ToString for SearchParams {
@@ -227,3 +293,68 @@ impl<K, V> Entry<K, V> {
         &self.1
     }
 }
+
+pub struct Pairs<'a> {
+    iter: Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = Entry<&'a String, &'a String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(name, value)| Entry(name, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_merges_every_value() {
+        let mut params = SearchParams::parse("?a=1&b=2".to_string()).unwrap();
+
+        params.push(SearchParam::new(
+            "a".to_string(),
+            vec!["3".to_string(), "4".to_string()],
+        ));
+        params.push(SearchParam::new("b".to_string(), vec![]));
+
+        assert_eq!(params.get("a").unwrap(), &["1", "3", "4"]);
+        assert_eq!(params.get("b").unwrap(), &["2"]);
+        assert_eq!(params.to_string_ordered(), "?a=1&b=2&a=3&a=4");
+    }
+
+    fn pairs(params: &SearchParams) -> Vec<(&str, &str)> {
+        params
+            .pairs()
+            .map(|pair| (pair.key().as_str(), pair.value().as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn pairs_keep_interleaved_order() {
+        let interleaved = SearchParams::parse("?a=1&b=2&a=3".to_string()).unwrap();
+        let grouped = SearchParams::parse("?a=1&a=3&b=2".to_string()).unwrap();
+
+        // Same merged view, told apart by their pairs only
+        for params in [&interleaved, &grouped] {
+            assert_eq!(params.get("a").unwrap(), &["1", "3"]);
+            assert_eq!(params.get("b").unwrap(), &["2"]);
+            assert_eq!(params.to_string(), "?a=1&a=3&b=2");
+        }
+
+        assert_eq!(pairs(&interleaved), [("a", "1"), ("b", "2"), ("a", "3")]);
+        assert_eq!(pairs(&grouped), [("a", "1"), ("a", "3"), ("b", "2")]);
+        assert_eq!(interleaved.to_string_ordered(), "?a=1&b=2&a=3");
+
+        let mut pushed = SearchParams::empty();
+
+        pushed.push(SearchParam::new("a".to_string(), vec!["1".to_string()]));
+        pushed.push(SearchParam::new("b".to_string(), vec!["2".to_string()]));
+        pushed.push(SearchParam::new("a".to_string(), vec!["3".to_string()]));
+
+        assert_eq!(pushed.get("a").unwrap(), &["1", "3"]);
+        assert_eq!(pairs(&pushed), pairs(&interleaved));
+    }
+}
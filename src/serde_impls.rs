@@ -0,0 +1,282 @@
+use crate::common::{Cache, Header, Method, Status, Uri, Version};
+use crate::json::{Number, Value};
+use crate::mime::Mime;
+use crate::record::RecordEntry;
+use crate::request;
+use crate::search::{SearchParam, SearchParams};
+use crate::server::ParseMode;
+use serde::de::{Error as DeError, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq, SerializeStruct};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Formatter, Result as FmtResult};
+
+/// Types sent as their header form, read back with `$parse` (`String -> Result<$type, impl Display>`).
+macro_rules! string_form {
+    ($type:ty, $parse:expr) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let raw = String::deserialize(deserializer)?;
+
+                $parse(raw).map_err(D::Error::custom)
+            }
+        }
+    };
+}
+
+string_form!(Method, |raw: String| Ok::<_, String>(Method::parse(&raw)));
+string_form!(Version, |raw: String| Ok::<_, String>(Version::parse(&raw)));
+string_form!(Mime, |raw: String| Mime::parse(&raw));
+string_form!(Cache, |raw: String| Cache::parse_once(&raw)
+    .ok_or(format!("Invalid cache directive: {:?}", raw)));
+
+/// Sent as its numeric code, the reason phrase of `Custom` is lost.
+impl Serialize for Status {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Status {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Status::from_code(u16::deserialize(deserializer)?))
+    }
+}
+
+/// `{ "name": "Content-Type", "value": "text/html" }`, read back like a request header.
+impl Serialize for Header {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut header = serializer.serialize_struct("Header", 2)?;
+        header.serialize_field("name", &self.name())?;
+        header.serialize_field("value", &self.value())?;
+        header.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Header {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            name: String,
+            value: String,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+
+        request::parse_header(raw.name.to_lowercase(), raw.value, ParseMode::Lenient)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Every pair in the order it was received, `[["a", "1"], ["b", "2"]]`.
+impl Serialize for SearchParams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut pairs = serializer.serialize_seq(None)?;
+
+        for pair in self.pairs() {
+            pairs.serialize_element(&(pair.key(), pair.value()))?;
+        }
+
+        pairs.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for SearchParams {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut search = SearchParams::empty();
+
+        for (name, value) in Vec::<(String, String)>::deserialize(deserializer)? {
+            search.push(SearchParam::new(name, vec![value]));
+        }
+
+        Ok(search)
+    }
+}
+
+impl Serialize for Uri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut uri = serializer.serialize_struct("Uri", 4)?;
+        uri.serialize_field("scheme", &self.scheme)?;
+        uri.serialize_field("host", &self.host)?;
+        uri.serialize_field("path", &self.path)?;
+        uri.serialize_field("search", &self.search)?;
+        uri.end()
+    }
+}
+
+/// Its HAR entry, see `RecordEntry::to_har`.
+impl Serialize for RecordEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_har().serialize(serializer)
+    }
+}
+
+/// The JSON value itself, so it can be sent through any serde format.
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            Value::Number(Number::Int(value)) => serializer.serialize_i64(*value),
+            Value::Number(Number::UInt(value)) => serializer.serialize_u64(*value),
+            Value::Number(Number::Float(value)) => serializer.serialize_f64(*value),
+            Value::String(value) => serializer.serialize_str(value),
+            Value::Array(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+
+                seq.end()
+            }
+            Value::Object(members) => {
+                let mut map = serializer.serialize_map(Some(members.len()))?;
+
+                for (name, value) in members {
+                    map.serialize_entry(name, value)?;
+                }
+
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "a JSON value")
+    }
+
+    fn visit_unit<E: DeError>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: DeError>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: DeError>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E: DeError>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_u64<E: DeError>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_f64<E: DeError>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_str<E: DeError>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_string<E: DeError>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut elements = vec![];
+
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+
+        Ok(Value::Array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut members = vec![];
+
+        while let Some(member) = map.next_entry()? {
+            members.push(member);
+        }
+
+        Ok(Value::Object(members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestSummary;
+
+    #[test]
+    fn request_summary_snapshot() {
+        let summary = RequestSummary {
+            method: Method::Post,
+            version: Version::parse("HTTP/1.1"),
+            path: "/orders".to_string(),
+            query: Some("dry-run=1".to_string()),
+            headers: vec![
+                Header::Host("localhost".to_string()),
+                Header::ContentType(Mime::application("json")),
+                Header::CacheControl(vec![Cache::NoStore, Cache::MaxAge(0)]),
+            ],
+            body_length: 42,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&summary).unwrap(),
+            r#"{"method":"POST","version":"HTTP/1.1","path":"/orders","query":"dry-run=1","headers":[{"name":"Host","value":"localhost"},{"name":"Content-Type","value":"application/json"},{"name":"Cache-Control","value":"no-store, max-age=0"}],"body_length":42}"#
+        );
+
+        let json = serde_json::to_string(&summary).unwrap();
+        let read: RequestSummary = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(serde_json::to_string(&read).unwrap(), json);
+    }
+
+    #[test]
+    fn status_and_mime_round_trips() {
+        for status in [Status::Ok, Status::NotFound, Status::from_code(599)] {
+            let json = serde_json::to_string(&status).unwrap();
+
+            assert_eq!(json, status.code().to_string());
+            assert_eq!(
+                serde_json::from_str::<Status>(&json).unwrap().to_string(),
+                status.to_string()
+            );
+        }
+
+        for mime in [
+            "text/html",
+            "application/problem+json",
+            "text/plain;charset=utf-8",
+        ] {
+            let json = serde_json::to_string(&Mime::parse(&mime.to_string()).unwrap()).unwrap();
+
+            assert_eq!(json, format!("{:?}", mime));
+            assert_eq!(
+                serde_json::from_str::<Mime>(&json).unwrap().to_string(),
+                mime
+            );
+        }
+
+        assert!(serde_json::from_str::<Mime>(r#""not a type""#).is_err());
+    }
+}
@@ -1,104 +1,3464 @@
-use crate::request::{handle_connection, Request};
+use crate::common::{Header, Method, Status, Version};
+use crate::drain::{Drain, ShutDown};
+use crate::host::HostCanonicalization;
+#[cfg(feature = "json")]
+use crate::json::Value;
+use crate::limit::{client_key, ConnectionCounts, LimitReached};
+use crate::log::{LogEvent, LogFormat, Logger, SlowRequest};
+use crate::maintenance::{Maintenance, MaintenanceConfig};
+use crate::pool::WorkerPool;
+use crate::quota::ByteCounts;
+use crate::record::Recorder;
+use crate::request::{
+    handle_idle_connection, BodyTooLarge, HeadTooLarge, Http09Refused, IdleConnection, NotHttp,
+    Request, TooManyHeaders,
+};
+use crate::response::{BodyEncoding, Response, ResponseDefaults, ServerHeaderPolicy};
 use chrono::offset::Local;
-use std::io::{ErrorKind, Result as IoResult};
-use std::net::TcpListener;
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError,
+};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{available_parallelism, sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum BindError {
     PortAlreadyInUse,
     PermissionDenied,
+    /// The address is not one of this host, or its family is not available.
+    AddrNotAvailable,
     Unknown(ErrorKind),
 }
 
+impl From<IoError> for BindError {
+    fn from(err: IoError) -> Self {
+        match err.kind() {
+            ErrorKind::AddrInUse => BindError::PortAlreadyInUse,
+            ErrorKind::PermissionDenied => BindError::PermissionDenied,
+            ErrorKind::AddrNotAvailable => BindError::AddrNotAvailable,
+            kind => BindError::Unknown(kind),
+        }
+    }
+}
+
+impl Display for BindError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            BindError::PortAlreadyInUse => write!(f, "Port already in use"),
+            BindError::PermissionDenied => write!(f, "Permission denied"),
+            BindError::AddrNotAvailable => write!(f, "Address not available"),
+            BindError::Unknown(kind) => write!(f, "Bind failed: {}", kind),
+        }
+    }
+}
+
+impl std::error::Error for BindError {}
+
+/// A listener on `address`, with the socket options of `options`.
+fn listen(address: SocketAddr, options: &ServerOptions) -> IoResult<TcpListener> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+
+    socket.set_reuse_address(options.reuse_address)?;
+    socket.bind(&address.into())?;
+    socket.listen(128)?;
+
+    Ok(socket.into())
+}
+
+/// OS error codes for running out of file descriptors: `ENFILE` and `EMFILE`,
+/// `WSAEMFILE` on Windows.
+const OUT_OF_DESCRIPTORS: &[i32] = if cfg!(windows) { &[10024] } else { &[23, 24] };
+
+/// How long to wait before accepting again after `err`, the `failures`-th in a
+/// row, `None` when it is fatal. A connection aborted before being accepted or
+/// an interrupted call is retried at once. Running out of file descriptors or
+/// memory waits for some to be released, from 10 ms up to a second.
+fn accept_retry_delay(err: &IoError, failures: u32) -> Option<Duration> {
+    match err.kind() {
+        ErrorKind::Interrupted | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset => {
+            Some(Duration::ZERO)
+        }
+        kind if kind == ErrorKind::OutOfMemory
+            || err
+                .raw_os_error()
+                .is_some_and(|code| OUT_OF_DESCRIPTORS.contains(&code)) =>
+        {
+            Some(Duration::from_millis(10 << failures.min(7)).min(Duration::from_secs(1)))
+        }
+        _ => None,
+    }
+}
+
+/// Where `Server` accepts connections from: its `TcpListener`s, or scripted
+/// listeners in tests.
+trait Accept {
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()>;
+    fn accept(&self) -> IoResult<(TcpStream, SocketAddr)>;
+}
+
+impl Accept for TcpListener {
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        TcpListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn accept(&self) -> IoResult<(TcpStream, SocketAddr)> {
+        TcpListener::accept(self)
+    }
+}
+
+/// How `Server::quickstart` looks for a free port.
+#[derive(Debug, Clone)]
+pub struct PortFallback {
+    /// Attempts on each port before moving to the next one.
+    pub tries: u32,
+    /// Delay between two attempts on the same port.
+    pub delay: Duration,
+    /// How many ports after the requested one may be tried.
+    pub ports: u16,
+    /// Once they are all in use, bind to any free port the system picks.
+    pub ephemeral: bool,
+}
+
+impl Default for PortFallback {
+    fn default() -> Self {
+        PortFallback {
+            tries: 1,
+            delay: Duration::from_millis(500),
+            ports: 10,
+            ephemeral: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Server {
     pub port: u16,
     pub listener: TcpListener,
+    /// The IPv4 listener of `bind_dual`, when `listener` only accepts IPv6.
+    pub listener_v4: Option<TcpListener>,
+    /// Which listener `next` tries first, alternated so that neither starves the other.
+    accept_turn: AtomicBool,
     pub options: ServerOptions,
+    pub maintenance: Maintenance,
+    /// Kept-alive connections `next` reads the following requests from.
+    pub idle: IdleConnections,
+    /// Open connections, see `ServerOptions::max_connections`.
+    pub connections: Arc<ConnectionCounts>,
+    /// Requests received by protocol version.
+    pub versions: Arc<VersionCounts>,
+    /// The worker threads of `serve`, see `set_workers`.
+    ///
+    /// Clone `pool` to resize it or follow its queue from another thread.
+    pub pool: WorkerPool,
+    /// The shutdown switch and the requests in flight, see `shutdown_graceful`.
+    ///
+    /// Clone `drain` to shut the server down from another thread, e.g. once
+    /// it is moved into `serve`.
+    pub drain: Drain,
 }
 
+/// Requests received by protocol version, for operators to see what clients
+/// speak. HTTP/0.9 request lines are counted even when refused.
+#[derive(Debug, Default)]
+pub struct VersionCounts {
+    http09: AtomicU64,
+    http10: AtomicU64,
+    http11: AtomicU64,
+    /// HTTP/2.0 and unknown versions.
+    other: AtomicU64,
+}
+
+impl VersionCounts {
+    pub fn count(&self, version: &Version) {
+        self.counter(version).fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The requests of `version` received so far, those of any version but
+    /// HTTP/0.9, 1.0 and 1.1 counted together.
+    pub fn get(&self, version: &Version) -> u64 {
+        self.counter(version).load(Ordering::Relaxed)
+    }
+
+    fn counter(&self, version: &Version) -> &AtomicU64 {
+        match version {
+            Version::Http09 => &self.http09,
+            Version::Http10 => &self.http10,
+            Version::Http11 => &self.http11,
+            _ => &self.other,
+        }
+    }
+}
+
+/// Live connection threads of `Server::serve_spawning`.
+#[derive(Debug, Default)]
+struct ConnectionThreads {
+    live: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl ConnectionThreads {
+    /// Wait until fewer than `max` threads are live, then count one more until
+    /// the returned guard is dropped.
+    fn start(threads: &Arc<ConnectionThreads>, max: usize) -> ConnectionThread {
+        let mut live = threads.live.lock().unwrap_or_else(|err| err.into_inner());
+
+        while *live >= max {
+            live = threads
+                .finished
+                .wait(live)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+
+        *live += 1;
+
+        ConnectionThread(Arc::clone(threads))
+    }
+}
+
+/// A live thread of `ConnectionThreads`, even if it panics.
+struct ConnectionThread(Arc<ConnectionThreads>);
+
+impl Drop for ConnectionThread {
+    fn drop(&mut self) {
+        *self.0.live.lock().unwrap_or_else(|err| err.into_inner()) -= 1;
+        self.0.finished.notify_one();
+    }
+}
+
+/// Kept-alive connections waiting for their next request.
 #[derive(Debug)]
+pub struct IdleConnections {
+    sender: Sender<IdleConnection>,
+    receiver: Mutex<Receiver<IdleConnection>>,
+    waiting: Mutex<Vec<(IdleConnection, Instant)>>,
+}
+
+impl Default for IdleConnections {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+
+        IdleConnections {
+            sender,
+            receiver: Mutex::new(receiver),
+            waiting: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl IdleConnections {
+    /// Take in the connections of the requests dropped since the last call,
+    /// returns whether any connection is waiting.
+    fn collect(&self) -> bool {
+        let receiver = self.receiver.lock().unwrap_or_else(|err| err.into_inner());
+        let mut waiting = self.waiting.lock().unwrap_or_else(|err| err.into_inner());
+
+        for connection in receiver.try_iter() {
+            if connection.stream.set_nonblocking(true).is_ok() {
+                waiting.push((connection, Instant::now()));
+            }
+        }
+
+        !waiting.is_empty()
+    }
+
+    /// Wait for the request of `connection`, polled by `ready`.
+    fn park(&self, connection: IdleConnection) {
+        let _ = self.sender.send(connection);
+    }
+
+    /// The first connection with a request to read, dropping those the client
+    /// closed or idle for longer than `timeout`, if any.
+    fn ready(&self, timeout: Option<Duration>) -> Option<IdleConnection> {
+        self.collect();
+
+        let mut waiting = self.waiting.lock().unwrap_or_else(|err| err.into_inner());
+        let mut index = 0;
+
+        while index < waiting.len() {
+            let (connection, since) = &waiting[index];
+            let readable = match connection.stream.peek(&mut [0]) {
+                _ if !connection.buffer.is_empty() => Some(true),
+                Ok(0) => None,
+                Ok(_) => Some(true),
+                Err(err)
+                    if err.kind() == ErrorKind::WouldBlock
+                        && timeout.is_none_or(|timeout| since.elapsed() < timeout) =>
+                {
+                    Some(false)
+                }
+                Err(_) => None,
+            };
+
+            match readable {
+                Some(true) => {
+                    let (connection, _) = waiting.remove(index);
+
+                    if connection.stream.set_nonblocking(false).is_ok() {
+                        return Some(connection);
+                    }
+                }
+                Some(false) => index += 1,
+                None => {
+                    waiting.remove(index);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ServerOptions {
-    pub log: bool,
+    /// Called with each answered request, once the response is sent.
+    pub logger: Option<Logger>,
+    /// Log `Uri::canonical` instead of the URI as received.
+    pub log_canonical: bool,
+    /// Keeps each answered request with its response, see `Recorder`.
+    pub recorder: Option<Recorder>,
+    /// Requests taking longer than this from their first byte to the end of
+    /// their body are reported to `Logger::with_slow_requests`, with the time
+    /// of each phase: clients trickling their headers show up there.
+    pub slow_request_threshold: Option<Duration>,
+    /// Answer `OPTIONS *` requests with `204 No Content` and an `Allow` header
+    /// listing `allowed_methods`, without handing them to the application.
+    pub answer_server_options: bool,
+    pub allowed_methods: Vec<Method>,
+    pub server_header: ServerHeaderPolicy,
+    /// Maximum number of header lines in a request, more is a `431 Request Header
+    /// Fields Too Large`. Also applies to the trailers of a chunked body.
+    pub max_headers: usize,
+    /// Maximum size in bytes of the request line and headers, however many reads they
+    /// arrive in, more is a `431 Request Header Fields Too Large`. Also applies to
+    /// the trailers of a chunked body.
+    pub max_head_size: usize,
+    /// Maximum size in bytes of a request body, more is a `413 Content Too Large`.
+    /// A chunked body counts with its chunk sizes and line endings.
+    pub max_body_size: Option<usize>,
+    /// How long reading a request may wait for the client, then `408 Request Timeout`.
+    pub read_timeout: Option<Duration>,
+    pub invalid_header_names: InvalidHeaderPolicy,
+    pub parse_mode: ParseMode,
+    /// Answer HTTP/0.9 requests (`GET /path`, no version) with the bare body,
+    /// instead of `505 HTTP Version Not Supported`.
+    pub http09: bool,
+    /// Whether `/users/` and `/users` are different paths for `Request::path_matches`.
+    pub strict_slashes: bool,
+    /// The server is behind a reverse proxy that adds the client address to
+    /// `Forwarded` or `X-Forwarded-For`, see `Request::remote_ip`. Leave it off
+    /// when clients can connect directly, as they could then pick their address.
+    pub trust_proxy: bool,
+    /// Host aliases and trailing dots to rewrite or redirect before the request
+    /// is handed over, see `HostCanonicalization`.
+    pub host_canonicalization: Option<HostCanonicalization>,
+    /// Bodies smaller than this are sent uncompressed, whatever the response encoding.
+    pub compression_threshold: usize,
+    /// Size of the internal buffer of the brotli encoder, 32 to 64 KiB works well.
+    pub brotli_buffer_size: usize,
+    /// Most ranges `Response::ranged` serves in one response, more and the whole body is sent.
+    pub max_ranges: usize,
+    /// Send `X-Content-Type-Options: nosniff` and `X-Frame-Options: SAMEORIGIN`
+    /// unless the handler set them.
+    pub security_headers: bool,
+    /// Send what compressing each body took in an `X-Compression` header, see
+    /// `CompressionStats::header_value`.
+    pub debug_headers: bool,
+    /// Threads handling requests in `Server::serve`, one per CPU when `None`,
+    /// see `Server::set_workers` to change it while serving.
+    pub workers: Option<usize>,
+    /// Connections with a request `Server::serve` keeps waiting for a free
+    /// worker, more get `503 Service Unavailable` with `Retry-After` and are closed.
+    pub worker_queue: usize,
+    /// Have `Server::serve` spawn a thread for each connection rather than use
+    /// a pool of `workers`, see `Server::serve_spawning`.
+    pub spawn_per_connection: bool,
+    /// Connections `Server::serve_spawning` handles at once, more wait to be read.
+    pub max_connection_threads: usize,
+    /// Requests `Server::into_channel` parses ahead of the receiver, accepting
+    /// waits past them.
+    pub channel_capacity: usize,
+    /// How long a connection is kept open waiting for its next request, `None`
+    /// closes it after each response. Idle connections are only closed between
+    /// requests, never while one is handled. In `Server::serve_spawning`, a
+    /// waiting connection holds its thread.
+    pub keep_alive_timeout: Option<Duration>,
+    /// Requests answered on one connection before it is closed, `None` for no limit.
+    pub keep_alive_max_requests: Option<usize>,
+    /// Send small responses right away instead of waiting to fill a packet
+    /// (disables Nagle's algorithm on accepted connections).
+    pub tcp_nodelay: bool,
+    /// Idle time after which the system checks that a connection's client is
+    /// still there, so that connections dropped by a NAT or firewall get closed.
+    pub tcp_keepalive: Option<Duration>,
+    /// Let the listener bind a port still held by closed connections (`SO_REUSEADDR`),
+    /// on by default except on Windows, where it allows stealing a bound port.
+    pub reuse_address: bool,
+    /// Connections the server keeps open at once, kept-alive ones included. More
+    /// get `503 Service Unavailable` with `Retry-After` and are closed unread.
+    pub max_connections: Option<usize>,
+    /// Connections a client may have open at once, more get `429 Too Many Requests`
+    /// and are closed. Behind a reverse proxy, every client shares its address.
+    pub max_connections_per_ip: Option<usize>,
+    /// Count IPv6 clients by their /64 network for `max_connections_per_ip`, so
+    /// that a client can't get around it by using more addresses.
+    pub count_ipv6_by_subnet: bool,
+}
+
+/// What to do with a request header whose name is not a valid token.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InvalidHeaderPolicy {
+    /// Answer `400 Bad Request`.
+    #[default]
+    Reject,
+    /// Ignore the header.
+    Drop,
+}
+
+/// How much a request may stray from the grammar of RFC 9112 before it gets a
+/// `400 Bad Request`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ParseMode {
+    /// For internet-facing servers: lines must end with CRLF, the method must be a
+    /// token and the version known, HTTP/1.1 requires a `Host` matching an absolute-form
+    /// target, at most one
+    /// `Content-Length` (and not along `Transfer-Encoding`), the target must be
+    /// validly percent-encoded, every cookie well formed and no header folded.
+    Strict,
+    /// Accept bare LF line endings, unfold folded headers, skip malformed cookies
+    /// and keep targets that don't decode as is.
+    #[default]
+    Lenient,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            logger: None,
+            log_canonical: false,
+            recorder: None,
+            slow_request_threshold: None,
+            answer_server_options: false,
+            allowed_methods: vec![
+                Method::Get,
+                Method::Head,
+                Method::Post,
+                Method::Put,
+                Method::Delete,
+                Method::Patch,
+                Method::Options,
+            ],
+            server_header: ServerHeaderPolicy::Default,
+            max_headers: 100,
+            max_head_size: 16 * 1024,
+            max_body_size: None,
+            read_timeout: None,
+            invalid_header_names: InvalidHeaderPolicy::Reject,
+            parse_mode: ParseMode::Lenient,
+            http09: false,
+            strict_slashes: false,
+            trust_proxy: false,
+            host_canonicalization: None,
+            compression_threshold: 256,
+            brotli_buffer_size: 32 * 1024,
+            max_ranges: 16,
+            security_headers: false,
+            debug_headers: false,
+            workers: None,
+            worker_queue: 128,
+            spawn_per_connection: false,
+            max_connection_threads: 256,
+            channel_capacity: 16,
+            keep_alive_timeout: Some(Duration::from_secs(15)),
+            keep_alive_max_requests: Some(100),
+            tcp_nodelay: false,
+            tcp_keepalive: None,
+            reuse_address: cfg!(not(windows)),
+            max_connections: None,
+            max_connections_per_ip: None,
+            count_ipv6_by_subnet: true,
+        }
+    }
+}
+
+impl ServerOptions {
+    /// Limits and checks for a server facing the internet: strict parsing, 10 s
+    /// read timeout, 1 MiB bodies, 8 KiB heads, no HTTP/0.9 and security headers.
+    pub fn hardened() -> ServerOptions {
+        ServerOptions {
+            max_head_size: 8 * 1024,
+            max_body_size: Some(1024 * 1024),
+            read_timeout: Some(Duration::from_secs(10)),
+            invalid_header_names: InvalidHeaderPolicy::Reject,
+            parse_mode: ParseMode::Strict,
+            http09: false,
+            security_headers: true,
+            ..ServerOptions::default()
+        }
+    }
+
+    /// The permissive defaults, with every request logged.
+    pub fn development() -> ServerOptions {
+        ServerOptions::default().with_stdout_log()
+    }
+
+    /// Print every request on stdout.
+    pub fn with_stdout_log(mut self) -> ServerOptions {
+        self.logger = Some(Logger::stdout());
+
+        self
+    }
+
+    /// Write every request to `writer` as an Apache access log, in Common Log Format.
+    pub fn with_common_log_format(self, writer: impl Write + Send + 'static) -> ServerOptions {
+        self.with_log_format(LogFormat::Common, writer)
+    }
+
+    /// Write every request to `writer` in `format`, one per line.
+    pub fn with_log_format(
+        mut self,
+        format: LogFormat,
+        writer: impl Write + Send + 'static,
+    ) -> ServerOptions {
+        self.logger = Some(Logger::formatted(format, writer));
+
+        self
+    }
+
+    /// Append every request to the file at `path` in `format`.
+    pub fn with_log_file<P: AsRef<Path>>(
+        mut self,
+        path: P,
+        format: LogFormat,
+    ) -> IoResult<ServerOptions> {
+        self.logger = Some(Logger::file(path, format)?);
+
+        Ok(self)
+    }
 }
 
 impl Server {
-    pub fn bind_v4(port: u16, options: Option<ServerOptions>) -> Result<Server, BindError> {
-        let options = options.unwrap_or(ServerOptions { log: false });
+    /// Bind to `address` only, e.g. `127.0.0.1:8080` for a local admin interface
+    /// or the address of one network interface. With port 0 the system picks
+    /// one, see `local_addr`.
+    pub fn bind(
+        address: impl Into<SocketAddr>,
+        options: Option<ServerOptions>,
+    ) -> Result<Server, BindError> {
+        let options = options.unwrap_or_default();
+        let listener = listen(address.into(), &options)?;
+        // The port actually bound, the system picks one for 0
+        let port = listener.local_addr()?.port();
+
+        Ok(Server::with_listener(port, listener, Some(options)))
+    }
+
+    /// Bind to the first of `addresses` that can be bound, trying them in order.
+    /// Fails with the error of the last one.
+    pub fn bind_all(
+        addresses: &[SocketAddr],
+        options: Option<ServerOptions>,
+    ) -> Result<Server, BindError> {
+        let mut last_error = BindError::Unknown(ErrorKind::InvalidInput);
 
-        match TcpListener::bind(format!("0.0.0.0:{}", port)) {
-            Ok(listener) => Ok(Server {
-                port,
-                listener,
-                options,
-            }),
-            Err(e) => Err(match e.kind() {
-                ErrorKind::AddrInUse => BindError::PortAlreadyInUse,
-                ErrorKind::PermissionDenied => BindError::PermissionDenied,
-                kind => BindError::Unknown(kind),
-            }),
+        for address in addresses {
+            match Server::bind(*address, options.clone()) {
+                Ok(server) => return Ok(server),
+                Err(err) => last_error = err,
+            }
         }
+
+        Err(last_error)
+    }
+
+    pub fn bind_v4(port: u16, options: Option<ServerOptions>) -> Result<Server, BindError> {
+        Server::bind((Ipv4Addr::UNSPECIFIED, port), options)
     }
 
     pub fn bind_v6(port: u16, options: Option<ServerOptions>) -> Result<Server, BindError> {
-        let options = options.unwrap_or(ServerOptions { log: false });
+        Server::bind((Ipv6Addr::UNSPECIFIED, port), options)
+    }
 
-        match TcpListener::bind(format!("[::]:{}", port)) {
-            Ok(listener) => Ok(Server {
-                port,
-                listener,
-                options,
-            }),
-            Err(e) => Err(match e.kind() {
-                ErrorKind::AddrInUse => BindError::PortAlreadyInUse,
-                ErrorKind::PermissionDenied => BindError::PermissionDenied,
-                kind => BindError::Unknown(kind),
-            }),
+    /// Bind on every IPv6 and IPv4 interface.
+    ///
+    /// Most systems let the IPv6 socket accept IPv4 clients too. Where it doesn't
+    /// (`IPV6_V6ONLY`, which the standard library can't clear before binding), an
+    /// IPv4 listener is bound next to it, see `listener_v4`. Hosts without IPv6
+    /// only get the IPv4 one.
+    pub fn bind_dual(port: u16, options: Option<ServerOptions>) -> Result<Server, BindError> {
+        let mut server = match Server::bind_v6(port, options.clone()) {
+            Ok(server) => server,
+            Err(err @ (BindError::PortAlreadyInUse | BindError::PermissionDenied)) => {
+                return Err(err)
+            }
+            Err(_) => return Server::bind_v4(port, options),
+        };
+
+        // When the IPv6 socket also takes IPv4 clients, the IPv4 port is already
+        // taken by it
+        match listen(
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, server.port)),
+            &server.options,
+        ) {
+            Ok(listener) => {
+                let listeners = vec![server.listener.local_addr()?, listener.local_addr()?];
+
+                server.drain = Drain::new(Arc::clone(&server.connections), listeners);
+                server.listener_v4 = Some(listener);
+            }
+            Err(err) if err.kind() == ErrorKind::AddrInUse => {}
+            Err(err) => return Err(err.into()),
         }
+
+        Ok(server)
     }
 
-    pub fn next(&self) -> IoResult<Request> {
-        match self.listener.accept() {
-            Ok((stream, _)) => {
-                let req = handle_connection(stream);
-
-                if let Ok(ref req) = req {
-                    if self.options.log {
-                        println!(
-                            "[{:?}] {} {} {}",
-                            Local::now(),
-                            req.method.to_string(),
-                            req.uri.to_string(),
-                            req.stream.peer_addr()?
-                        );
+    fn with_listener(port: u16, listener: TcpListener, options: Option<ServerOptions>) -> Server {
+        let options = options.unwrap_or_default();
+        let workers = options
+            .workers
+            .unwrap_or_else(|| available_parallelism().map_or(1, |n| n.get()));
+        let connections = Arc::new(ConnectionCounts::default());
+        let drain = Drain::new(
+            Arc::clone(&connections),
+            listener.local_addr().into_iter().collect(),
+        );
+
+        Server {
+            port,
+            listener,
+            listener_v4: None,
+            accept_turn: AtomicBool::new(false),
+            options,
+            maintenance: Maintenance::default(),
+            idle: IdleConnections::default(),
+            connections,
+            versions: Arc::default(),
+            pool: WorkerPool::new(workers),
+            drain,
+        }
+    }
+
+    /// Have `serve` run `workers` threads: more are started at once, extra ones
+    /// exit once done with their current connection. See `pool` to resize a
+    /// server moved into `serve`.
+    pub fn set_workers(&self, workers: usize) {
+        self.pool.set_workers(workers);
+    }
+
+    /// The port the server is bound to, the one the system picked when asked
+    /// for port 0.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The address the server is bound to, see `port`.
+    pub fn local_addr(&self) -> IoResult<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Stop accepting connections and give the requests in flight up to
+    /// `timeout` to finish, their responses closing their connection. Open
+    /// connections are then closed, and `serve` returns.
+    ///
+    /// Returns whether every request finished in time, see `Drain::shutdown_graceful`.
+    pub fn shutdown_graceful(&self, timeout: Duration) -> bool {
+        self.drain.shutdown_graceful(timeout)
+    }
+
+    /// Stop accepting connections and close every open one, requests in flight included.
+    pub fn shutdown(&self) {
+        self.drain.shutdown();
+    }
+
+    /// Requests handed to the application that weren't dropped yet, e.g. to
+    /// follow a shutdown.
+    pub fn in_flight(&self) -> usize {
+        self.drain.in_flight()
+    }
+
+    /// The listeners to accept from, in the order to try them.
+    fn listeners(&self) -> Vec<&TcpListener> {
+        let mut listeners: Vec<&TcpListener> = std::iter::once(&self.listener)
+            .chain(self.listener_v4.as_ref())
+            .collect();
+
+        if self.accept_turn.fetch_xor(true, Ordering::Relaxed) {
+            listeners.reverse();
+        }
+
+        listeners
+    }
+
+    /// Bind on every IPv4 interface with the default options, moving to the
+    /// next ports while the requested one is in use.
+    ///
+    /// Returns the server and the port it is actually bound to.
+    pub fn quickstart(port: u16) -> Result<(Server, u16), BindError> {
+        Server::quickstart_with(port, None, PortFallback::default())
+    }
+
+    pub fn quickstart_with(
+        port: u16,
+        options: Option<ServerOptions>,
+        fallback: PortFallback,
+    ) -> Result<(Server, u16), BindError> {
+        let options = options.unwrap_or_default();
+        let mut last_error = BindError::PortAlreadyInUse;
+
+        for offset in 0..=fallback.ports {
+            let Some(port) = port.checked_add(offset) else {
+                break;
+            };
+
+            for attempt in 0..fallback.tries.max(1) {
+                if attempt > 0 {
+                    sleep(fallback.delay);
+                }
+
+                match Server::bind_v4(port, Some(options.clone())) {
+                    Ok(server) => {
+                        let port = server.port;
+
+                        return Ok((server, port));
                     }
+                    Err(BindError::PortAlreadyInUse) => last_error = BindError::PortAlreadyInUse,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        if fallback.ephemeral {
+            let server = Server::bind_v4(0, Some(options))?;
+            let port = server.port;
+
+            return Ok((server, port));
+        }
+
+        Err(last_error)
+    }
+
+    /// The next request, from a new connection or a kept-alive one.
+    ///
+    /// Drop each request before asking for the next one: its connection is
+    /// only read again from here once the request is gone.
+    ///
+    /// Accept errors the listener recovers from are retried, handed to
+    /// `Logger::with_errors`, only fatal ones are returned.
+    pub fn next(&self) -> IoResult<Request> {
+        loop {
+            if let Some(req) = self.next_until(None, || self.listeners())? {
+                return Ok(req);
+            }
+        }
+    }
+
+    /// Like `next`, but `None` once `timeout` passed without a request, for
+    /// callers polling between other work.
+    ///
+    /// A client that is slow to send its request may hold this past `timeout`,
+    /// up to `ServerOptions::read_timeout`.
+    pub fn try_next(&self, timeout: Duration) -> Result<Option<Request>, AcceptError> {
+        self.try_next_from(timeout, || self.listeners())
+    }
+
+    /// `try_next`, accepting from `listeners`.
+    fn try_next_from<'a, L: Accept + 'a>(
+        &self,
+        timeout: Duration,
+        listeners: impl Fn() -> Vec<&'a L>,
+    ) -> Result<Option<Request>, AcceptError> {
+        Ok(self.next_until(Some(Instant::now() + timeout), listeners)?)
+    }
+
+    /// The next request accepted from `listeners`, `None` when `deadline` passes first.
+    fn next_until<'a, L: Accept + 'a>(
+        &self,
+        deadline: Option<Instant>,
+        listeners: impl Fn() -> Vec<&'a L>,
+    ) -> IoResult<Option<Request>> {
+        // Failed accepts in a row, see `accept_retry_delay`
+        let mut failures = 0;
+
+        loop {
+            if let Some(connection) = self.idle.ready(self.options.keep_alive_timeout) {
+                match self.accept_request(connection, &self.idle.sender)? {
+                    Some(req) => return Ok(Some(req)),
+                    None => continue,
                 }
-                return req;
             }
-            Err(e) => {
-                println!("Failed to accept connection: {:?}", e);
 
-                return Err(e);
+            // Requests already sent on kept-alive connections are still read
+            if self.drain.is_shutting_down() {
+                return Err(IoError::other(ShutDown));
+            }
+
+            // Only poll for new connections while some kept-alive one may send a
+            // request, until the deadline or with several listeners
+            let listeners = listeners();
+            let poll = self.idle.collect() || deadline.is_some() || listeners.len() > 1;
+            let mut accepted = Err(IoError::from(ErrorKind::WouldBlock));
+
+            for listener in listeners {
+                listener.set_nonblocking(poll)?;
+                accepted = listener.accept();
+
+                if !matches!(accepted, Err(ref e) if e.kind() == ErrorKind::WouldBlock) {
+                    break;
+                }
             }
+
+            match accepted {
+                Ok((stream, _)) => {
+                    failures = 0;
+
+                    if self.drain.is_shutting_down() {
+                        continue;
+                    }
+
+                    stream.set_nonblocking(false)?;
+
+                    let Some(connection) = self.admit(stream) else {
+                        continue;
+                    };
+
+                    if let Some(req) = self.accept_request(connection, &self.idle.sender)? {
+                        return Ok(Some(req));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Ok(None);
+                    }
+
+                    sleep(ACCEPT_POLL);
+                }
+                Err(e) => self.accept_failed(e, &mut failures)?,
+            };
+        }
+    }
+
+    /// Handle every request with `handler` on a pool of `ServerOptions::workers`
+    /// threads, while this one accepts connections. Connections wait here until
+    /// their client sends a request, kept-alive ones included, so that an idle
+    /// client holds no worker, and a slow one only its own. Past
+    /// `ServerOptions::worker_queue` requests waiting for a worker, more are
+    /// answered `503 Service Unavailable`. Returns once shut down through a clone of `drain`, or
+    /// once accepting fails with an error that can't be retried, handed to
+    /// `Logger::with_errors`.
+    ///
+    /// A handler that panics answers `500 Internal Server Error`, its worker keeps going.
+    ///
+    /// With `ServerOptions::spawn_per_connection`, this is `serve_spawning`.
+    pub fn serve<F>(self, handler: F)
+    where
+        F: Fn(&mut Request) -> Response + Send + Sync + 'static,
+    {
+        if self.options.spawn_per_connection {
+            return self.serve_spawning(handler);
+        }
+
+        let server = Arc::new(self);
+        let handler = Arc::new(handler);
+        // Connections with a request to read
+        let (sender, receiver) = sync_channel::<IdleConnection>(server.options.worker_queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let start_worker = || {
+            let mut worker = server.pool.start();
+            let server = Arc::clone(&server);
+            let handler = Arc::clone(&handler);
+            let receiver = Arc::clone(&receiver);
+
+            spawn(move || loop {
+                // Past the pool size, between two connections
+                if worker.retire() {
+                    return;
+                }
+
+                let connection = receiver
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .recv_timeout(WORKER_POLL);
+                let connection = match connection {
+                    Ok(connection) => connection,
+                    Err(RecvTimeoutError::Timeout) if !server.drain.is_shutting_down() => continue,
+                    Err(_) => return,
+                };
+
+                server.pool.dequeued();
+                // Kept-alive, the connection waits in `idle` for its next request
+                server.serve_connection(connection, &server.idle.sender, &*handler);
+            });
         };
+
+        server.dispatch(&sender, || {
+            while server.pool.live() < server.pool.workers() {
+                start_worker();
+            }
+        });
+        server.drain.wait();
     }
 
-    pub fn requests(&self) -> Requests {
-        Requests { server: self }
+    /// `serve` with `state` shared by every worker, e.g. a database pool or the
+    /// configuration, handed to `handler` along with each request. Mutable
+    /// state needs its own locking (`Mutex`, atomics).
+    pub fn serve_with_state<S, F>(self, state: S, handler: F)
+    where
+        S: Send + Sync + 'static,
+        F: Fn(&S, &mut Request) -> Response + Send + Sync + 'static,
+    {
+        let state = Arc::new(state);
+
+        self.serve(move |request| handler(&state, request));
     }
-}
 
-pub struct Requests<'a> {
-    pub server: &'a Server,
-}
+    /// Handle each connection with `handler` on a thread of its own, spawned
+    /// once it is accepted: a slow client only holds its own thread. Returns
+    /// once shut down, as `serve`.
+    ///
+    /// Past `ServerOptions::max_connection_threads` live threads, new connections
+    /// wait for one to finish. A handler that panics answers `500 Internal Server
+    /// Error`, the connection stays open when kept alive.
+    pub fn serve_spawning<F>(self, handler: F)
+    where
+        F: Fn(&mut Request) -> Response + Send + Sync + 'static,
+    {
+        let max_threads = self.options.max_connection_threads.max(1);
+        let server = Arc::new(self);
+        let handler = Arc::new(handler);
+        let threads = Arc::new(ConnectionThreads::default());
+        let (sender, receiver) = channel::<IdleConnection>();
 
-impl<'a> Iterator for Requests<'a> {
-    type Item = Option<Request>;
+        // Accepting goes on while this thread waits for a free one
+        {
+            let server = Arc::clone(&server);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.server.next() {
-            Ok(req) => Some(Some(req)),
-            Err(_) => Some(None),
+            spawn(move || Server::accept_into(&server, &sender));
+        }
+
+        for connection in receiver {
+            let thread = ConnectionThreads::start(&threads, max_threads);
+            let server = Arc::clone(&server);
+            let handler = Arc::clone(&handler);
+
+            spawn(move || {
+                let _thread = thread;
+                // The connection comes back here when kept alive
+                let (sender, receiver) = channel::<IdleConnection>();
+                let mut connection = Some(connection);
+
+                while let Some(next) = connection {
+                    if next.buffer.is_empty() && !server.wait_for_request(&next.stream) {
+                        break;
+                    }
+
+                    server.serve_connection(next, &sender, &*handler);
+                    connection = receiver.try_recv().ok();
+                }
+            });
+        }
+
+        server.drain.wait();
+    }
+
+    /// Accept connections from every listener until shut down, handing them to
+    /// `sender` before their client sent anything.
+    fn accept_into(server: &Arc<Server>, sender: &Sender<IdleConnection>) {
+        // The IPv4 listener of `bind_dual` accepts on its own thread
+        if server.listener_v4.is_some() {
+            let server = Arc::clone(server);
+            let sender = sender.clone();
+
+            spawn(move || {
+                if let Some(ref listener) = server.listener_v4 {
+                    server.queue_connections(listener, &sender);
+                }
+            });
+        }
+
+        server.queue_connections(&server.listener, sender);
+    }
+
+    /// Accept connections and hand those with a request to read to `workers`,
+    /// until shut down, calling `grow` first each time to start the workers the
+    /// pool lacks. The others wait in `idle`, costing no thread.
+    fn dispatch(&self, workers: &SyncSender<IdleConnection>, grow: impl Fn()) {
+        // Failed accepts in a row, see `accept_retry_delay`
+        let mut failures = 0;
+
+        loop {
+            if self.drain.is_shutting_down() {
+                return;
+            }
+
+            grow();
+
+            while let Some(connection) = self.idle.ready(self.options.keep_alive_timeout) {
+                match workers.try_send(connection) {
+                    Ok(()) => self.pool.queue(),
+                    // Shed the load rather than queue without bound
+                    Err(TrySendError::Full(connection)) => refuse(connection.stream),
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+
+            let mut accepted = Err(IoError::from(ErrorKind::WouldBlock));
+
+            for listener in self.listeners() {
+                accepted = listener
+                    .set_nonblocking(true)
+                    .and_then(|_| listener.accept());
+
+                if !matches!(accepted, Err(ref e) if e.kind() == ErrorKind::WouldBlock) {
+                    break;
+                }
+            }
+
+            match accepted {
+                Ok((stream, _)) => {
+                    failures = 0;
+
+                    if let Some(connection) = self.admit(stream) {
+                        self.idle.park(connection);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => sleep(ACCEPT_POLL),
+                Err(e) => {
+                    if let Err(e) = self.accept_failed(e, &mut failures) {
+                        // Fatal, as for `next`: serving ends once the requests in
+                        // flight are answered
+                        self.log_error(&e);
+                        self.drain.shutdown_graceful(FATAL_ACCEPT_GRACE);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Answer the next request of `connection` with `handler`, reading it as it
+    /// comes. Parse errors are answered, and the connection sent to `recycle`
+    /// when kept alive.
+    fn serve_connection<F>(
+        &self,
+        connection: IdleConnection,
+        recycle: &Sender<IdleConnection>,
+        handler: &F,
+    ) where
+        F: Fn(&mut Request) -> Response,
+    {
+        let mut request = match self.accept_request(connection, recycle) {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(err) => {
+                self.log_error(&err);
+                return;
+            }
+        };
+
+        let response =
+            catch_unwind(AssertUnwindSafe(|| handler(&mut request))).unwrap_or_else(|_| {
+                let mut response = Response::empty();
+
+                response.set_status(Status::InternalServerError);
+
+                response
+            });
+
+        if !request.responded {
+            if let Err(err) = request.respond(response) {
+                self.log_error(&err.into());
+            }
         }
     }
+
+    /// Accept connections from `listener` until shut down, handing them to `sender`.
+    fn queue_connections(&self, listener: &TcpListener, sender: &Sender<IdleConnection>) {
+        let mut failures = 0;
+
+        for stream in listener.incoming() {
+            if self.drain.is_shutting_down() {
+                return;
+            }
+
+            match stream {
+                Ok(stream) => {
+                    failures = 0;
+
+                    if let Some(connection) = self.admit(stream) {
+                        let _ = sender.send(connection);
+                    }
+                }
+                Err(e) => {
+                    if let Err(e) = self.accept_failed(e, &mut failures) {
+                        // Fatal, as for `next`: serving ends once the requests in
+                        // flight are answered
+                        self.log_error(&e);
+                        self.drain.shutdown_graceful(FATAL_ACCEPT_GRACE);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Log an accept error the listener may recover from and wait before the
+    /// next accept, see `accept_retry_delay`. Fatal errors are given back.
+    fn accept_failed(&self, err: IoError, failures: &mut u32) -> IoResult<()> {
+        let Some(delay) = accept_retry_delay(&err, *failures) else {
+            return Err(err);
+        };
+
+        self.log_error(&err);
+
+        *failures += 1;
+        sleep(delay);
+
+        Ok(())
+    }
+
+    /// Hand `err` to `ServerOptions::logger`, see `Logger::with_errors`.
+    fn log_error(&self, err: &IoError) {
+        if let Some(ref logger) = self.options.logger {
+            logger.log_error(err);
+        }
+    }
+
+    /// A new connection, counted for `ServerOptions::max_connections` and
+    /// `max_connections_per_ip` until closed, and closed by a shutdown. `None`
+    /// when a limit is reached, once answered `503 Service Unavailable` or
+    /// `429 Too Many Requests`.
+    fn admit(&self, mut stream: TcpStream) -> Option<IdleConnection> {
+        if let Err(err) = self.configure_stream(&stream) {
+            self.log_error(&err);
+        }
+
+        let client = self
+            .options
+            .max_connections_per_ip
+            .zip(stream.peer_addr().ok())
+            .map(|(max, peer)| {
+                (
+                    client_key(peer.ip(), self.options.count_ipv6_by_subnet),
+                    max,
+                )
+            });
+
+        // Refused before reading anything, the request is never parsed
+        let mut slot = match ConnectionCounts::acquire(
+            &self.connections,
+            self.options.max_connections,
+            client,
+        ) {
+            Ok(slot) => slot,
+            Err(LimitReached::Server) => {
+                let _ = stream.write_all(SERVICE_UNAVAILABLE);
+                return None;
+            }
+            Err(LimitReached::Client) => {
+                let _ = stream.write_all(
+                        b"HTTP/1.1 429 Too Many Requests\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+                    );
+                return None;
+            }
+        };
+
+        slot.track(&stream);
+
+        Some(IdleConnection {
+            stream,
+            buffer: vec![],
+            requests: 0,
+            slot: Some(slot),
+            bytes: ByteCounts::default(),
+            since: Instant::now(),
+        })
+    }
+
+    /// Apply the TCP options of `ServerOptions` to an accepted connection.
+    fn configure_stream(&self, stream: &TcpStream) -> IoResult<()> {
+        if self.options.tcp_nodelay {
+            stream.set_nodelay(true)?;
+        }
+
+        if let Some(time) = self.options.tcp_keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait at most `ServerOptions::keep_alive_timeout` for the first bytes of a request,
+    /// returns whether some came.
+    fn wait_for_request(&self, stream: &TcpStream) -> bool {
+        let readable = stream
+            .set_read_timeout(self.options.keep_alive_timeout)
+            .is_ok()
+            && stream.peek(&mut [0]).is_ok_and(|read| read > 0);
+
+        readable && stream.set_read_timeout(None).is_ok()
+    }
+
+    /// Parse the next request of `connection`, `None` when the server answered
+    /// it itself (host redirect, maintenance, `OPTIONS *`). Once dropped, the request sends its
+    /// connection to `recycle` if kept alive.
+    fn accept_request(
+        &self,
+        connection: IdleConnection,
+        recycle: &Sender<IdleConnection>,
+    ) -> IoResult<Option<Request>> {
+        connection
+            .stream
+            .set_read_timeout(self.options.read_timeout)?;
+
+        let answer = connection.stream.try_clone();
+        let req = handle_idle_connection(connection, &self.options);
+
+        match req {
+            Ok(ref req) => self.versions.count(&req.version),
+            Err(ref err)
+                if err
+                    .get_ref()
+                    .is_some_and(|inner| inner.is::<Http09Refused>()) =>
+            {
+                self.versions.count(&Version::Http09)
+            }
+            Err(_) => {}
+        }
+
+        if let (Err(ref err), Ok(mut answer)) = (&req, answer) {
+            Server::answer_bad_request(&mut answer, err);
+        }
+
+        let mut req = req?;
+
+        req.in_flight = Some(self.drain.start());
+        req.recycle = Some(recycle.clone());
+        req.defaults = self.response_defaults();
+        req.strict_slashes = self.options.strict_slashes;
+        req.trust_proxy = self.options.trust_proxy;
+
+        // The server only speaks plain HTTP: an `https://` target doesn't make
+        // the connection secure, only a trusted proxy can say it was
+        req.uri.scheme = req.forwarded_proto().unwrap_or_else(|| "http".to_string());
+
+        if let Some(ref logger) = self.options.logger {
+            let uri = if self.options.log_canonical {
+                req.uri.canonical()
+            } else {
+                req.uri.to_string()
+            };
+            let head = String::from_utf8_lossy(&req.head_raw);
+
+            req.log = Some((
+                logger.clone(),
+                LogEvent {
+                    time: Local::now(),
+                    peer: req.peer_addr,
+                    method: req.method.clone(),
+                    uri,
+                    request_line: head.lines().next().unwrap_or_default().to_string(),
+                    status: None,
+                    duration: None,
+                    head_duration: Some(req.timings.head()),
+                    body_size: None,
+                    bytes_received: 0,
+                    bytes_sent: None,
+                    compression: None,
+                    referer: req.get_header("Referer").map(Header::value),
+                    user_agent: req.get_header("User-Agent").map(Header::value),
+                },
+            ));
+        }
+
+        if let (Some(logger), Some(threshold)) =
+            (&self.options.logger, self.options.slow_request_threshold)
+        {
+            if req.timings.receive() > threshold {
+                logger.log_slow_request(&SlowRequest {
+                    peer: req.peer_addr,
+                    method: req.method.clone(),
+                    uri: req.uri.to_string(),
+                    timings: req.timings,
+                });
+            }
+        }
+
+        if let Some(ref recorder) = self.options.recorder {
+            if recorder.is_enabled() {
+                req.record = Some((recorder.clone(), Local::now()));
+            }
+        }
+
+        if let Some(ref canonicalization) = self.options.host_canonicalization {
+            if let Some(response) = canonicalization.apply(&mut req) {
+                req.respond(response)?;
+                return Ok(None);
+            }
+        }
+
+        if let Some(response) = self.maintenance.check(&req) {
+            req.respond(response)?;
+            return Ok(None);
+        }
+
+        if self.answer_server_options(&mut req)? {
+            return Ok(None);
+        }
+
+        Ok(Some(req))
+    }
+
+    /// Answer every request with `503 Service Unavailable` while `config` is set,
+    /// except those for its `allow_paths`; `None` turns maintenance off.
+    ///
+    /// Clone `maintenance` to do this from a handler on another thread.
+    pub fn set_maintenance(&self, config: Option<MaintenanceConfig>) {
+        self.maintenance.set(config);
+    }
+
+    /// Answer `400 Bad Request` to a request that could not be parsed because
+    /// it is malformed, rather than dropping the connection silently.
+    ///
+    /// Clients speaking another protocol get a plain text hint, for whoever looks
+    /// at the raw bytes.
+    fn answer_bad_request(stream: &mut TcpStream, err: &IoError) {
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.downcast_ref::<NotHttp>().is_some())
+        {
+            let _ = stream.write_all(
+                b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 28\r\n\r\nThis port speaks plain HTTP\n",
+            );
+        } else if err
+            .get_ref()
+            .is_some_and(|inner| inner.downcast_ref::<BodyTooLarge>().is_some())
+        {
+            let _ = stream.write_all(
+                b"HTTP/1.1 413 Content Too Large\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            );
+        } else if err.get_ref().is_some_and(|inner| {
+            inner.downcast_ref::<HeadTooLarge>().is_some()
+                || inner.downcast_ref::<TooManyHeaders>().is_some()
+        }) {
+            let _ = stream.write_all(
+                b"HTTP/1.1 431 Request Header Fields Too Large\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            );
+        } else if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+            let _ = stream.write_all(
+                b"HTTP/1.1 408 Request Timeout\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            );
+        } else if err.kind() == ErrorKind::Unsupported {
+            let _ = stream.write_all(
+                b"HTTP/1.1 505 HTTP Version Not Supported\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            );
+        } else if matches!(
+            err.kind(),
+            ErrorKind::InvalidInput | ErrorKind::InvalidData | ErrorKind::UnexpectedEof
+        ) {
+            let _ = stream.write_all(
+                b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            );
+        }
+    }
+
+    fn response_defaults(&self) -> ResponseDefaults {
+        ResponseDefaults {
+            server_header: self.options.server_header.clone(),
+            compression_threshold: self.options.compression_threshold,
+            brotli_buffer_size: self.options.brotli_buffer_size,
+            max_ranges: self.options.max_ranges,
+            security_headers: self.options.security_headers,
+            keep_alive_timeout: self.options.keep_alive_timeout,
+            keep_alive_max_requests: self.options.keep_alive_max_requests,
+            debug_headers: self.options.debug_headers,
+        }
+    }
+
+    /// Respond to `OPTIONS *` when enabled, returns whether the request was answered.
+    fn answer_server_options(&self, request: &mut Request) -> IoResult<bool> {
+        if !self.options.answer_server_options
+            || request.method != Method::Options
+            || !request.uri.is_asterisk()
+        {
+            return Ok(false);
+        }
+
+        let mut response = Response::empty();
+
+        response
+            .set_status(Status::NoContent)
+            .add_header(Header::Allow(self.options.allowed_methods.clone()));
+
+        request.respond(response)?;
+
+        Ok(true)
+    }
+
+    /// A summary of how the server is configured, for startup logs or an admin endpoint.
+    pub fn info(&self) -> ServerInfo {
+        ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            address: self.listener.local_addr().ok(),
+            log: self.options.logger.is_some(),
+            answer_server_options: self.options.answer_server_options,
+            allowed_methods: self.options.allowed_methods.clone(),
+            encodings: vec![
+                BodyEncoding::Gzip,
+                BodyEncoding::Deflate,
+                BodyEncoding::Brotli,
+            ],
+            workers: self.pool.workers(),
+            max_body_size: self.options.max_body_size,
+            max_head_size: self.options.max_head_size,
+            read_timeout: self.options.read_timeout,
+            keep_alive_timeout: self.options.keep_alive_timeout,
+            requests_by_version: [Version::Http09, Version::Http10, Version::Http11]
+                .into_iter()
+                .map(|version| (version.to_string(), self.versions.get(&version)))
+                .chain([("other".to_string(), self.versions.get(&Version::Http20))])
+                .collect(),
+        }
+    }
+
+    pub fn requests(&self) -> Requests {
+        Requests { server: self }
+    }
+
+    /// Accept and parse requests on a thread of its own, sending them to the
+    /// returned receiver in the order they are read, e.g. to handle them from
+    /// an existing event loop.
+    ///
+    /// Past `ServerOptions::channel_capacity` requests waiting in the channel,
+    /// the thread stops accepting until some are received. It runs until
+    /// `ServerHandle::stop`, or its next request once the receiver is dropped.
+    /// The receiver still gets the requests already sent, then `Err`.
+    pub fn into_channel(self) -> (Receiver<Request>, ServerHandle) {
+        let (sender, receiver) = sync_channel(self.options.channel_capacity);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+
+            spawn(move || {
+                while !stop.load(Ordering::Acquire) {
+                    let mut request = match self.try_next(CHANNEL_POLL) {
+                        Ok(Some(request)) => request,
+                        Err(AcceptError::Shutdown) => return,
+                        _ => continue,
+                    };
+
+                    // Wait for room in the channel, unless stopped meanwhile
+                    loop {
+                        match sender.try_send(request) {
+                            Ok(()) => break,
+                            Err(TrySendError::Full(full)) if !stop.load(Ordering::Acquire) => {
+                                request = full;
+                                sleep(CHANNEL_POLL / 10);
+                            }
+                            Err(_) => return,
+                        }
+                    }
+                }
+            })
+        };
+
+        (receiver, ServerHandle { stop, thread })
+    }
+
+    /// Configuration worth a look before serving, to print at startup.
+    pub fn preflight(&self) -> Vec<PreflightWarning> {
+        let mut warnings = vec![];
+        let public = self
+            .listener
+            .local_addr()
+            .is_ok_and(|address| address.ip().is_unspecified());
+
+        if public {
+            warnings.push(PreflightWarning::warning(
+                "listening on every interface, the server is reachable from other hosts",
+            ));
+
+            if self.options.parse_mode == ParseMode::Lenient {
+                warnings.push(PreflightWarning::info(
+                    "lenient parsing on a public interface, consider `ParseMode::Strict`",
+                ));
+            }
+        }
+
+        if self.options.max_body_size.is_none() {
+            warnings.push(PreflightWarning::info(
+                "no request body size limit, consider `max_body_size`",
+            ));
+        }
+
+        if self.options.logger.is_none() {
+            warnings.push(PreflightWarning::info("request logging is disabled"));
+        }
+
+        warnings
+    }
+
+    /// Whether a server answers HTTP on `address`, with an `OPTIONS *` request.
+    /// For readiness checks from another thread or process: the server must be
+    /// calling `next` to answer.
+    pub fn probe(address: SocketAddr) -> bool {
+        let timeout = Duration::from_secs(2);
+        let Ok(mut stream) = TcpStream::connect_timeout(&address, timeout) else {
+            return false;
+        };
+        let request = format!(
+            "OPTIONS * HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            address
+        );
+        let mut status = [0; 9];
+
+        stream.set_read_timeout(Some(timeout)).is_ok()
+            && stream.write_all(request.as_bytes()).is_ok()
+            && stream.read_exact(&mut status).is_ok()
+            && status.starts_with(b"HTTP/1.")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreflightLevel {
+    Info,
+    Warning,
+}
+
+/// A finding of `Server::preflight`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreflightWarning {
+    pub level: PreflightLevel,
+    pub message: String,
+}
+
+impl PreflightWarning {
+    pub fn info(message: &str) -> PreflightWarning {
+        PreflightWarning {
+            level: PreflightLevel::Info,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn warning(message: &str) -> PreflightWarning {
+        PreflightWarning {
+            level: PreflightLevel::Warning,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for PreflightWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.level {
+            PreflightLevel::Info => write!(f, "info: {}", self.message),
+            PreflightLevel::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    pub version: String,
+    pub address: Option<SocketAddr>,
+    pub log: bool,
+    pub answer_server_options: bool,
+    pub allowed_methods: Vec<Method>,
+    pub encodings: Vec<BodyEncoding>,
+    /// Threads `Server::serve` handles requests on.
+    pub workers: usize,
+    pub max_body_size: Option<usize>,
+    pub max_head_size: usize,
+    pub read_timeout: Option<Duration>,
+    pub keep_alive_timeout: Option<Duration>,
+    /// Requests received so far by version, `("HTTP/1.1", 42)`, see `Server::versions`.
+    pub requests_by_version: Vec<(String, u64)>,
+}
+
+impl ServerInfo {
+    fn address(&self) -> String {
+        match self.address {
+            Some(address) => address.to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+
+    fn methods(&self) -> Vec<String> {
+        self.allowed_methods
+            .iter()
+            .map(|method| method.to_string())
+            .collect()
+    }
+
+    fn encodings(&self) -> Vec<String> {
+        self.encodings
+            .iter()
+            .map(|encoding| encoding.to_string())
+            .collect()
+    }
+
+    pub fn render_text(&self) -> String {
+        let switch = |on: bool| if on { "on" } else { "off" };
+        let limit = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+
+        format!(
+            "JulMan-Http/{}\n\
+             address: {}\n\
+             log: {}\n\
+             answer OPTIONS *: {}\n\
+             allowed methods: {}\n\
+             encodings: {}\n\
+             workers: {}\n\
+             max body size: {}\n\
+             max head size: {} bytes\n\
+             read timeout: {}\n\
+             keep-alive timeout: {}\n\
+             requests: {}\n",
+            self.version,
+            self.address(),
+            switch(self.log),
+            switch(self.answer_server_options),
+            self.methods().join(", "),
+            self.encodings().join(", "),
+            self.workers,
+            limit(self.max_body_size.map(|size| format!("{} bytes", size))),
+            self.max_head_size,
+            limit(self.read_timeout.map(|timeout| format!("{:?}", timeout))),
+            limit(
+                self.keep_alive_timeout
+                    .map(|timeout| format!("{:?}", timeout))
+            ),
+            self.requests_by_version
+                .iter()
+                .map(|(version, count)| format!("{} {}", version, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    /// The summary as a JSON object, timeouts in milliseconds.
+    #[cfg(feature = "json")]
+    pub fn render_json(&self) -> String {
+        let ms = |timeout: Option<Duration>| timeout.map(|timeout| timeout.as_millis() as u64);
+
+        Value::object()
+            .insert("version", self.version.as_str())
+            .insert("address", self.address.map(|address| address.to_string()))
+            .insert("log", self.log)
+            .insert("answer_server_options", self.answer_server_options)
+            .insert("allowed_methods", self.methods())
+            .insert("encodings", self.encodings())
+            .insert("workers", self.workers)
+            .insert("max_body_size", self.max_body_size)
+            .insert("max_head_size", self.max_head_size)
+            .insert("read_timeout_ms", ms(self.read_timeout))
+            .insert("keep_alive_timeout_ms", ms(self.keep_alive_timeout))
+            .insert(
+                "requests_by_version",
+                Value::Object(
+                    self.requests_by_version
+                        .iter()
+                        .map(|(version, count)| (version.clone(), Value::from(*count)))
+                        .collect(),
+                ),
+            )
+            .to_string()
+    }
+}
+
+impl Display for ServerInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.render_text())
+    }
+}
+
+/// How often the thread of `Server::into_channel` checks whether it was stopped.
+const CHANNEL_POLL: Duration = Duration::from_millis(50);
+
+/// Answer `503 Service Unavailable` to a connection with a request waiting, and
+/// close it. The request is read first: closing with unread bytes resets the
+/// connection, the answer lost along.
+fn refuse(mut stream: TcpStream) {
+    let mut chunk = [0; 2048];
+
+    if stream.set_nonblocking(true).is_ok() {
+        while matches!(stream.read(&mut chunk), Ok(read) if read > 0) {}
+    }
+
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.write_all(SERVICE_UNAVAILABLE);
+}
+
+/// Answered to connections refused for lack of room.
+const SERVICE_UNAVAILABLE: &[u8] =
+    b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 1\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+
+/// How long accepting waits before polling the listeners and idle connections
+/// again, when none had anything.
+const ACCEPT_POLL: Duration = Duration::from_millis(5);
+
+/// How often an idle worker of `Server::serve` checks for a shutdown.
+const WORKER_POLL: Duration = Duration::from_millis(100);
+
+/// How long the requests in flight of `Server::serve` get to finish once
+/// accepting failed for good.
+const FATAL_ACCEPT_GRACE: Duration = Duration::from_secs(10);
+
+/// The thread of `Server::into_channel`, running until `stop`. Dropping the
+/// handle leaves it running.
+#[derive(Debug)]
+pub struct ServerHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// Stop accepting and wait for the thread to finish, closing the channel.
+    /// A request still waiting for room in the channel is dropped.
+    ///
+    /// A client still sending its request may hold this up to
+    /// `ServerOptions::read_timeout`.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Release);
+
+        let _ = self.thread.join();
+    }
+}
+
+pub struct Requests<'a> {
+    pub server: &'a Server,
+}
+
+/// Ends once the server is shut down, an error only concerns the connection
+/// it happened on.
+impl<'a> Iterator for Requests<'a> {
+    type Item = Result<Request, AcceptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.server.next().map_err(AcceptError::from) {
+            Err(AcceptError::Shutdown) => None,
+            result => Some(result),
+        }
+    }
+}
+
+/// Why `Server::next` (through `Server::requests`) or `Server::try_next` gave no
+/// request. Malformed requests and timeouts were already answered to the client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AcceptError {
+    /// Accepting or reading the connection failed.
+    Io(ErrorKind),
+    /// The request was malformed, too large or of an unsupported version.
+    Parse(String),
+    /// The client took longer than `ServerOptions::read_timeout` to send its request.
+    Timeout,
+    /// The server was shut down, see `Server::shutdown_graceful`.
+    Shutdown,
+}
+
+impl From<IoError> for AcceptError {
+    fn from(err: IoError) -> Self {
+        let rejected = err.get_ref().is_some_and(|inner| {
+            inner.downcast_ref::<NotHttp>().is_some()
+                || inner.downcast_ref::<BodyTooLarge>().is_some()
+                || inner.downcast_ref::<HeadTooLarge>().is_some()
+                || inner.downcast_ref::<TooManyHeaders>().is_some()
+        });
+
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.downcast_ref::<ShutDown>().is_some())
+        {
+            return AcceptError::Shutdown;
+        }
+
+        match err.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => AcceptError::Timeout,
+            ErrorKind::InvalidInput
+            | ErrorKind::InvalidData
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::Unsupported => AcceptError::Parse(err.to_string()),
+            _ if rejected => AcceptError::Parse(err.to_string()),
+            kind => AcceptError::Io(kind),
+        }
+    }
+}
+
+impl Display for AcceptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            AcceptError::Io(kind) => write!(f, "Connection error: {}", kind),
+            AcceptError::Parse(message) => write!(f, "Malformed request: {}", message),
+            AcceptError::Timeout => write!(f, "Timed out reading the request"),
+            AcceptError::Shutdown => write!(f, "The server was shut down"),
+        }
+    }
+}
+
+impl std::error::Error for AcceptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cookie::ResponseCookie;
+    use crate::response::ResponseBody;
+
+    fn local(options: ServerOptions) -> Server {
+        Server::bind((Ipv4Addr::LOCALHOST, 0), Some(options)).unwrap()
+    }
+
+    /// Send `request` on a new connection, returns the whole response.
+    fn exchange(address: SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut response = String::new();
+
+        stream.write_all(request).unwrap();
+        stream.read_to_string(&mut response).unwrap();
+
+        response
+    }
+
+    /// Serve `handler` on a new local server, returns its address, a handle to
+    /// shut it down and the serving thread.
+    fn start<F>(options: ServerOptions, handler: F) -> (SocketAddr, Drain, JoinHandle<()>)
+    where
+        F: Fn(&mut Request) -> Response + Send + Sync + 'static,
+    {
+        let server = local(options);
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+
+        (address, drain, spawn(move || server.serve(handler)))
+    }
+
+    fn hello(_: &mut Request) -> Response {
+        let mut response = Response::empty();
+
+        response.set_body(ResponseBody::Text("hello".to_string()));
+
+        response
+    }
+
+    const GET_CLOSE: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+    #[test]
+    fn preflight_reports_missing_limits_and_logging() {
+        let warnings = local(ServerOptions::default()).preflight();
+
+        assert!(warnings.contains(&PreflightWarning::info(
+            "no request body size limit, consider `max_body_size`"
+        )));
+        assert!(warnings.contains(&PreflightWarning::info("request logging is disabled")));
+
+        let warnings = local(ServerOptions::hardened().with_stdout_log()).preflight();
+
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn serve_with_state_shares_the_state() {
+        use std::sync::atomic::AtomicUsize;
+
+        let server = local(ServerOptions {
+            workers: Some(4),
+            ..ServerOptions::default()
+        });
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let serving = {
+            let counter = Arc::clone(&counter);
+
+            spawn(move || {
+                server.serve_with_state(counter, |counter, _| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+
+                    Response::empty()
+                })
+            })
+        };
+
+        let clients: Vec<_> = (0..8)
+            .map(|_| {
+                spawn(move || {
+                    for _ in 0..5 {
+                        assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 200"));
+                    }
+                })
+            })
+            .collect();
+
+        for client in clients {
+            client.join().unwrap();
+        }
+
+        assert_eq!(counter.load(Ordering::SeqCst), 40);
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn idle_connections_hold_no_worker() {
+        let server = local(ServerOptions {
+            workers: Some(1),
+            ..ServerOptions::default()
+        });
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let serving = spawn(move || server.serve(|_| Response::empty()));
+
+        // One connection that never sends anything, one kept alive after a request
+        let _silent = TcpStream::connect(address).unwrap();
+        let mut kept = TcpStream::connect(address).unwrap();
+        let mut head = vec![];
+        let mut byte = [0];
+
+        kept.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+
+        while !head.ends_with(b"\r\n\r\n") {
+            kept.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+
+        let started = Instant::now();
+
+        assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 200"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn resizing_the_pool_adds_workers() {
+        let server = local(ServerOptions {
+            workers: Some(2),
+            ..ServerOptions::default()
+        });
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let pool = server.pool.clone();
+        let serving = spawn(move || {
+            server.serve(|_| {
+                sleep(Duration::from_millis(300));
+
+                Response::empty()
+            })
+        });
+        // Four slow requests at once, returns how long the last one took
+        let burst = || {
+            let started = Instant::now();
+            let clients: Vec<_> = (0..4)
+                .map(|_| spawn(move || exchange(address, GET_CLOSE)))
+                .collect();
+
+            for client in clients {
+                assert!(client.join().unwrap().starts_with("HTTP/1.1 200"));
+            }
+
+            started.elapsed()
+        };
+
+        assert!(burst() >= Duration::from_millis(600));
+        assert_eq!((pool.workers(), pool.live()), (2, 2));
+
+        pool.set_workers(4);
+
+        assert!(burst() < Duration::from_millis(600));
+        assert_eq!((pool.workers(), pool.live()), (4, 4));
+
+        pool.set_workers(1);
+
+        // Idle workers take turns waiting for a connection, each one retiring after
+        let started = Instant::now();
+
+        while pool.live() > 1 && started.elapsed() < Duration::from_secs(2) {
+            sleep(WORKER_POLL);
+        }
+
+        assert_eq!((pool.workers(), pool.live()), (1, 1));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn full_worker_queue_answers_503() {
+        let server = local(ServerOptions {
+            workers: Some(1),
+            worker_queue: 1,
+            ..ServerOptions::default()
+        });
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let serving = spawn(move || {
+            server.serve(|_| {
+                sleep(Duration::from_millis(500));
+
+                Response::empty()
+            })
+        });
+
+        // The first request holds the only worker, the second waits in the queue
+        let handled = spawn(move || exchange(address, GET_CLOSE));
+        sleep(Duration::from_millis(100));
+        let queued = spawn(move || exchange(address, GET_CLOSE));
+        sleep(Duration::from_millis(100));
+
+        assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 503 Service Unavailable"));
+        assert!(handled.join().unwrap().starts_with("HTTP/1.1 200"));
+        assert!(queued.join().unwrap().starts_with("HTTP/1.1 200"));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn malformed_requests_are_parse_errors() {
+        let server = local(ServerOptions::default());
+        let address = server.local_addr().unwrap();
+        let client = spawn(move || {
+            let malformed = exchange(
+                address,
+                b"GET / HTTP/1.1\r\nHost: localhost\r\nDNT: maybe\r\n\r\n",
+            );
+
+            (malformed, exchange(address, GET_CLOSE))
+        });
+        let mut requests = server.requests();
+
+        assert!(matches!(requests.next(), Some(Err(AcceptError::Parse(_)))));
+        assert_eq!(requests.next().unwrap().unwrap().uri.path, "/");
+
+        let (malformed, _) = client.join().unwrap();
+
+        assert!(
+            malformed.starts_with("HTTP/1.1 400 Bad Request"),
+            "{}",
+            malformed
+        );
+    }
+
+    #[test]
+    fn oversized_heads_answer_431() {
+        let options = ServerOptions {
+            max_head_size: 256,
+            max_headers: 4,
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, hello);
+        let large = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\n\r\n",
+            "a".repeat(300)
+        );
+        let many = "GET / HTTP/1.1\r\nHost: localhost\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n";
+
+        for head in [large.as_str(), many] {
+            assert!(exchange(address, head.as_bytes())
+                .starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        }
+
+        assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 200 OK\r\n"));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    /// Read the head of one response to a body-less request from `stream`.
+    fn read_head(stream: &mut TcpStream) -> String {
+        let mut head = vec![];
+        let mut byte = [0];
+
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+
+        String::from_utf8(head).unwrap()
+    }
+
+    #[test]
+    fn keep_alive_max_counts_down() {
+        let options = ServerOptions {
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            keep_alive_max_requests: Some(3),
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, |_| Response::empty());
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut heads = vec![];
+
+        for _ in 0..3 {
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+            heads.push(read_head(&mut stream));
+        }
+
+        assert!(
+            heads[0].contains("Keep-Alive: timeout=5, max=2\r\n"),
+            "{}",
+            heads[0]
+        );
+        assert!(
+            heads[1].contains("Keep-Alive: timeout=5, max=1\r\n"),
+            "{}",
+            heads[1]
+        );
+        // The last request closes the connection
+        assert!(heads[2].contains("Connection: close\r\n"), "{}", heads[2]);
+        assert!(!heads[2].contains("Keep-Alive:"), "{}", heads[2]);
+        assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn idle_keep_alive_connections_close() {
+        let options = ServerOptions {
+            keep_alive_timeout: Some(Duration::from_millis(200)),
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, |_| Response::empty());
+        let mut stream = TcpStream::connect(address).unwrap();
+
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+
+        assert!(read_head(&mut stream).contains("Connection: keep-alive\r\n"));
+
+        // Closed once idle past the timeout, not before
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+
+        let idle = Instant::now();
+
+        assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+        assert!(idle.elapsed() >= Duration::from_millis(150));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn half_closed_connections_are_answered() {
+        let (address, drain, serving) = start(ServerOptions::default(), hello);
+        // The whole request then FIN: answered on the open write side
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut response = String::new();
+
+        stream.write_all(GET_CLOSE).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+        // Half a head then FIN: refused, never parsed as a request
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut response = String::new();
+
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: loc").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            "{}",
+            response
+        );
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    /// A ClientHello for `localhost` offering two TLS 1.3 cipher suites, as sent
+    /// to a plain HTTP port by `https://localhost:<port>`.
+    const CLIENT_HELLO: &[u8] = b"\x16\x03\x01\x00\x43\x01\x00\x00\x3f\x03\x03\
+        \x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\
+        \x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c\x1d\x1e\x1f\
+        \x00\x00\x04\x13\x01\x13\x02\x01\x00\x00\x12\x00\x00\x00\x0e\x00\x0c\x00\x00\x09localhost";
+
+    #[test]
+    fn tls_handshakes_get_a_hint() {
+        let handled = Arc::new(AtomicBool::new(false));
+        let (address, drain, serving) = start(ServerOptions::default(), {
+            let handled = Arc::clone(&handled);
+
+            move |request| {
+                handled.store(true, Ordering::SeqCst);
+                hello(request)
+            }
+        });
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut response = String::new();
+
+        stream.write_all(CLIENT_HELLO).unwrap();
+        // Closed by the server right after the hint, no FIN needed
+        stream.read_to_string(&mut response).unwrap();
+
+        assert_eq!(
+            response,
+            "HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 28\r\n\r\n\
+             This port speaks plain HTTP\n"
+        );
+        assert!(!handled.load(Ordering::SeqCst));
+
+        let response = exchange(address, b"SSH-2.0-OpenSSH_9.6\r\n");
+
+        assert!(
+            response.ends_with("This port speaks plain HTTP\n"),
+            "{}",
+            response
+        );
+        assert!(!handled.load(Ordering::SeqCst));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    /// Hands out its accept results in order, `WouldBlock` once out of them.
+    struct ScriptedListener(Mutex<Vec<IoResult<(TcpStream, SocketAddr)>>>);
+
+    impl Accept for ScriptedListener {
+        fn set_nonblocking(&self, _: bool) -> IoResult<()> {
+            Ok(())
+        }
+
+        fn accept(&self) -> IoResult<(TcpStream, SocketAddr)> {
+            let mut script = self.0.lock().unwrap();
+
+            match script.is_empty() {
+                true => Err(ErrorKind::WouldBlock.into()),
+                false => script.remove(0),
+            }
+        }
+    }
+
+    #[test]
+    fn try_next_maps_accept_errors() {
+        let logged = Arc::new(Mutex::new(vec![]));
+        let server = local(ServerOptions {
+            logger: Some(Logger::new(|_| {}).with_errors({
+                let logged = Arc::clone(&logged);
+
+                move |err| logged.lock().unwrap().push(err.kind())
+            })),
+            ..ServerOptions::default()
+        });
+        let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        let (stream, peer) = server.listener.accept().unwrap();
+
+        client.write_all(GET_CLOSE).unwrap();
+
+        let listener = ScriptedListener(Mutex::new(vec![
+            Err(ErrorKind::WouldBlock.into()),
+            Err(ErrorKind::Interrupted.into()),
+            Ok((stream, peer)),
+            Err(ErrorKind::WouldBlock.into()),
+            Err(ErrorKind::ConnectionAborted.into()),
+            Err(ErrorKind::PermissionDenied.into()),
+        ]));
+        let timeout = Duration::from_secs(2);
+
+        // Retried past `WouldBlock` and `Interrupted` up to the connection
+        let request = server.try_next_from(timeout, || vec![&listener]);
+
+        assert_eq!(request.unwrap().unwrap().uri.path, "/");
+
+        // An aborted connection is retried too, a fatal error is returned
+        assert_eq!(
+            server
+                .try_next_from(timeout, || vec![&listener])
+                .unwrap_err(),
+            AcceptError::Io(ErrorKind::PermissionDenied)
+        );
+        assert_eq!(
+            *logged.lock().unwrap(),
+            [ErrorKind::Interrupted, ErrorKind::ConnectionAborted]
+        );
+
+        // Nothing but `WouldBlock` until the timeout
+        assert!(server
+            .try_next_from(Duration::from_millis(50), || vec![&listener])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn graceful_shutdown_completes_requests_in_flight() {
+        let (started, handling) = channel();
+        let (address, drain, serving) = start(ServerOptions::default(), move |request| {
+            started.send(()).unwrap();
+            sleep(Duration::from_millis(200));
+            hello(request)
+        });
+        let mut stream = TcpStream::connect(address).unwrap();
+
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        handling.recv().unwrap();
+
+        assert_eq!(drain.in_flight(), 1);
+        assert!(drain.shutdown_graceful(Duration::from_secs(5)));
+        assert_eq!(drain.in_flight(), 0);
+
+        // The whole response, closing the kept-alive connection
+        let mut response = String::new();
+
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Connection: close\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn options_asterisk_answered_by_the_server() {
+        const OPTIONS: &[u8] =
+            b"OPTIONS * HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+        let options = ServerOptions {
+            answer_server_options: true,
+            allowed_methods: vec![Method::Get, Method::Head, Method::Options],
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, hello);
+        let response = exchange(address, OPTIONS);
+
+        assert!(
+            response.starts_with("HTTP/1.1 204 No Content\r\n"),
+            "{}",
+            response
+        );
+        assert!(
+            response.contains("Allow: GET, HEAD, OPTIONS\r\n"),
+            "{}",
+            response
+        );
+        assert!(response.ends_with("\r\n\r\n"), "{}", response);
+
+        // A path is for the handler to answer
+        let response = exchange(
+            address,
+            b"OPTIONS /items HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+        drain.shutdown();
+        serving.join().unwrap();
+
+        // Turned off, `*` goes to the handler too
+        let (address, drain, serving) = start(ServerOptions::default(), hello);
+        let response = exchange(address, OPTIONS);
+
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn quickstart_moves_past_a_busy_port() {
+        let busy = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let port = busy.local_addr().unwrap().port();
+        let fallback = PortFallback {
+            delay: Duration::from_millis(10),
+            ports: 20,
+            ..PortFallback::default()
+        };
+
+        let (server, bound) = Server::quickstart_with(port, None, fallback.clone()).unwrap();
+
+        // The next free one, usually `port + 1`
+        assert!(
+            bound > port && bound <= port + 20,
+            "{} after {}",
+            bound,
+            port
+        );
+        assert_eq!(server.local_addr().unwrap().port(), bound);
+        assert_eq!(server.port, bound);
+
+        let no_fallback = PortFallback {
+            tries: 2,
+            ports: 0,
+            ..fallback
+        };
+
+        assert!(matches!(
+            Server::quickstart_with(port, None, no_fallback.clone()),
+            Err(BindError::PortAlreadyInUse)
+        ));
+
+        let ephemeral = PortFallback {
+            ephemeral: true,
+            ..no_fallback
+        };
+        let (server, bound) = Server::quickstart_with(port, None, ephemeral).unwrap();
+
+        assert_ne!(bound, port);
+        assert_eq!(server.local_addr().unwrap().port(), bound);
+    }
+
+    #[test]
+    fn bodies_framed_by_their_headers_only() {
+        let (address, drain, serving) = start(ServerOptions::default(), |request| {
+            let mut response = Response::empty();
+
+            response.set_body(ResponseBody::Text(format!(
+                "{:?} {} {} {:?}",
+                request.method, request.uri.path, request.has_body, request.body
+            )));
+
+            response
+        });
+
+        // An empty body, then the next request pipelined right after it
+        let response = exchange(
+            address,
+            b"GET /first HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n\
+            GET /second HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+
+        assert!(
+            response.contains("\r\n\r\nGet /first true \"\""),
+            "{}",
+            response
+        );
+        assert!(
+            response.ends_with("\r\n\r\nGet /second false \"\""),
+            "{}",
+            response
+        );
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 2);
+
+        // A body on a method that usually has none is read, not left for the next request
+        let response = exchange(
+            address,
+            b"DELETE /item HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\n0123456789\
+            GET /after HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+
+        assert!(
+            response.contains("\r\n\r\nDelete /item true \"0123456789\""),
+            "{}",
+            response
+        );
+        assert!(
+            response.ends_with("\r\n\r\nGet /after false \"\""),
+            "{}",
+            response
+        );
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn maintenance_toggled_at_runtime() {
+        use crate::date::RetryAfter;
+
+        let server = local(ServerOptions::default());
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let maintenance = server.maintenance.clone();
+        let serving = spawn(move || server.serve(hello));
+        let get = |path: &str, accept: &str| {
+            exchange(
+                address,
+                format!(
+                    "GET {} HTTP/1.1\r\nHost: localhost\r\nAccept: {}\r\nConnection: close\r\n\r\n",
+                    path, accept
+                )
+                .as_bytes(),
+            )
+        };
+
+        assert!(get("/", "*/*").starts_with("HTTP/1.1 200 OK\r\n"));
+
+        maintenance.set(Some(MaintenanceConfig {
+            retry_after: RetryAfter::Seconds(120),
+            message: "Back <soon>".to_string(),
+            allow_paths: vec!["/healthz".to_string()],
+        }));
+
+        let response = get("/", "*/*");
+
+        assert!(
+            response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{}",
+            response
+        );
+        assert!(response.contains("Retry-After: 120\r\n"), "{}", response);
+        assert!(
+            response.contains("Cache-Control: no-store\r\n"),
+            "{}",
+            response
+        );
+        assert!(response.ends_with("\r\n\r\nBack <soon>"), "{}", response);
+
+        let response = get("/orders", "text/html");
+
+        assert!(response.starts_with("HTTP/1.1 503 "), "{}", response);
+        assert!(
+            response.contains("<p>Back &lt;soon&gt;</p>"),
+            "{}",
+            response
+        );
+
+        // The allow-list still reaches the application
+        assert!(get("/healthz", "*/*").ends_with("\r\n\r\nhello"));
+
+        maintenance.set(None);
+
+        assert!(get("/", "*/*").starts_with("HTTP/1.1 200 OK\r\n"));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn hardened_and_development_presets() {
+        let hardened = ServerOptions::hardened();
+
+        assert_eq!(hardened.max_head_size, 8 * 1024);
+        assert_eq!(hardened.max_body_size, Some(1024 * 1024));
+        assert_eq!(hardened.read_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(hardened.parse_mode, ParseMode::Strict);
+        assert!(!hardened.http09 && hardened.security_headers);
+
+        let development = ServerOptions::development();
+
+        assert!(development.logger.is_some());
+        assert_eq!(development.max_body_size, None);
+        assert!(!development.security_headers);
+
+        // Over the hardened body limit, and not strictly parsable
+        let large_body = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            2 << 20,
+            "a".repeat(2 << 20)
+        );
+        let bare_lf = "GET / HTTP/1.1\nHost: localhost\nConnection: close\n\n".to_string();
+        let (address, drain, serving) = start(hardened, hello);
+        // Rejected on its Content-Length: sending the body would only get the connection reset
+        let head_end = large_body.find("\r\n\r\n").unwrap() + 4;
+
+        assert!(exchange(address, &large_body.as_bytes()[..head_end]).starts_with("HTTP/1.1 413 "));
+        assert!(exchange(address, bare_lf.as_bytes()).starts_with("HTTP/1.1 400 "));
+
+        let response = exchange(address, GET_CLOSE);
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(
+            response.contains("X-Content-Type-Options: nosniff\r\n"),
+            "{}",
+            response
+        );
+
+        drain.shutdown();
+        serving.join().unwrap();
+
+        let (address, drain, serving) = start(development, hello);
+
+        for request in [large_body, bare_lf] {
+            assert!(exchange(address, request.as_bytes()).starts_with("HTTP/1.1 200 OK\r\n"));
+        }
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn dual_stack_binding() {
+        let server = Server::bind_dual(0, None).unwrap();
+        let port = server.port;
+        let ipv6 = server.listener.local_addr().unwrap().is_ipv6();
+        let drain = server.drain.clone();
+        let serving = spawn(move || server.serve(hello));
+
+        // Answered on both families, whichever listener takes them
+        for _ in 0..3 {
+            let response = exchange(SocketAddr::from((Ipv4Addr::LOCALHOST, port)), GET_CLOSE);
+
+            assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+            if ipv6 {
+                let response = exchange(SocketAddr::from((Ipv6Addr::LOCALHOST, port)), GET_CLOSE);
+
+                assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+            }
+        }
+
+        // The port is taken on either family now
+        let err = Server::bind_dual(port, None).unwrap_err();
+
+        assert!(matches!(err, BindError::PortAlreadyInUse));
+        assert_eq!(err.to_string(), "Port already in use");
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn bind_errors_propagate() {
+        fn bind_twice() -> Result<(), Box<dyn std::error::Error>> {
+            let server = Server::bind((Ipv4Addr::LOCALHOST, 0), None)?;
+
+            Server::bind((Ipv4Addr::LOCALHOST, server.port), None)?;
+
+            Ok(())
+        }
+
+        let err = bind_twice().unwrap_err();
+
+        assert!(err.is::<BindError>());
+        assert_eq!(err.to_string(), "Port already in use");
+
+        // Not an address of this host
+        assert!(matches!(
+            Server::bind((Ipv4Addr::new(192, 0, 2, 1), 0), None),
+            Err(BindError::AddrNotAvailable)
+        ));
+        assert_eq!(
+            BindError::from(IoError::from(ErrorKind::AddrNotAvailable)).to_string(),
+            "Address not available"
+        );
+    }
+
+    #[test]
+    fn connections_capped_per_client() {
+        let options = ServerOptions {
+            max_connections_per_ip: Some(2),
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, |_| Response::empty());
+        // A connection from `source`, a loopback address, answered once
+        let connect = |source: Ipv4Addr| {
+            let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+
+            socket.bind(&SocketAddr::from((source, 0)).into()).unwrap();
+            socket.connect(&address.into()).unwrap();
+
+            let mut stream: TcpStream = socket.into();
+
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+
+            let head = read_head(&mut stream);
+
+            (stream, head)
+        };
+
+        let first = connect(Ipv4Addr::LOCALHOST);
+        let second = connect(Ipv4Addr::LOCALHOST);
+
+        for (_, head) in [&first, &second] {
+            assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+        }
+
+        // The client's third connection is refused, another client still gets in
+        let (_, head) = connect(Ipv4Addr::LOCALHOST);
+
+        assert!(
+            head.starts_with("HTTP/1.1 429 Too Many Requests\r\n"),
+            "{}",
+            head
+        );
+
+        let (_, head) = connect(Ipv4Addr::new(127, 0, 0, 2));
+
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+
+        // Closing one gives its place back, once the server sees it closed
+        drop(first);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            let (_, head) = connect(Ipv4Addr::LOCALHOST);
+
+            if head.starts_with("HTTP/1.1 200 OK\r\n") {
+                break;
+            }
+
+            assert!(Instant::now() < deadline, "{}", head);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        drop(second);
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn spawning_serves_clients_side_by_side() {
+        let options = ServerOptions {
+            spawn_per_connection: true,
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, |request| {
+            if request.uri.path == "/panic" {
+                panic!("handler failure");
+            }
+
+            hello(request)
+        });
+
+        // A slow upload holds its own thread only
+        let mut upload = TcpStream::connect(address).unwrap();
+
+        upload
+            .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\n01234")
+            .unwrap();
+
+        assert!(exchange(address, GET_CLOSE).ends_with("\r\n\r\nhello"));
+
+        upload.write_all(b"56789").unwrap();
+
+        assert!(read_head(&mut upload).starts_with("HTTP/1.1 200 OK\r\n"));
+
+        // A panic answers 500 and the connection goes on
+        let mut stream = TcpStream::connect(address).unwrap();
+
+        stream
+            .write_all(b"GET /panic HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+
+        let head = read_head(&mut stream);
+
+        assert!(
+            head.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+            "{}",
+            head
+        );
+
+        let length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(0, |length| length.parse().unwrap());
+
+        stream.read_exact(&mut vec![0; length]).unwrap();
+        stream.write_all(GET_CLOSE).unwrap();
+
+        let mut response = String::new();
+
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+        drop(upload);
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn spawning_waits_for_a_free_thread() {
+        let options = ServerOptions {
+            spawn_per_connection: true,
+            max_connection_threads: 1,
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, |_| Response::empty());
+        let request = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut first = TcpStream::connect(address).unwrap();
+
+        first.write_all(request).unwrap();
+        read_head(&mut first);
+
+        // The kept-alive first connection holds the only thread
+        let mut second = TcpStream::connect(address).unwrap();
+
+        second.write_all(request).unwrap();
+        second
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+
+        assert!(second.read(&mut [0]).is_err());
+
+        drop(first);
+        second
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        assert!(read_head(&mut second).starts_with("HTTP/1.1 200 OK\r\n"));
+
+        drop(second);
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn tcp_options_applied() {
+        // Each accepted stream reports its own options
+        let options_of = |stream: &TcpStream| {
+            format!(
+                "nodelay={} keepalive={}",
+                stream.nodelay().unwrap(),
+                SockRef::from(stream).keepalive().unwrap()
+            )
+        };
+        let handler = move |request: &mut Request| {
+            let mut response = Response::empty();
+
+            response.set_body(ResponseBody::Text(options_of(&request.stream)));
+
+            response
+        };
+
+        let defaults = local(ServerOptions::default());
+
+        assert_eq!(
+            SockRef::from(&defaults.listener).reuse_address().unwrap(),
+            cfg!(not(windows))
+        );
+        drop(defaults);
+
+        let (address, drain, serving) = start(ServerOptions::default(), handler);
+
+        assert!(exchange(address, GET_CLOSE).ends_with("\r\n\r\nnodelay=false keepalive=false"));
+
+        drain.shutdown();
+        serving.join().unwrap();
+
+        let options = ServerOptions {
+            tcp_nodelay: true,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            reuse_address: false,
+            ..ServerOptions::default()
+        };
+        let server = local(options);
+
+        assert!(!SockRef::from(&server.listener).reuse_address().unwrap());
+
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let serving = spawn(move || server.serve(handler));
+
+        assert!(exchange(address, GET_CLOSE).ends_with("\r\n\r\nnodelay=true keepalive=true"));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn wire_bytes_counted_against_a_quota() {
+        use crate::quota::Quota;
+        use std::net::IpAddr;
+
+        let quota = Arc::new(Quota::<IpAddr>::new(200, Duration::from_secs(60)));
+        let logged = Arc::new(Mutex::new(vec![]));
+        let options = ServerOptions {
+            logger: Some(Logger::new({
+                let (quota, logged) = (Arc::clone(&quota), Arc::clone(&logged));
+
+                move |event| {
+                    let ip = event.peer.unwrap().ip();
+
+                    quota.record(ip, event.bytes_received + event.bytes_sent.unwrap_or(0));
+                    logged
+                        .lock()
+                        .unwrap()
+                        .push((event.bytes_received, event.bytes_sent));
+                }
+            })),
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, {
+            let quota = Arc::clone(&quota);
+
+            move |request| {
+                if let Some(response) = quota.check(&request.peer_addr.unwrap().ip()) {
+                    return response;
+                }
+
+                let mut response = Response::empty();
+
+                response
+                    .set_body(ResponseBody::Text("compressible ".repeat(1000)))
+                    .set_body_encoding(Some(BodyEncoding::Gzip), None);
+
+                response
+            }
+        });
+        let request = b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n";
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut response = vec![];
+
+        stream.write_all(request).unwrap();
+        stream.read_to_end(&mut response).unwrap();
+
+        let head = String::from_utf8_lossy(&response);
+
+        assert!(head.contains("Content-Encoding: gzip\r\n"), "{}", head);
+
+        // Logged once the response is out
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while logged.lock().unwrap().is_empty() {
+            assert!(Instant::now() < deadline);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let (received, sent) = logged.lock().unwrap()[0];
+
+        assert_eq!(received, request.len());
+        // The compressed bytes on the wire, not the body handed to the response
+        assert_eq!(sent, Some(response.len()));
+        assert!(response.len() < "compressible ".len() * 1000);
+
+        // Over its allowance now
+        let response = exchange(address, request);
+
+        assert!(
+            response.starts_with("HTTP/1.1 429 Too Many Requests\r\n"),
+            "{}",
+            response
+        );
+        assert!(response.contains("Retry-After: "), "{}", response);
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn connections_capped_with_a_503() {
+        let options = ServerOptions {
+            max_connections: Some(2),
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, |_| Response::empty());
+        let connect = || {
+            let mut stream = TcpStream::connect(address).unwrap();
+
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+
+            let head = read_head(&mut stream);
+
+            (stream, head)
+        };
+
+        // Both kept alive
+        let first = connect();
+        let second = connect();
+
+        for (_, head) in [&first, &second] {
+            assert!(head.contains("Connection: keep-alive\r\n"), "{}", head);
+        }
+
+        let (_, head) = connect();
+
+        assert!(
+            head.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{}",
+            head
+        );
+        assert!(head.contains("Retry-After: 1\r\n"), "{}", head);
+        assert!(head.contains("Connection: close\r\n"), "{}", head);
+
+        // A closed connection is no longer counted
+        drop(first);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !connect().1.starts_with("HTTP/1.1 200 OK\r\n") {
+            assert!(Instant::now() < deadline);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        drop(second);
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn secure_scheme_from_trusted_proxies() {
+        fn served(trust_proxy: bool, request: &str) -> String {
+            let options = ServerOptions {
+                trust_proxy,
+                ..ServerOptions::default()
+            };
+            let (address, drain, serving) = start(options, |req| {
+                let mut response = Response::empty();
+
+                response
+                    .add_cookie(ResponseCookie::session("sid", "1", req))
+                    .set_body(ResponseBody::Text(format!(
+                        "{} {}",
+                        req.base_url(),
+                        req.is_secure()
+                    )));
+
+                response
+            });
+            let response = exchange(address, request.as_bytes());
+
+            drain.shutdown();
+            serving.join().unwrap();
+
+            response
+        }
+
+        let proxied = "GET / HTTP/1.1\r\nHost: example.com:8443\r\n\
+                       X-Forwarded-Proto: https\r\nConnection: close\r\n\r\n";
+
+        // Behind a trusted proxy terminating TLS
+        let response = served(true, proxied);
+
+        assert!(
+            response.ends_with("\r\n\r\nhttps://example.com:8443 true"),
+            "{}",
+            response
+        );
+        assert!(
+            response.contains("Set-Cookie: sid=1;Path=/;Secure;HttpOnly\r\n"),
+            "{}",
+            response
+        );
+
+        // Anyone could send the header without trust_proxy
+        let response = served(false, proxied);
+
+        assert!(
+            response.ends_with("\r\n\r\nhttp://example.com:8443 false"),
+            "{}",
+            response
+        );
+        assert!(
+            response.contains("Set-Cookie: sid=1;Path=/;HttpOnly\r\n"),
+            "{}",
+            response
+        );
+
+        // `Forwarded` wins over `X-Forwarded-Proto`, the last proxy over the first
+        let response = served(
+            true,
+            "GET / HTTP/1.1\r\nHost: example.com\r\n\
+             Forwarded: proto=http, for=10.0.0.1;proto=https\r\n\
+             X-Forwarded-Proto: http\r\nConnection: close\r\n\r\n",
+        );
+
+        assert!(
+            response.ends_with("\r\n\r\nhttps://example.com true"),
+            "{}",
+            response
+        );
+
+        // Neither an https:// target nor an unknown proto make the connection secure
+        for request in [
+            "GET https://example.com/ HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: example.com\r\nX-Forwarded-Proto: wss\r\nConnection: close\r\n\r\n",
+        ] {
+            let response = served(true, request);
+
+            assert!(
+                response.ends_with("\r\n\r\nhttp://example.com false"),
+                "{}",
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn ephemeral_port_reported() {
+        let server = Server::bind_v4(0, None).unwrap();
+        let port = server.port();
+
+        assert_ne!(port, 0);
+        assert_eq!(server.local_addr().unwrap().port(), port);
+
+        let drain = server.drain.clone();
+        let serving = spawn(move || server.serve(hello));
+        let response = exchange((Ipv4Addr::LOCALHOST, port).into(), GET_CLOSE);
+
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn requests_received_from_a_channel() {
+        let server = local(ServerOptions {
+            channel_capacity: 1,
+            ..ServerOptions::default()
+        });
+        let address = server.local_addr().unwrap();
+        let (requests, handle) = server.into_channel();
+        let clients: Vec<TcpStream> = (0..3)
+            .map(|i| {
+                let mut stream = TcpStream::connect(address).unwrap();
+
+                write!(
+                    stream,
+                    "GET /{} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                    i
+                )
+                .unwrap();
+
+                stream
+            })
+            .collect();
+
+        // In the order they were sent, past the capacity of the channel
+        for (i, mut client) in clients.into_iter().enumerate() {
+            let mut request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+
+            assert_eq!(request.uri.path, format!("/{}", i));
+
+            request.respond(Response::empty()).unwrap();
+
+            assert!(read_head(&mut client).starts_with("HTTP/1.1 200 OK\r\n"));
+        }
+
+        // Stopping closes the channel under a pending `recv`
+        let receiving = spawn(move || requests.recv());
+
+        sleep(Duration::from_millis(100));
+        handle.stop();
+
+        assert!(receiving.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn compression_stats_debug_header() {
+        fn compressed(debug_headers: bool) -> String {
+            let options = ServerOptions {
+                debug_headers,
+                ..ServerOptions::default()
+            };
+            let (address, drain, serving) = start(options, |_| {
+                let mut response = Response::empty();
+
+                response
+                    .set_body(ResponseBody::Text("compressible ".repeat(500)))
+                    .set_body_encoding(Some(BodyEncoding::Gzip), None);
+
+                response
+            });
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut response = vec![];
+
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            stream.read_to_end(&mut response).unwrap();
+            drain.shutdown();
+            serving.join().unwrap();
+
+            String::from_utf8_lossy(&response).into_owned()
+        }
+
+        let response = compressed(true);
+        let stats = response
+            .lines()
+            .find_map(|line| line.strip_prefix("X-Compression: "))
+            .unwrap_or_else(|| panic!("{}", response));
+        let mut fields = stats.split(';');
+
+        assert_eq!(fields.next(), Some("gzip"), "{}", stats);
+
+        let mut field = |name: &str| -> f64 {
+            let (key, value) = fields.next().unwrap().split_once('=').unwrap();
+
+            assert_eq!(key, name, "{}", stats);
+
+            value.parse().unwrap()
+        };
+
+        field("level");
+
+        let ratio = field("ratio");
+
+        assert!(ratio > 0.0 && ratio < 1.0, "{}", stats);
+        assert!(field("ms") >= 0.0, "{}", stats);
+
+        // Only with `debug_headers`
+        let response = compressed(false);
+
+        assert!(
+            response.contains("Content-Encoding: gzip\r\n"),
+            "{}",
+            response
+        );
+        assert!(!response.contains("X-Compression:"), "{}", response);
+    }
+
+    #[test]
+    fn hosts_canonicalized() {
+        fn served(redirect: bool, host: &str) -> String {
+            let options = ServerOptions {
+                host_canonicalization: Some(
+                    HostCanonicalization::new()
+                        .alias("www.example.com", "example.com")
+                        .strip_trailing_dot(true)
+                        .redirect(redirect),
+                ),
+                ..ServerOptions::default()
+            };
+            let (address, drain, serving) = start(options, |request| {
+                let mut response = Response::empty();
+                let host = match request.get_header("Host") {
+                    Some(Header::Host(host)) => host.clone(),
+                    _ => String::new(),
+                };
+
+                response.set_body(ResponseBody::Text(format!("{} {}", host, request.uri.host)));
+
+                response
+            });
+            let response = exchange(
+                address,
+                format!(
+                    "GET /a?b=1 HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                    host
+                )
+                .as_bytes(),
+            );
+
+            drain.shutdown();
+            serving.join().unwrap();
+
+            response
+        }
+
+        for host in ["www.example.com", "example.com."] {
+            let response = served(true, host);
+
+            assert!(
+                response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"),
+                "{}",
+                response
+            );
+            assert!(
+                response.contains("Location: http://example.com/a?b=1\r\n"),
+                "{}",
+                response
+            );
+        }
+
+        // The canonical host itself isn't redirected again
+        let response = served(true, "example.com");
+
+        assert!(
+            response.ends_with("\r\n\r\nexample.com example.com"),
+            "{}",
+            response
+        );
+
+        // Only rewritten for the handler without `redirect`
+        let response = served(false, "www.example.com.");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(
+            response.ends_with("\r\n\r\nexample.com example.com"),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn slow_requests_reported() {
+        let slow = Arc::new(Mutex::new(vec![]));
+        let head_durations = Arc::new(Mutex::new(vec![]));
+        let logger = Logger::new({
+            let head_durations = Arc::clone(&head_durations);
+
+            move |event| head_durations.lock().unwrap().push(event.head_duration)
+        })
+        .with_slow_requests({
+            let slow = Arc::clone(&slow);
+
+            move |request| slow.lock().unwrap().push(request.clone())
+        });
+        let options = ServerOptions {
+            logger: Some(logger),
+            slow_request_threshold: Some(Duration::from_millis(100)),
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, |request| {
+            let mut response = Response::empty();
+
+            response.set_body(ResponseBody::Text(
+                request.timings().head().as_millis().to_string(),
+            ));
+
+            response
+        });
+        // Fast enough
+        assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+
+        // A client trickling its headers
+        for line in [
+            "GET /slow HTTP/1.1\r\n",
+            "Host: localhost\r\n",
+            "Connection: close\r\n",
+        ] {
+            stream.write_all(line.as_bytes()).unwrap();
+            sleep(Duration::from_millis(300));
+        }
+
+        stream.write_all(b"\r\n").unwrap();
+
+        let mut response = String::new();
+
+        stream.read_to_string(&mut response).unwrap();
+
+        let (_, head_ms) = response.split_once("\r\n\r\n").unwrap();
+        let head_ms: u64 = head_ms.parse().unwrap();
+
+        // Read a little after they were sent, at least the last two delays
+        assert!(head_ms >= 600, "{}", response);
+
+        drain.shutdown();
+        serving.join().unwrap();
+
+        let slow = slow.lock().unwrap();
+
+        assert_eq!(slow.len(), 1);
+        assert_eq!(slow[0].uri, "http://localhost/slow");
+        assert!(slow[0].timings.head() >= Duration::from_millis(600));
+        assert!(slow[0].timings.receive() >= slow[0].timings.head());
+        assert!(slow[0].timings.wait() < Duration::from_millis(300));
+        assert!(
+            slow[0]
+                .to_string()
+                .starts_with("Slow request from 127.0.0.1:"),
+            "{}",
+            slow[0]
+        );
+
+        let head_durations = head_durations.lock().unwrap();
+
+        assert_eq!(head_durations.len(), 2);
+        assert!(head_durations[1].unwrap() >= Duration::from_millis(600));
+    }
+
+    #[test]
+    fn http09_request_lines() {
+        let (address, drain, serving) = start(ServerOptions::default(), hello);
+
+        assert!(exchange(address, b"GET /path\r\n")
+            .starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+        assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 200 OK\r\n"));
+
+        drain.shutdown();
+        serving.join().unwrap();
+
+        let options = ServerOptions {
+            http09: true,
+            ..ServerOptions::default()
+        };
+        let (address, drain, serving) = start(options, hello);
+
+        // The body alone, no status line nor headers
+        assert_eq!(exchange(address, b"GET /path\r\n"), "hello");
+        assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 200 OK\r\n"));
+
+        drain.shutdown();
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn requests_are_counted_by_version() {
+        let server = local(ServerOptions::default());
+        let address = server.local_addr().unwrap();
+        let drain = server.drain.clone();
+        let versions = Arc::clone(&server.versions);
+        let serving = spawn(move || server.serve(hello));
+
+        // Refused, but counted
+        assert!(exchange(address, b"GET /path\r\n").starts_with("HTTP/1.1 505"));
+        assert!(exchange(address, b"GET / HTTP/1.0\r\n\r\n").starts_with("HTTP/1.0 200"));
+
+        for _ in 0..2 {
+            assert!(exchange(address, GET_CLOSE).starts_with("HTTP/1.1 200"));
+        }
+
+        drain.shutdown();
+        serving.join().unwrap();
+
+        assert_eq!(versions.get(&Version::Http09), 1);
+        assert_eq!(versions.get(&Version::Http10), 1);
+        assert_eq!(versions.get(&Version::Http11), 2);
+        assert_eq!(versions.get(&Version::Http20), 0);
+    }
+
+    #[test]
+    fn info_reports_address_limits_and_workers() {
+        let server = local(ServerOptions {
+            workers: Some(3),
+            ..ServerOptions::hardened()
+        });
+        let address = server.local_addr().unwrap().to_string();
+        let text = server.info().render_text();
+
+        assert!(text.contains(&format!("address: {}\n", address)));
+        assert!(text.contains("workers: 3\n"));
+        assert!(text.contains("max body size: 1048576 bytes\n"));
+        assert!(text.contains("max head size: 8192 bytes\n"));
+        assert!(text.contains("read timeout: 10s\n"));
+        assert!(text.contains("keep-alive timeout: 15s\n"));
+        assert!(text.contains("requests: HTTP/0.9 0, HTTP/1.0 0, HTTP/1.1 0, other 0\n"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn info_renders_json() {
+        let server = local(ServerOptions {
+            workers: Some(3),
+            ..ServerOptions::hardened()
+        });
+        let json = Value::parse(&server.info().render_json()).unwrap();
+        let address = server.local_addr().unwrap().to_string();
+
+        assert_eq!(json.get("address").and_then(Value::as_str), Some(&*address));
+        assert_eq!(json.get("workers").and_then(Value::as_i64), Some(3));
+        assert_eq!(
+            json.get("max_body_size").and_then(Value::as_i64),
+            Some(1024 * 1024)
+        );
+        assert_eq!(
+            json.get("read_timeout_ms").and_then(Value::as_i64),
+            Some(10_000)
+        );
+        assert_eq!(
+            json.get("requests_by_version")
+                .and_then(|versions| versions.get("HTTP/1.1"))
+                .and_then(Value::as_i64),
+            Some(0)
+        );
+        assert!(json
+            .get("allowed_methods")
+            .and_then(Value::as_array)
+            .is_some());
+    }
 }